@@ -60,6 +60,67 @@ pub struct MotionPins {
     pub y_dir_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
     pub z_dir_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
     pub e_dir_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
+
+    /// Second extruder drive. It has no endstop
+    #[cfg(feature = "with-e1")]
+    pub e1_enable_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
+    #[cfg(feature = "with-e1")]
+    pub e1_step_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
+    #[cfg(feature = "with-e1")]
+    pub e1_dir_pin: crate::board::mocked_peripherals::MockedOutputPin<'static, u8>,
+}
+
+#[cfg(feature = "with-motion")]
+impl MotionPins {
+    /// Untraced pins, all low
+    pub const fn new() -> Self {
+        use crate::board::mocked_peripherals::{MockedInputPin, MockedOutputPin};
+        Self {
+            x_enable_pin: MockedOutputPin::new(),
+            y_enable_pin: MockedOutputPin::new(),
+            z_enable_pin: MockedOutputPin::new(),
+            e_enable_pin: MockedOutputPin::new(),
+            x_endstop_pin: MockedInputPin::new(),
+            y_endstop_pin: MockedInputPin::new(),
+            z_endstop_pin: MockedInputPin::new(),
+            e_endstop_pin: MockedInputPin::new(),
+            x_step_pin: MockedOutputPin::new(),
+            y_step_pin: MockedOutputPin::new(),
+            z_step_pin: MockedOutputPin::new(),
+            e_step_pin: MockedOutputPin::new(),
+            x_dir_pin: MockedOutputPin::new(),
+            y_dir_pin: MockedOutputPin::new(),
+            z_dir_pin: MockedOutputPin::new(),
+            e_dir_pin: MockedOutputPin::new(),
+            #[cfg(feature = "with-e1")]
+            e1_enable_pin: MockedOutputPin::new(),
+            #[cfg(feature = "with-e1")]
+            e1_step_pin: MockedOutputPin::new(),
+            #[cfg(feature = "with-e1")]
+            e1_dir_pin: MockedOutputPin::new(),
+        }
+    }
+}
+
+#[cfg(feature = "with-motion")]
+impl Default for MotionPins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The spindle shares the laser output
+#[cfg(feature = "with-spindle")]
+pub type PwmSpindle = PwmLaser;
+
+#[cfg(feature = "with-spindle")]
+pub type SpindleDirPin = crate::board::mocked_peripherals::MockedOutputPin<'static, u8>;
+
+#[cfg(feature = "with-spindle")]
+pub struct SpindlePeripherals {
+    pub power_pwm: printhor_hwa_common::ControllerRef<PwmSpindle>,
+    pub power_channel: PwmChannel,
+    pub dir_pin: SpindleDirPin,
 }
 
 #[cfg(feature = "with-motion")]
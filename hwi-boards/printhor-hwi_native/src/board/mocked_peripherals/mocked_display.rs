@@ -72,9 +72,13 @@ impl<UI: DisplayScreenUI> SimulatorDisplayScreen<UI>
         #[cfg(not(feature = "with-lvgl"))]
         self.simulator_window.update(&self.raw_display);
 
-        self.simulator_window.events().for_each(|_e| {
-            //println!("Event! {:?}", _e)
-        });
+        // A click on the screen stands for the pause/resume control
+        let clicked = self.simulator_window.events()
+            .filter(|e| matches!(e, SimulatorEvent::MouseButtonUp { .. }))
+            .count() > 0;
+        if clicked {
+            self.main_ui.pause_resume().await;
+        }
     }
 
     pub async fn retain(&mut self)  {
@@ -1,32 +1,50 @@
 use std::marker::PhantomData;
 use embassy_time::{Duration, Timer};
+use crate::board::mocked_peripherals::pin_trace::{record_transition, TracedSignal};
 #[cfg(feature = "with-hotbed")]
 use crate::hwi::native::traits::{AdcPin, TemperatureAdcCompat};
 
 pub struct MockedOutputPin<'a, T> {
-    p: PhantomData<&'a T>
+    p: PhantomData<&'a T>,
+    state: bool,
+    signal: Option<TracedSignal>,
 }
 
 #[allow(unused)]
 impl<'a, T> MockedOutputPin<'_, T> {
     pub(crate) const fn new() -> Self {
-        Self { p: PhantomData }
+        Self { p: PhantomData, state: false, signal: None }
     }
 
-    pub fn set_high(&mut self) {
+    /// A pin whose transitions are recorded by the pin trace recorder
+    pub(crate) const fn traced(signal: TracedSignal) -> Self {
+        Self { p: PhantomData, state: false, signal: Some(signal) }
+    }
 
+    pub fn set_high(&mut self) {
+        self.set_state(true);
     }
 
     pub fn set_low(&mut self) {
-
+        self.set_state(false);
     }
 
     pub fn is_set_high(&mut self) -> bool {
-        false
+        self.state
     }
 
     pub fn is_set_low(&mut self) -> bool {
-        false
+        !self.state
+    }
+
+    #[inline]
+    fn set_state(&mut self, state: bool) {
+        if self.state != state {
+            self.state = state;
+            if let Some(signal) = self.signal {
+                record_transition(signal, state);
+            }
+        }
     }
 }
 
@@ -69,10 +87,12 @@ impl<'a, T> TemperatureAdcCompat for MockedInputPin<'a, T> {}
 impl<T> embedded_hal::digital::v2::OutputPin for MockedOutputPin<'_, T> {
     type Error = core::convert::Infallible;
     fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_state(false);
         Ok(())
     }
 
     fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_state(true);
         Ok(())
     }
 }
\ No newline at end of file
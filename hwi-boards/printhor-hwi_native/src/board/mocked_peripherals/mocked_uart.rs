@@ -12,9 +12,29 @@ use futures::Future;
 
 pub(crate) static SERIAL_PIPE: Pipe<CriticalSectionRawMutex, 256> = Pipe::<CriticalSectionRawMutex, 256>::new();
 
+/// When set, the G-code file it points to is fed to the port instead of the standard input
+pub const GCODE_FILE_ENV_VAR: &str = "PRINTHOR_GCODE_FILE";
+
 #[embassy_executor::task(pool_size=1)]
 pub async fn processor() {
 
+    // Fed from the executor, so a simulation run does not depend on when the host delivers the input
+    if let Ok(path) = std::env::var(GCODE_FILE_ENV_VAR) {
+        match std::fs::read(&path) {
+            Ok(content) => {
+                log::info!("Feeding G-code from {}", path);
+                let mut sent = 0;
+                while sent < content.len() {
+                    sent += SERIAL_PIPE.write(&content[sent..]).await;
+                }
+            }
+            Err(e) => {
+                log::error!("Unable to read G-code file {}: {}", path, e);
+            }
+        }
+        return;
+    }
+
     let port = NativeSerialPort::new("/dev/stdin", None).unwrap();
     let port = async_io::Async::new(port).unwrap();
     let mut port = embedded_io::adapters::FromFutures::new(port);
@@ -1,6 +1,7 @@
 #[cfg(feature = "needs-adc")]
 mod mocked_adc;
 mod mocked_pin;
+pub(crate) mod pin_trace;
 #[cfg(feature = "with-hotbed")]
 mod mocked_pwm;
 #[cfg(feature = "with-spi")]
@@ -18,6 +19,7 @@ mod mocked_display;
 mod mocked_wdt;
 
 pub use mocked_pin::*;
+pub use pin_trace::{init_pin_trace, flush_pin_trace, TracedSignal};
 pub use mocked_wdt::*;
 
 #[cfg(feature = "with-uart-port-1")]
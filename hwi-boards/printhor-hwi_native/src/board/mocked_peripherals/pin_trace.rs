@@ -0,0 +1,189 @@
+//! Step-level trace recorder for the mocked output pins.
+//!
+//! When the `PRINTHOR_PIN_TRACE` environment variable points to a file, every state transition of the
+//! traced pins (step, dir and enable of each axis) is timestamped (embassy time, in microseconds) and written to it.
+//! A path ending in `.csv` produces `time_us,signal,value` rows. Anything else produces a VCD file that can be
+//! opened with GTKWave.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Mutex;
+
+pub const PIN_TRACE_ENV_VAR: &str = "PRINTHOR_PIN_TRACE";
+
+/// Flush to disk every this many transitions
+const FLUSH_EVERY: u32 = 1024;
+
+/// The signals that can be traced
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TracedSignal {
+    XStep, YStep, ZStep, EStep,
+    XDir, YDir, ZDir, EDir,
+    XEnable, YEnable, ZEnable, EEnable,
+    E1Step, E1Dir, E1Enable,
+}
+
+impl TracedSignal {
+    pub const ALL: [TracedSignal; 15] = [
+        TracedSignal::XStep, TracedSignal::YStep, TracedSignal::ZStep, TracedSignal::EStep,
+        TracedSignal::XDir, TracedSignal::YDir, TracedSignal::ZDir, TracedSignal::EDir,
+        TracedSignal::XEnable, TracedSignal::YEnable, TracedSignal::ZEnable, TracedSignal::EEnable,
+        TracedSignal::E1Step, TracedSignal::E1Dir, TracedSignal::E1Enable,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TracedSignal::XStep => "x_step",
+            TracedSignal::YStep => "y_step",
+            TracedSignal::ZStep => "z_step",
+            TracedSignal::EStep => "e_step",
+            TracedSignal::XDir => "x_dir",
+            TracedSignal::YDir => "y_dir",
+            TracedSignal::ZDir => "z_dir",
+            TracedSignal::EDir => "e_dir",
+            TracedSignal::XEnable => "x_enable",
+            TracedSignal::YEnable => "y_enable",
+            TracedSignal::ZEnable => "z_enable",
+            TracedSignal::EEnable => "e_enable",
+            TracedSignal::E1Step => "e1_step",
+            TracedSignal::E1Dir => "e1_dir",
+            TracedSignal::E1Enable => "e1_enable",
+        }
+    }
+
+    /// Single char VCD identifier
+    const fn vcd_id(&self) -> char {
+        (b'!' + *self as u8) as char
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TraceFormat {
+    Vcd,
+    Csv,
+}
+
+pub struct PinTraceRecorder<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    last_timestamp: Option<u64>,
+    pending: u32,
+}
+
+impl<W: Write> PinTraceRecorder<W> {
+    pub fn new(mut writer: W, format: TraceFormat) -> std::io::Result<Self> {
+        match format {
+            TraceFormat::Vcd => {
+                writeln!(writer, "$timescale 1us $end")?;
+                writeln!(writer, "$scope module printhor $end")?;
+                for signal in TracedSignal::ALL {
+                    writeln!(writer, "$var wire 1 {} {} $end", signal.vcd_id(), signal.name())?;
+                }
+                writeln!(writer, "$upscope $end")?;
+                writeln!(writer, "$enddefinitions $end")?;
+                writeln!(writer, "$dumpvars")?;
+                for signal in TracedSignal::ALL {
+                    writeln!(writer, "0{}", signal.vcd_id())?;
+                }
+                writeln!(writer, "$end")?;
+            }
+            TraceFormat::Csv => {
+                writeln!(writer, "time_us,signal,value")?;
+            }
+        }
+        Ok(Self {
+            writer,
+            format,
+            last_timestamp: None,
+            pending: 0,
+        })
+    }
+
+    pub fn record(&mut self, timestamp_us: u64, signal: TracedSignal, state: bool) -> std::io::Result<()> {
+        let value = if state { 1 } else { 0 };
+        match self.format {
+            TraceFormat::Vcd => {
+                if self.last_timestamp != Some(timestamp_us) {
+                    writeln!(self.writer, "#{}", timestamp_us)?;
+                    self.last_timestamp = Some(timestamp_us);
+                }
+                writeln!(self.writer, "{}{}", value, signal.vcd_id())?;
+            }
+            TraceFormat::Csv => {
+                writeln!(self.writer, "{},{},{}", timestamp_us, signal.name(), value)?;
+            }
+        }
+        self.pending += 1;
+        if self.pending >= FLUSH_EVERY {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.pending = 0;
+        self.writer.flush()
+    }
+
+    #[allow(unused)]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+static RECORDER: Mutex<Option<PinTraceRecorder<BufWriter<File>>>> = Mutex::new(None);
+
+/// Opens the trace file given by [PIN_TRACE_ENV_VAR], if any
+pub fn init_pin_trace() {
+    if let Ok(path) = std::env::var(PIN_TRACE_ENV_VAR) {
+        let format = match path.to_lowercase().ends_with(".csv") {
+            true => TraceFormat::Csv,
+            false => TraceFormat::Vcd,
+        };
+        match File::create(&path).and_then(|f| PinTraceRecorder::new(BufWriter::new(f), format)) {
+            Ok(recorder) => {
+                log::info!("Pin trace recording to {} ({:?})", path, format);
+                RECORDER.lock().unwrap().replace(recorder);
+            }
+            Err(e) => {
+                log::error!("Unable to create pin trace {}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[inline]
+pub fn record_transition(signal: TracedSignal, state: bool) {
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        let _ = recorder.record(embassy_time::Instant::now().as_micros(), signal, state);
+    }
+}
+
+pub fn flush_pin_trace() {
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        let _ = recorder.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vcd_groups_transitions_by_timestamp() {
+        let mut recorder = PinTraceRecorder::new(Vec::new(), TraceFormat::Vcd).unwrap();
+        recorder.record(10, TracedSignal::XDir, true).unwrap();
+        recorder.record(10, TracedSignal::XStep, true).unwrap();
+        recorder.record(12, TracedSignal::XStep, false).unwrap();
+        let out = String::from_utf8(recorder.into_inner()).unwrap();
+        let body = out.split("$end\n").last().unwrap();
+        assert_eq!(body, "#10\n1%\n1!\n#12\n0!\n");
+    }
+
+    #[test]
+    fn csv_rows() {
+        let mut recorder = PinTraceRecorder::new(Vec::new(), TraceFormat::Csv).unwrap();
+        recorder.record(5, TracedSignal::ZEnable, false).unwrap();
+        let out = String::from_utf8(recorder.into_inner()).unwrap();
+        assert_eq!(out, "time_us,signal,value\n5,z_enable,0\n");
+    }
+}
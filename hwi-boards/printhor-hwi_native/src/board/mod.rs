@@ -3,6 +3,10 @@ pub mod device;
 pub mod io;
 
 mod mocked_peripherals;
+#[cfg(feature = "with-virtual-clock")]
+pub(crate) mod virtual_clock;
+
+pub use mocked_peripherals::flush_pin_trace;
 
 use embassy_executor::Spawner;
 
@@ -22,10 +26,12 @@ use embassy_stm32::timer::{CountingMode,simple_pwm::SimplePwm};
 use device::{MotionDevice, MotionPins};
 #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
 use embassy_stm32::adc::SampleTime;
-#[cfg(feature = "with-motion")]
+#[cfg(any(feature = "with-motion", feature = "with-spindle"))]
 use crate::board::mocked_peripherals::MockedOutputPin;
 #[cfg(feature = "with-motion")]
 use crate::board::mocked_peripherals::MockedInputPin;
+#[cfg(feature = "with-motion")]
+use crate::board::mocked_peripherals::TracedSignal;
 
 pub const MACHINE_TYPE: &str = "Simulator/debugger";
 pub const MACHINE_BOARD: &str = "PC";
@@ -78,6 +84,8 @@ pub struct PwmDevices {
     pub fan1: device::Fan1Peripherals,
     #[cfg(feature = "with-laser")]
     pub laser: device::LaserPeripherals,
+    #[cfg(feature = "with-spindle")]
+    pub spindle: device::SpindlePeripherals,
 }
 
 pub struct MotionDevices {
@@ -143,27 +151,36 @@ pub async fn setup(_spawner: Spawner, _p: HWIPeripherals) -> printhor_hwa_common
         device::SDCardBlockDevice::new("data/sdcard.img", false).unwrap()
     };
 
+    #[cfg(feature = "with-motion")]
+    mocked_peripherals::init_pin_trace();
+
     #[cfg(feature = "with-motion")]
     let motion_devices = MotionDevice {
         #[cfg(feature = "with-trinamic")]
         trinamic_uart,
         motion_pins: MotionPins {
-            x_enable_pin: MockedOutputPin::new(),
-            y_enable_pin: MockedOutputPin::new(),
-            z_enable_pin: MockedOutputPin::new(),
-            e_enable_pin: MockedOutputPin::new(),
+            x_enable_pin: MockedOutputPin::traced(TracedSignal::XEnable),
+            y_enable_pin: MockedOutputPin::traced(TracedSignal::YEnable),
+            z_enable_pin: MockedOutputPin::traced(TracedSignal::ZEnable),
+            e_enable_pin: MockedOutputPin::traced(TracedSignal::EEnable),
             x_endstop_pin: MockedInputPin::new(),
             y_endstop_pin: MockedInputPin::new(),
             z_endstop_pin: MockedInputPin::new(),
             e_endstop_pin: MockedInputPin::new(),
-            x_step_pin: MockedOutputPin::new(),
-            y_step_pin: MockedOutputPin::new(),
-            z_step_pin: MockedOutputPin::new(),
-            e_step_pin: MockedOutputPin::new(),
-            x_dir_pin: MockedOutputPin::new(),
-            y_dir_pin: MockedOutputPin::new(),
-            z_dir_pin: MockedOutputPin::new(),
-            e_dir_pin: MockedOutputPin::new(),
+            x_step_pin: MockedOutputPin::traced(TracedSignal::XStep),
+            y_step_pin: MockedOutputPin::traced(TracedSignal::YStep),
+            z_step_pin: MockedOutputPin::traced(TracedSignal::ZStep),
+            e_step_pin: MockedOutputPin::traced(TracedSignal::EStep),
+            x_dir_pin: MockedOutputPin::traced(TracedSignal::XDir),
+            y_dir_pin: MockedOutputPin::traced(TracedSignal::YDir),
+            z_dir_pin: MockedOutputPin::traced(TracedSignal::ZDir),
+            e_dir_pin: MockedOutputPin::traced(TracedSignal::EDir),
+            #[cfg(feature = "with-e1")]
+            e1_enable_pin: MockedOutputPin::traced(TracedSignal::E1Enable),
+            #[cfg(feature = "with-e1")]
+            e1_step_pin: MockedOutputPin::traced(TracedSignal::E1Step),
+            #[cfg(feature = "with-e1")]
+            e1_dir_pin: MockedOutputPin::traced(TracedSignal::E1Dir),
         }
     };
     #[cfg(feature = "with-motion")]
@@ -219,7 +236,7 @@ pub async fn setup(_spawner: Spawner, _p: HWIPeripherals) -> printhor_hwa_common
         ))
     };
 
-    #[cfg(any(feature = "with-laser"))]
+    #[cfg(any(feature = "with-laser", feature = "with-spindle"))]
     let (pwm_laser, pwm_laser_channel) = {
         static PWM_LASER_INST: TrackedStaticCell<ControllerMutex<device::PwmLaser>> = TrackedStaticCell::new();
         (
@@ -333,6 +350,12 @@ pub async fn setup(_spawner: Spawner, _p: HWIPeripherals) -> printhor_hwa_common
                 power_pwm: pwm_laser.clone(),
                 power_channel: pwm_laser_channel,
             },
+            #[cfg(feature = "with-spindle")]
+            spindle: SpindlePeripherals {
+                power_pwm: pwm_laser.clone(),
+                power_channel: pwm_laser_channel,
+                dir_pin: MockedOutputPin::new(),
+            },
         }
     }
 
@@ -342,4 +365,10 @@ pub async fn setup(_spawner: Spawner, _p: HWIPeripherals) -> printhor_hwa_common
 pub mod consts {
     /// 50ms to enqueue ~28 motion gcodes at 115200 bps
     pub(crate) const LINGER_MS: u64 = 10000;
+    /// Axes whose motor is wired in reverse (forward is dir pin low). Axis masks: X = 1, Y = 2, Z = 4, E = 8
+    pub const INVERT_DIR_AXES: u8 = 0;
+    /// Axes whose driver is enabled with the pin high
+    pub const INVERT_ENABLE_AXES: u8 = 0;
+    /// Axes whose endstop reads low when triggered
+    pub const INVERT_ENDSTOP_AXES: u8 = 0;
 }
\ No newline at end of file
@@ -0,0 +1,224 @@
+//! Virtual clock `embassy_time` driver and executor for simulation runs.
+//!
+//! Time does not follow the wall clock. Every executor is polled from a single thread (see [Executor]), and time only
+//! advances when none of them has a task ready to run: the clock then jumps straight to the earliest pending alarm and
+//! fires it. So timers, tickers and timeouts expire in the same order and at the same virtual instants every run, as
+//! fast as the host allows.
+//! Nothing may busy-wait on [embassy_time::Instant::now]: time is frozen while a task runs, so waits go through an alarm.
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::{Condvar, Mutex};
+use embassy_executor::{raw, Spawner};
+use embassy_time::driver::{AlarmHandle, Driver};
+
+const ALARM_COUNT: usize = 4;
+
+/// Executors the simulation can drive. The first one is the main executor
+const EXECUTOR_COUNT: usize = 4;
+
+#[derive(Clone, Copy)]
+struct AlarmState {
+    timestamp: u64,
+    /// Callback and its context (as address, so the state is Send)
+    callback: Option<(fn(*mut ()), usize)>,
+}
+
+impl AlarmState {
+    const fn new() -> Self {
+        Self {
+            timestamp: u64::MAX,
+            callback: None,
+        }
+    }
+}
+
+struct ClockState {
+    now: u64,
+    alarms: [AlarmState; ALARM_COUNT],
+    allocated: u8,
+    /// Bit set for each executor with tasks ready to run
+    pending: u8,
+}
+
+struct VirtualClockDriver {
+    state: Mutex<ClockState>,
+    /// Signaled when an executor is pended from another thread (I/O) while the simulation waits for it
+    signal: Condvar,
+}
+
+embassy_time::time_driver_impl!(static DRIVER: VirtualClockDriver = VirtualClockDriver {
+    state: Mutex::new(ClockState {
+        now: 0,
+        alarms: [AlarmState::new(); ALARM_COUNT],
+        allocated: 0,
+        pending: 0,
+    }),
+    signal: Condvar::new(),
+});
+
+impl VirtualClockDriver {
+    fn pend(&self, executor_id: usize) {
+        let mut st = self.state.lock().unwrap();
+        st.pending |= 1 << executor_id;
+        self.signal.notify_all();
+    }
+
+    /// Takes the highest priority executor with tasks ready to run
+    fn take_pending(&self) -> Option<usize> {
+        let mut st = self.state.lock().unwrap();
+        match st.pending {
+            0 => None,
+            pending => {
+                let id = 7 - pending.leading_zeros() as usize;
+                st.pending &= !(1 << id);
+                Some(id)
+            }
+        }
+    }
+
+    /// Called when every executor is idle: jumps to the earliest alarm and fires it.
+    /// Without alarms, only an external event (I/O) can make progress, so it waits for it
+    fn advance(&self) {
+        let mut st = self.state.lock().unwrap();
+        loop {
+            if st.pending != 0 {
+                return;
+            }
+            let allocated = st.allocated as usize;
+            let next = st.alarms[..allocated].iter()
+                .filter(|a| a.timestamp != u64::MAX)
+                .map(|a| a.timestamp)
+                .min();
+            match next {
+                Some(next) => {
+                    st.now = st.now.max(next);
+                    let now = st.now;
+                    let mut expired: [Option<(fn(*mut ()), usize)>; ALARM_COUNT] = [None; ALARM_COUNT];
+                    for (idx, alarm) in st.alarms[..allocated].iter_mut().enumerate() {
+                        if alarm.timestamp <= now {
+                            alarm.timestamp = u64::MAX;
+                            expired[idx] = alarm.callback;
+                        }
+                    }
+                    // Callbacks pend executors, so they are invoked unlocked
+                    drop(st);
+                    for (callback, ctx) in expired.into_iter().flatten() {
+                        callback(ctx as *mut ());
+                    }
+                    return;
+                }
+                None => {
+                    st = self.signal.wait(st).unwrap();
+                }
+            }
+        }
+    }
+}
+
+impl Driver for VirtualClockDriver {
+    fn now(&self) -> u64 {
+        self.state.lock().unwrap().now
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        let mut st = self.state.lock().unwrap();
+        match (st.allocated as usize) < ALARM_COUNT {
+            true => {
+                let id = st.allocated;
+                st.allocated += 1;
+                Some(AlarmHandle::new(id))
+            }
+            false => None,
+        }
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        let mut st = self.state.lock().unwrap();
+        st.alarms[alarm.id() as usize].callback = Some((callback, ctx as usize));
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) -> bool {
+        let mut st = self.state.lock().unwrap();
+        if timestamp <= st.now {
+            st.alarms[alarm.id() as usize].timestamp = u64::MAX;
+            false
+        }
+        else {
+            st.alarms[alarm.id() as usize].timestamp = timestamp;
+            true
+        }
+    }
+}
+
+/// The context of each raw executor is its id
+#[export_name = "__pender"]
+fn __pender(context: *mut ()) {
+    DRIVER.pend(context as usize);
+}
+
+/// The executors driven by the simulation thread. Their index is their priority
+struct Executors {
+    executors: [Option<&'static raw::Executor>; EXECUTOR_COUNT],
+    count: usize,
+}
+
+impl Executors {
+    const fn new() -> Self {
+        Self {
+            executors: [None; EXECUTOR_COUNT],
+            count: 0,
+        }
+    }
+
+    fn register(&mut self) -> &'static raw::Executor {
+        if self.count >= EXECUTOR_COUNT {
+            panic!("Too many simulated executors");
+        }
+        let executor: &'static raw::Executor = Box::leak(Box::new(raw::Executor::new(self.count as *mut ())));
+        self.executors[self.count] = Some(executor);
+        self.count += 1;
+        executor
+    }
+}
+
+thread_local! {
+    static EXECUTORS: RefCell<Executors> = const { RefCell::new(Executors::new()) };
+}
+
+/// Thread executor of a simulation run. Higher priority executors (see [spawner]) are polled from the same thread, so
+/// tasks run one at a time in a reproducible order
+pub struct Executor {
+    inner: &'static raw::Executor,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            inner: EXECUTORS.with(|e| e.borrow_mut().register()),
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn run(&'static mut self, init: impl FnOnce(Spawner)) -> ! {
+        log::info!("Virtual clock enabled");
+        init(self.inner.spawner());
+        loop {
+            match DRIVER.take_pending().and_then(|id| EXECUTORS.with(|e| e.borrow().executors[id])) {
+                Some(executor) => unsafe { executor.poll() },
+                None => DRIVER.advance(),
+            }
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Creates an executor with priority over the ones created before it. Must be called from the simulation thread
+pub fn spawner() -> Spawner {
+    EXECUTORS.with(|e| e.borrow_mut().register()).spawner()
+}
@@ -2,6 +2,12 @@
 #![feature(type_alias_impl_trait)]
 #![allow(stable_features)]
 
+#[cfg(all(feature = "with-wallclock", feature = "with-virtual-clock"))]
+compile_error!("with-wallclock and with-virtual-clock both provide the embassy-time driver. Enable only one of them (native-wallclock or native-simulation)");
+
+#[cfg(not(any(feature = "with-wallclock", feature = "with-virtual-clock")))]
+compile_error!("A time driver is required: enable with-wallclock or with-virtual-clock (native-wallclock or native-simulation)");
+
 mod board;
 
 pub use log::{trace,debug,info,warn,error};
@@ -14,6 +20,7 @@ pub use board::MotionDevices;
 pub use board::PwmDevices;
 
 pub use board::init;
+pub use board::flush_pin_trace;
 pub use board::setup;
 pub use board::heap_current_size;
 pub use board::heap_current_usage_percentage;
@@ -31,15 +38,24 @@ const USBSERIAL_BUFFER_SIZE: usize = 32;
 #[cfg(feature = "with-uart-port-1")]
 const UART_PORT1_BUFFER_SIZE: usize = 32;
 
+/// Executor of simulation runs, where every task is polled from the main thread
+#[cfg(feature = "with-virtual-clock")]
+pub use board::virtual_clock::Executor as SimulationExecutor;
+
+#[cfg(feature = "with-wallclock")]
 static EXECUTOR_HIGH: printhor_hwa_common::TrackedStaticCell<embassy_executor::Executor> = printhor_hwa_common::TrackedStaticCell::new();
 
+#[cfg(feature = "with-wallclock")]
 struct TokenHolder<S> {
     token: embassy_executor::SpawnToken<S>
 }
 
+#[cfg(feature = "with-wallclock")]
 unsafe impl<S> Sync for TokenHolder<S> {}
+#[cfg(feature = "with-wallclock")]
 unsafe impl<S> Send for TokenHolder<S> {}
 
+#[cfg(feature = "with-wallclock")]
 #[inline]
 pub fn launch_high_priotity<S: 'static>(token: embassy_executor::SpawnToken<S>) -> Result<(),()> {
     let r = Box::new(TokenHolder {token});
@@ -54,6 +70,13 @@ pub fn launch_high_priotity<S: 'static>(token: embassy_executor::SpawnToken<S>)
     Ok(())
 }
 
+/// Polled from the simulation thread, ahead of the main executor
+#[cfg(feature = "with-virtual-clock")]
+#[inline]
+pub fn launch_high_priotity<S: 'static>(token: embassy_executor::SpawnToken<S>) -> Result<(),()> {
+    board::virtual_clock::spawner().spawn(token).map_err(|_| ())
+}
+
 #[inline]
 pub fn init_logger() {
     env_logger::init();
@@ -61,6 +84,7 @@ pub fn init_logger() {
 
 #[inline]
 pub fn sys_reset() {
+    board::flush_pin_trace();
     std::process::exit(0);
 }
 
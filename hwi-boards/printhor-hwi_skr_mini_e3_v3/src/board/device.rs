@@ -74,9 +74,16 @@ pub type PwmImpl<TimPeri> = embassy_stm32::timer::simple_pwm::SimplePwm<'static,
 
 pub type PwmServo = SimplePwm<'static, embassy_stm32::peripherals::TIM2>;
 
-#[cfg(feature = "with-laser")]
+#[cfg(any(feature = "with-laser", feature = "with-spindle"))]
 pub type PwmLaser = SimplePwm<'static, embassy_stm32::peripherals::TIM16>;
 
+/// The spindle shares the laser output
+#[cfg(feature = "with-spindle")]
+pub type PwmSpindle = PwmLaser;
+
+#[cfg(feature = "with-spindle")]
+pub type SpindleDirPin = Output<'static, embassy_stm32::peripherals::PA15>;
+
 pub type PwmFan0Fan1HotendHotbed = SimplePwm<'static, embassy_stm32::peripherals::TIM3>;
 
 pub type PwmFan0 = PwmFan0Fan1HotendHotbed;
@@ -145,6 +152,13 @@ pub struct LaserPeripherals {
     pub power_channel: PwmChannel,
 }
 
+#[cfg(feature = "with-spindle")]
+pub struct SpindlePeripherals {
+    pub power_pwm: printhor_hwa_common::ControllerRef<PwmSpindle>,
+    pub power_channel: PwmChannel,
+    pub dir_pin: SpindleDirPin,
+}
+
 #[cfg(feature = "with-motion")]
 pub struct MotionPins {
     pub x_enable_pin: Output<'static, embassy_stm32::peripherals::PB14>,
@@ -94,6 +94,8 @@ pub struct PwmDevices {
     pub fan1: device::Fan1Peripherals,
     #[cfg(feature = "with-laser")]
     pub laser: device::LaserPeripherals,
+    #[cfg(feature = "with-spindle")]
+    pub spindle: device::SpindlePeripherals,
 }
 
 pub struct MotionDevices {
@@ -417,7 +419,7 @@ pub async fn setup(_spawner: Spawner, p: embassy_stm32::Peripherals) -> printhor
         ))
     };
 
-    #[cfg(any(feature = "with-laser"))]
+    #[cfg(any(feature = "with-laser", feature = "with-spindle"))]
     let (pwm_laser, pwm_laser_channel) = {
         static PWM_LASER_INST: TrackedStaticCell<ControllerMutex<device::PwmLaser>> = TrackedStaticCell::new();
         (
@@ -533,6 +535,12 @@ pub async fn setup(_spawner: Spawner, p: embassy_stm32::Peripherals) -> printhor
                 power_pwm: pwm_laser.clone(),
                 power_channel: pwm_laser_channel,
             },
+            #[cfg(feature = "with-spindle")]
+            spindle: SpindlePeripherals {
+                power_pwm: pwm_laser.clone(),
+                power_channel: pwm_laser_channel,
+                dir_pin: Output::new(p.PA15, Level::Low, Speed::Low),
+            },
         }
     }
 
@@ -542,4 +550,10 @@ pub async fn setup(_spawner: Spawner, p: embassy_stm32::Peripherals) -> printhor
 pub mod consts {
     /// 50ms to enqueue ~28 motion gcodes at 115200 bps
     pub(crate) const LINGER_MS: u64 = 10000;
+    /// Axes whose motor is wired in reverse (forward is dir pin low). Axis masks: X = 1, Y = 2, Z = 4, E = 8
+    pub const INVERT_DIR_AXES: u8 = 0;
+    /// Axes whose driver is enabled with the pin high
+    pub const INVERT_ENABLE_AXES: u8 = 0;
+    /// Axes whose endstop reads low when triggered
+    pub const INVERT_ENDSTOP_AXES: u8 = 0;
 }
\ No newline at end of file
@@ -8,4 +8,7 @@ pub trait DisplayScreenUI
     async fn refresh<D>(&mut self, m: &mut D)
     where D:DrawTarget,
         <D as DrawTarget>::Color: RgbColor;
+
+    /// The user pressed the pause/resume control (touch, button...)
+    async fn pause_resume(&mut self) {}
 }
\ No newline at end of file
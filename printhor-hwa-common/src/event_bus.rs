@@ -14,20 +14,25 @@ pub type SubscriberType<'a> = embassy_sync::pubsub::Subscriber<'a, ChannelMutexT
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq)]
     #[cfg_attr(feature = "with-defmt", derive(defmt::Format))]
-    pub struct EventFlags: u16 {
-        const SYS_BOOTING      = 0b1000000000000000;
-        const SYS_BOOT_FAILURE = 0b0100000000000000;
-        const SYS_READY        = 0b0010000000000000;
-        const SYS_ALARM        = 0b0001000000000000;
-        const ATX_ON           = 0b0000010000000000;
-        const HOMMING          = 0b0000001000000000;
-        const MOV_QUEUE_EMPTY  = 0b0000000100000000;
-        const HOTBED_TEMP_OK   = 0b0000000000000010;
-        const HOTEND_TEMP_OK   = 0b0000000000000100;
-        const X_MIN_ON         = 0b0000000000001000;
-        const Y_MIN_ON         = 0b0000000000010000;
-        const Z_MIN_ON         = 0b0000000000100000;
-
+    pub struct EventFlags: u32 {
+        const SYS_BOOTING      = 0b00000000000000001000000000000000;
+        const SYS_BOOT_FAILURE = 0b00000000000000000100000000000000;
+        const SYS_READY        = 0b00000000000000000010000000000000;
+        const SYS_ALARM        = 0b00000000000000000001000000000000;
+        const ATX_ON           = 0b00000000000000000000010000000000;
+        const HOMMING          = 0b00000000000000000000001000000000;
+        const MOV_QUEUE_EMPTY  = 0b00000000000000000000000100000000;
+        const JOB_PRINTING     = 0b00000000000000000000000010000000;
+        const JOB_PAUSED       = 0b00000000000000000000000001000000;
+        const HOTBED_TEMP_OK   = 0b00000000000000000000000000000010;
+        const HOTEND_TEMP_OK   = 0b00000000000000000000000000000100;
+        const X_MIN_ON         = 0b00000000000000000000000000001000;
+        const Y_MIN_ON         = 0b00000000000000000000000000010000;
+        const Z_MIN_ON         = 0b00000000000000000000000000100000;
+        const X_ENABLED        = 0b00000000000000010000000000000000;
+        const Y_ENABLED        = 0b00000000000000100000000000000000;
+        const Z_ENABLED        = 0b00000000000001000000000000000000;
+        const E_ENABLED        = 0b00000000000010000000000000000000;
     }
 }
 
@@ -39,6 +44,14 @@ pub struct EventBus {
 }
 
 impl EventBus {
+    pub fn new(bus: &'static PubSubType) -> Self {
+        Self {
+            bus,
+            publisher: bus.publisher().expect("publisher exausted"),
+            status: EventFlags::empty(),
+        }
+    }
+
     pub(crate) fn publish_event(&mut self, event: EventStatus) {
         // Get changing bits
         // i: 000
@@ -238,17 +251,10 @@ pub fn init_event_bus() -> EventBusRef {
     static EVT_CTRL_BUS: TrackedStaticCell<ControllerMutex<EventBus>> = TrackedStaticCell::new();
 
     let bus = EVT_BUS.init("EventBusChannel", PubSubType::new());
-    let publisher: PublisherType = bus.publisher().expect("publisher exausted");
 
     EventBusRef::new(
         ControllerRef::new(
-            EVT_CTRL_BUS.init("EventBus", ControllerMutex::new(
-                EventBus {
-                    bus,
-                    publisher,
-                    status: EventFlags::empty(),
-                }
-            ))
+            EVT_CTRL_BUS.init("EventBus", ControllerMutex::new(EventBus::new(bus)))
         )
     )
 }
@@ -11,10 +11,11 @@ use crate::hwa::controllers::printer_controller::{PrinterController, PrinterCont
 #[cfg(feature = "with-sdcard")]
 use crate::hwa::controllers::sdcard_controller::SDEntryType;
 
+/// With the Grbl protocol, the input stream is read by the Grbl input task instead
 pub struct ControlTaskDevices {
-    #[cfg(feature = "with-usbserial")]
+    #[cfg(all(feature = "with-usbserial", not(feature = "with-grbl-protocol")))]
     pub usb_serial_rx: hwa::devices::USBSerialDeviceInputStream,
-    #[cfg(feature = "with-uart-port-1")]
+    #[cfg(all(feature = "with-uart-port-1", not(feature = "with-grbl-protocol")))]
     pub uart_port1_rx_stream: hwa::devices::UartPort1RxInputStream,
 }
 
@@ -35,21 +36,35 @@ pub async fn control_task(
 
     // TODO: Design a multiplexing mechanism:
     // future_set[usb, uart_port1, uart_port2, ...] => iterator[channel, gcode]
-    #[cfg(feature = "with-usbserial")]
+    #[cfg(all(feature = "with-usbserial", not(feature = "with-grbl-protocol")))]
     let mut code_parser = crate::control::parser::GCodeLineParser::new(_d.usb_serial_rx);
-    #[cfg(all(not(feature = "with-usbserial"), feature = "with-uart-port-1"))]
+    #[cfg(all(not(feature = "with-usbserial"), feature = "with-uart-port-1", not(feature = "with-grbl-protocol")))]
     let mut code_parser = crate::control::parser::GCodeLineParser::new(_d.uart_port1_rx_stream);
+    #[cfg(feature = "with-grbl-protocol")]
+    let mut code_parser = crate::control::parser::GCodeLineParser::new(crate::control::grbl::GrblInputStream);
 
     let mut _processor = _processor;
 
     s.wait_until(EventStatus::containing(EventFlags::SYS_READY)).await;
     hwa::info!("Control_task started");
+    #[cfg(feature = "with-grbl-protocol")]
+    _processor.write(crate::control::grbl::WELCOME).await;
 
     #[cfg(any(feature = "with-usbserial", feature = "with-uart-port-1"))]
     loop {
         match code_parser.next_gcode().await {
             Err(err) => {
                 hwa::error!("GCODE ERR");
+                #[cfg(feature = "with-grbl-protocol")]
+                match err {
+                    crate::control::parser::GCodeLineParserError::ParseError(_x) => {
+                        _processor.write("error:1\r\n").await;
+                    }
+                    crate::control::parser::GCodeLineParserError::GCodeNotImplemented(_ln, _gcode_name) => {
+                        _processor.write("error:20\r\n").await;
+                    }
+                }
+                #[cfg(not(feature = "with-grbl-protocol"))]
                 match err {
                     crate::control::parser::GCodeLineParserError::ParseError(_x) => {
                         _processor.write("E. (ParserError)\n").await;
@@ -131,10 +146,67 @@ pub async fn control_task(
                                 }
                                 //println!("Exec M23...");
                             },
+                            #[cfg(feature = "with-motion")]
+                            crate::control::GCode::M24 | crate::control::GCode::M108 if _processor.motion_planner.is_feed_hold_requested() => {
+                                // Cycle start, before the print job or the pause get the code
+                                let result = _processor.execute(&gc, false).await;
+                                reply(&_processor, &gc, &result).await;
+                            },
+                            #[cfg(feature = "with-printjob")]
+                            crate::control::GCode::M108 if _processor.event_bus.has_flags(EventFlags::JOB_PRINTING).await => {
+                                // The print job is the one waiting: let it resume by itself
+                                _c.printer_controller.resume();
+                                _processor.write("O. M108 (OK)\n").await;
+                            },
+                            #[cfg(all(feature = "with-sdcard", feature = "with-motion"))]
+                            crate::control::GCode::T(tool) => {
+                                match crate::control::macros::change_tool(&mut _processor, &_c.card_controller, &mut s, tool).await {
+                                    Ok(_) => {
+                                        let s = alloc::format!("O. T{} (OK)\n", tool);
+                                        _processor.write(s.as_str()).await;
+                                    }
+                                    Err(_e) => {
+                                        let s = alloc::format!("E. T{} ({:?})\n", tool, _e);
+                                        _processor.write(s.as_str()).await;
+                                    }
+                                }
+                            },
+                            #[cfg(feature = "with-motion")]
+                            crate::control::GCode::M125 | crate::control::GCode::M600 | crate::control::GCode::M6(_) => {
+                                // Answered once resumed, as in a print job. Meanwhile, only M108 is accepted
+                                let result = match _processor.execute(&gc, false).await {
+                                    Ok(_) => {
+                                        loop {
+                                            match code_parser.next_gcode().await {
+                                                Ok(Some(crate::control::GCode::M108)) => break,
+                                                Ok(Some(other)) => {
+                                                    let s = alloc::format!("E. {} (Busy: paused. Send M108 to resume)\n", other.as_ref());
+                                                    _processor.write(s.as_str()).await;
+                                                }
+                                                Ok(None) => {
+                                                    embassy_time::Timer::after_secs(10).await;
+                                                }
+                                                Err(_) => {
+                                                    _processor.write("E. (Busy: paused. Send M108 to resume)\n").await;
+                                                }
+                                            }
+                                        }
+                                        _processor.execute(&crate::control::GCode::M108, false).await
+                                    }
+                                    Err(e) => Err(e),
+                                };
+                                reply(&_processor, &gc, &result).await;
+                            },
                             #[cfg(feature = "with-sdcard")]
                             crate::control::GCode::M24 => {
                                 _processor.write("E. M24 (Not yet properly implemented)\n").await;
                             },
+                            #[cfg(feature = "with-grbl-protocol")]
+                            _ => {
+                                let result = _processor.execute(&gc, false).await;
+                                crate::control::grbl::reply(&_processor, &result).await;
+                            }
+                            #[cfg(not(feature = "with-grbl-protocol"))]
                             _ => {
                                 match _processor.execute(&gc, false).await {
                                     Ok(CodeExecutionSuccess::OK) => {
@@ -167,3 +239,21 @@ pub async fn control_task(
         ).await;
     }
 }
+
+/// Final answer to a code whose reply was deferred
+#[allow(unused)]
+async fn reply(processor: &hwa::GCodeProcessor, gc: &crate::control::GCode, result: &CodeExecutionResult) {
+    #[cfg(feature = "with-grbl-protocol")]
+    crate::control::grbl::reply(processor, result).await;
+    #[cfg(not(feature = "with-grbl-protocol"))]
+    match result {
+        Ok(_) => {
+            let s = alloc::format!("O. {} (OK)\n", gc.as_ref());
+            processor.write(s.as_str()).await;
+        }
+        Err(_e) => {
+            let s = alloc::format!("E. {} ({:?})\n", gc.as_ref(), _e);
+            processor.write(s.as_str()).await;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Grbl-compatible protocol, for senders such as UGS or bCNC
+//!
+//! A dedicated task reads the serial input, so that the single-byte real-time commands (`?`, `!`, `~`, 0x18, 0x85)
+//! are handled even while the line parser is blocked on a full motion queue. The rest of the bytes are forwarded to
+//! the line parser, with the `$J=` (jog) and `$H` (homing) system commands rewritten to codes it understands.
+//! Other system commands are forwarded as they are and fail to parse
+use alloc::format;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use futures::{Stream, StreamExt};
+use printhor_hwa_common::EventFlags;
+use crate::hwa;
+use crate::ctrl::{CodeExecutionFailure, CodeExecutionResult};
+use crate::math::Real;
+
+/// Bytes buffered for the line parser. Senders never have more than Grbl's RX buffer (128) in flight
+const INPUT_BUFFER_SIZE: usize = 128;
+
+static PARSER_INPUT: Channel<CriticalSectionRawMutex, u8, INPUT_BUFFER_SIZE> = Channel::new();
+
+/// Sent on start and after a soft reset. Senders look for it to detect the protocol
+pub(crate) const WELCOME: &str = "\r\nGrbl 1.1f ['$' for help]\r\n";
+
+#[cfg(feature = "with-usbserial")]
+pub type GrblRxStream = hwa::devices::USBSerialDeviceInputStream;
+#[cfg(all(not(feature = "with-usbserial"), feature = "with-uart-port-1"))]
+pub type GrblRxStream = hwa::devices::UartPort1RxInputStream;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RealtimeCommand {
+    /// `?`
+    StatusReport,
+    /// `!`
+    FeedHold,
+    /// `~`
+    CycleStart,
+    /// 0x18 (Ctrl-X)
+    SoftReset,
+    /// 0x85
+    JogCancel,
+}
+
+impl RealtimeCommand {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            b'?' => Some(Self::StatusReport),
+            b'!' => Some(Self::FeedHold),
+            b'~' => Some(Self::CycleStart),
+            0x18 => Some(Self::SoftReset),
+            0x85 => Some(Self::JogCancel),
+            _ => None,
+        }
+    }
+}
+
+/// The line parser input: the bytes forwarded by [grbl_input_task]
+pub struct GrblInputStream;
+
+impl Stream for GrblInputStream {
+    type Item = Result<u8, async_gcode::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<<Self as futures::Stream>::Item>> {
+        PARSER_INPUT.poll_receive(ctx).map(|b| Some(Ok(b)))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LineState {
+    Start,
+    Body,
+    /// `$` at the start of the line
+    Dollar,
+    /// `$J`
+    DollarJ,
+}
+
+async fn forward(bytes: &[u8]) {
+    for b in bytes {
+        PARSER_INPUT.send(*b).await;
+    }
+}
+
+/// Forwards a byte, rewriting `$J=` to `J0 ` and `$H` to `G28`
+async fn rewrite(state: LineState, b: u8) -> LineState {
+    if b == b'\n' || b == b'\r' {
+        match state {
+            LineState::Dollar => forward(b"$").await,
+            LineState::DollarJ => forward(b"$J").await,
+            _ => {}
+        }
+        forward(&[b]).await;
+        return LineState::Start;
+    }
+    match (state, b) {
+        (LineState::Start, b'$') => LineState::Dollar,
+        (LineState::Start, _) | (LineState::Body, _) => {
+            forward(&[b]).await;
+            LineState::Body
+        }
+        (LineState::Dollar, b'J') | (LineState::Dollar, b'j') => LineState::DollarJ,
+        (LineState::Dollar, b'H') | (LineState::Dollar, b'h') => {
+            forward(b"G28").await;
+            LineState::Body
+        }
+        (LineState::Dollar, _) => {
+            forward(&[b'$', b]).await;
+            LineState::Body
+        }
+        (LineState::DollarJ, b'=') => {
+            forward(b"J0 ").await;
+            LineState::Body
+        }
+        (LineState::DollarJ, _) => {
+            forward(&[b'$', b'J', b]).await;
+            LineState::Body
+        }
+    }
+}
+
+/// `<State|MPos:x,y,z|WCO:x,y,z|FS:feed,speed>`
+async fn status_report(processor: &hwa::GCodeProcessor) {
+    let motion_planner = &processor.motion_planner;
+    let flags = processor.event_bus.get_status().await;
+    let idle = flags.contains(EventFlags::MOV_QUEUE_EMPTY);
+    let state = if flags.contains(EventFlags::SYS_ALARM) {
+        "Alarm"
+    } else if flags.contains(EventFlags::HOMMING) {
+        "Home"
+    } else if motion_planner.is_feed_hold_requested() {
+        "Hold"
+    } else if idle {
+        "Idle"
+    } else if motion_planner.is_jogging().await {
+        "Jog"
+    } else {
+        "Run"
+    };
+    let pos = motion_planner.get_realtime_pos().await.rdp(3);
+    let wco = motion_planner.get_work_coords().await.offset().rdp(3);
+    let feed = match idle {
+        true => Real::zero(),
+        // Units per minute
+        false => (motion_planner.get_realtime_speed().await * Real::from_lit(60, 0)).rdp(0),
+    };
+    #[cfg(feature = "with-spindle")]
+    let speed = processor.spindle.lock().await.get_rpm();
+    #[cfg(not(feature = "with-spindle"))]
+    let speed = 0u32;
+    let s = format!("<{}|MPos:{},{},{}|WCO:{},{},{}|FS:{},{}>\r\n",
+        state,
+        pos.x.unwrap_or(Real::zero()), pos.y.unwrap_or(Real::zero()), pos.z.unwrap_or(Real::zero()),
+        wco.x.unwrap_or(Real::zero()), wco.y.unwrap_or(Real::zero()), wco.z.unwrap_or(Real::zero()),
+        feed, speed,
+    );
+    processor.write(s.as_str()).await;
+}
+
+async fn execute_realtime(processor: &hwa::GCodeProcessor, cmd: RealtimeCommand) {
+    let motion_planner = &processor.motion_planner;
+    match cmd {
+        RealtimeCommand::StatusReport => status_report(processor).await,
+        RealtimeCommand::FeedHold => motion_planner.feed_hold(),
+        RealtimeCommand::CycleStart => motion_planner.cycle_start(),
+        RealtimeCommand::SoftReset => {
+            let _ = motion_planner.quick_stop().await;
+            // Also drops a feed hold with nothing executing
+            motion_planner.cycle_start();
+            motion_planner.cancel_canned_cycle().await;
+            processor.write(WELCOME).await;
+        }
+        RealtimeCommand::JogCancel => {
+            if motion_planner.is_jogging().await && !processor.event_bus.has_flags(EventFlags::MOV_QUEUE_EMPTY).await {
+                let _ = motion_planner.quick_stop().await;
+            }
+        }
+    }
+}
+
+/// The reply senders count lines with: `ok` or `error:<code>`
+pub(crate) async fn reply(processor: &hwa::GCodeProcessor, result: &CodeExecutionResult) {
+    let code = match result {
+        Ok(_) => {
+            processor.write("ok\r\n").await;
+            return;
+        }
+        Err(CodeExecutionFailure::HomingRequired) => 9,
+        Err(_) => 20,
+    };
+    processor.write(format!("error:{}\r\n", code).as_str()).await;
+}
+
+#[embassy_executor::task(pool_size=1)]
+pub async fn grbl_input_task(processor: hwa::GCodeProcessor, mut rx: GrblRxStream) {
+    hwa::info!("Grbl input task started");
+    let mut state = LineState::Start;
+    loop {
+        match rx.next().await {
+            Some(Ok(b)) => match RealtimeCommand::from_byte(b) {
+                Some(cmd) => execute_realtime(&processor, cmd).await,
+                None => state = rewrite(state, b).await,
+            },
+            Some(Err(_)) => {
+                hwa::warn!("Grbl input: read error");
+            }
+            None => {
+                // Avoid spinning on a closed stream
+                embassy_time::Timer::after_secs(1).await;
+            }
+        }
+    }
+}
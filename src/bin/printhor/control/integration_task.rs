@@ -119,7 +119,12 @@ pub(crate) async fn integration_task(mut params: IntegrationaskParams)
     #[cfg(feature = "integration-test-dwell")]
     {
         hwa::info!("Testing G4");
-        if let Some(evt) = processor.execute(&GCode::G4, false).await.and_then(expect_deferred).ok() {
+        let g4_code = GCode::G4(crate::control::PS {
+            ln: None,
+            p: Some(Real::new(500, 0)),
+            s: None,
+        });
+        if let Some(evt) = processor.execute(&g4_code, false).await.and_then(expect_deferred).ok() {
             subscriber.wait_until(evt).await;
             crate::info!("-- G4 OK");
         } else {
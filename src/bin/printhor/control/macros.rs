@@ -0,0 +1,73 @@
+//! G-Code macros stored in the SD card
+//!
+//! So far, only the tool change ones: `/sys/tfree<n>.g`, `/sys/tpre<n>.g` and `/sys/tpost<n>.g`
+use crate::hwa;
+use alloc::format;
+use crate::control::GCode;
+use crate::control::parser::GCodeLineParser;
+use crate::ctrl::*;
+use crate::hwa::controllers::CardController;
+use crate::hwa::controllers::sdcard_controller::SDCardError;
+use printhor_hwa_common::EventBusSubscriber;
+
+/// Directory where the macros are looked up
+const MACRO_DIR: &str = "/sys";
+
+/// Runs the G-Code file at `path`, waiting for each code to complete. A missing file is not an error
+pub(crate) async fn run_macro(
+    processor: &mut hwa::GCodeProcessor,
+    card_controller: &CardController,
+    subscriber: &mut EventBusSubscriber<'static>,
+    path: &str,
+) -> Result<(), SDCardError> {
+    let mut parser = match card_controller.new_stream(path).await {
+        Ok(stream) => GCodeLineParser::new(stream),
+        Err(SDCardError::NotFound) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    hwa::debug!("Running macro {}", path);
+    loop {
+        match parser.next_gcode().await {
+            Ok(None) => break,
+            Ok(Some(gc)) => {
+                match processor.execute(&gc, true).await {
+                    Ok(CodeExecutionSuccess::DEFERRED(state)) => {
+                        subscriber.wait_until(state).await;
+                    }
+                    Ok(_) => {}
+                    Err(_) => {
+                        hwa::warn!("Macro {}: line {} failed", path, parser.current_line());
+                    }
+                }
+            }
+            Err(_) => {
+                hwa::warn!("Macro {}: line {} ignored", path, parser.current_line());
+            }
+        }
+    }
+    parser.close().await;
+    Ok(())
+}
+
+/// Tn with macros: tfree of the current tool, tpre of the new one, the switch itself and tpost of the new one
+pub(crate) async fn change_tool(
+    processor: &mut hwa::GCodeProcessor,
+    card_controller: &CardController,
+    subscriber: &mut EventBusSubscriber<'static>,
+    tool: u8,
+) -> CodeExecutionResult {
+    let prev = processor.motion_planner.get_current_tool().await;
+    if prev == Some(tool) {
+        return Ok(CodeExecutionSuccess::OK);
+    }
+    if processor.motion_planner.get_tool(tool).await.is_none() {
+        return Err(CodeExecutionFailure::ERR);
+    }
+    if let Some(prev) = prev {
+        let _ = run_macro(processor, card_controller, subscriber, format!("{}/tfree{}.g", MACRO_DIR, prev).as_str()).await;
+    }
+    let _ = run_macro(processor, card_controller, subscriber, format!("{}/tpre{}.g", MACRO_DIR, tool).as_str()).await;
+    let r = processor.execute(&GCode::T(tool), true).await?;
+    let _ = run_macro(processor, card_controller, subscriber, format!("{}/tpost{}.g", MACRO_DIR, tool).as_str()).await;
+    Ok(r)
+}
@@ -13,8 +13,14 @@ pub(crate) mod control_task;
 pub(crate) mod parser;
 #[cfg(feature = "with-motion")]
 pub(crate) mod deferr_task;
+#[cfg(feature = "with-motion")]
+pub(crate) mod report_task;
+#[cfg(all(feature = "with-sdcard", feature = "with-motion"))]
+pub(crate) mod macros;
 #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
 pub(crate) mod temperature_task;
+#[cfg(feature = "with-grbl-protocol")]
+pub(crate) mod grbl;
 
 #[allow(dead_code)]
 #[derive(Clone, Default)]
@@ -23,6 +29,29 @@ pub struct S {
     pub(crate) ln: Option<u32>,
     pub(crate) s: Option<Real>,
 }
+/// Flag-only param. Set when the `R` word is present
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct R {
+    pub(crate) ln: Option<u32>,
+    pub(crate) r: bool,
+}
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct T {
+    pub(crate) ln: Option<u32>,
+    pub(crate) t: Option<Real>,
+}
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct H {
+    pub(crate) ln: Option<u32>,
+    pub(crate) h: Option<Real>,
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Default)]
 #[cfg_attr(feature = "native", derive(Debug))]
@@ -51,6 +80,8 @@ pub struct XYZ {
     pub(crate) x: Option<Real>,
     pub(crate) y: Option<Real>,
     pub(crate) z: Option<Real>,
+    /// Only used by G92
+    pub(crate) e: Option<Real>,
 }
 
 impl XYZ {
@@ -87,6 +118,26 @@ impl Display for XYZ {
     }
 }
 
+/// Axis selection (flags, value ignored) plus S
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct XYZES {
+    pub(crate) ln: Option<u32>,
+    pub(crate) x: bool,
+    pub(crate) y: bool,
+    pub(crate) z: bool,
+    pub(crate) e: bool,
+    pub(crate) s: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for XYZES {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "XYZES {:?}", self.ln)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Default)]
 #[cfg_attr(feature = "native", derive(Debug))]
@@ -122,6 +173,177 @@ impl crate::hwa::defmt::Format for XYZEFS {
     }
 }
 
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct PS {
+    pub(crate) ln: Option<u32>,
+    pub(crate) p: Option<Real>,
+    pub(crate) s: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for PS {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "PS {:?}", self.ln)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct FSZ {
+    pub(crate) ln: Option<u32>,
+    pub(crate) f: Option<Real>,
+    pub(crate) s: Option<Real>,
+    pub(crate) z: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for FSZ {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "FSZ {:?}", self.ln)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct LPRSXYZ {
+    pub(crate) ln: Option<u32>,
+    pub(crate) l: Option<Real>,
+    pub(crate) p: Option<Real>,
+    pub(crate) r: Option<Real>,
+    pub(crate) s: Option<Real>,
+    pub(crate) x: Option<Real>,
+    pub(crate) y: Option<Real>,
+    pub(crate) z: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for LPRSXYZ {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "LPRSXYZ {:?}", self.ln)
+    }
+}
+
+/// Drilling cycle parameters
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct FPQRXYZ {
+    pub(crate) ln: Option<u32>,
+    pub(crate) f: Option<Real>,
+    pub(crate) p: Option<Real>,
+    pub(crate) q: Option<Real>,
+    pub(crate) r: Option<Real>,
+    pub(crate) x: Option<Real>,
+    pub(crate) y: Option<Real>,
+    pub(crate) z: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for FPQRXYZ {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "FPQRXYZ {:?}", self.ln)
+    }
+}
+
+/// Jog params. G91 (relative) and G53 (machine coords) are flags
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct FGXYZ {
+    pub(crate) ln: Option<u32>,
+    pub(crate) f: Option<Real>,
+    pub(crate) relative: bool,
+    pub(crate) machine: bool,
+    pub(crate) x: Option<Real>,
+    pub(crate) y: Option<Real>,
+    pub(crate) z: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for FGXYZ {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "FGXYZ {:?}", self.ln)
+    }
+}
+
+/// Raster scanline params. D is the base64 of the pixel intensities (0 to 255)
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct DFSXY {
+    pub(crate) ln: Option<u32>,
+    pub(crate) d: Option<String>,
+    pub(crate) f: Option<Real>,
+    pub(crate) s: Option<Real>,
+    pub(crate) x: Option<Real>,
+    pub(crate) y: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for DFSXY {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "DFSXY {:?}", self.ln)
+    }
+}
+
+/// Heater PID gains
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct PID {
+    pub(crate) ln: Option<u32>,
+    pub(crate) p: Option<Real>,
+    pub(crate) i: Option<Real>,
+    pub(crate) d: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for PID {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "PID {:?}", self.ln)
+    }
+}
+
+/// PID autotune params
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct CESU {
+    pub(crate) ln: Option<u32>,
+    pub(crate) c: Option<Real>,
+    pub(crate) e: Option<Real>,
+    pub(crate) s: Option<Real>,
+    pub(crate) u: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for CESU {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "CESU {:?}", self.ln)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "native", derive(Debug))]
+pub struct PDH {
+    pub(crate) ln: Option<u32>,
+    pub(crate) p: Option<Real>,
+    pub(crate) d: Option<Real>,
+    pub(crate) h: Option<Real>,
+}
+
+#[cfg(feature = "with-defmt")]
+impl crate::hwa::defmt::Format for PDH {
+    fn format(&self, fmt: crate::hwa::defmt::Formatter) {
+        crate::hwa::defmt::write!(fmt, "PDH {:?}", self.ln)
+    }
+}
+
 #[allow(unused)]
 #[derive(Clone, EnumVariantNames, AsRefStr, Default)]
 #[cfg_attr(feature = "native", derive(Display))]
@@ -129,18 +351,34 @@ pub enum GCode {
     /// No Operation
     #[default]
     NOP,
+    /// Coordinates without a command. Drills another hole while a drilling cycle is active
+    MODAL(XYZEFS),
+    /// Jog (Grbl `$J=`). Cancelled by the jog cancel real-time command
+    #[cfg(feature = "with-grbl-protocol")]
+    JOG(FGXYZ),
     /// List supported G-Codes
     G,
     /// Rapid move
     G0(XYZ),
     /// Linear move
     G1(XYZEFS),
-    /// Dwell
-    G4,
-    G10, G11, // retraction
+    /// Dwell (P milliseconds or S seconds)
+    G4(PS),
+    /// Raster scanline: burns the pixels (D) up to X Y, with S the power of a 255 pixel
+    #[cfg(feature = "with-laser")]
+    G7(DFSXY),
+    /// Retract (firmware retraction) when neither L nor P are given.
+    /// G10 L2 P<wcs> sets a work coordinate system origin (X Y Z). G10 L20 P<wcs> sets it so the current position gets the given coords.
+    /// G10 P<tool> sets the tool offsets (X Y Z) and temperatures (S active, R standby)
+    G10(LPRSXYZ),
+    /// Recover (firmware retraction)
+    G11,
     G17, G18, G19, // CNC Plane selection
     G21, // Settings
-    G22, G23, // Retraction
+    /// Retract (alias of G10)
+    G22,
+    /// Recover (alias of G11)
+    G23,
 
     /// Move to Origin (Home)
     G28(XYZW),
@@ -156,28 +394,81 @@ pub enum GCode {
     G31,
     /// Undock Sled
     G32,
+    /// Probe toward the workpiece, stop on contact. Error on failure
     #[strum(serialize = "G38.2")]
-    G38_2, #[strum(serialize = "G38.3")] G38_3, #[strum(serialize = "G38.4")] G38_4, #[strum(serialize = "G38.5")] G38_5, G80, G81, G82, // Probing
-    G90, G91,
-    /// Set position
-    G92,
+    G38_2(XYZEFS),
+    /// Probe toward the workpiece, stop on contact
+    #[strum(serialize = "G38.3")]
+    G38_3(XYZEFS),
+    /// Probe away from the workpiece, stop on loss of contact. Error on failure
+    #[strum(serialize = "G38.4")]
+    G38_4(XYZEFS),
+    /// Probe away from the workpiece, stop on loss of contact
+    #[strum(serialize = "G38.5")]
+    G38_5(XYZEFS),
+    /// Apply the measured length of tool H (the one in the spindle when not given) to Z
+    G43(H),
+    /// Cancel tool length offset
+    G49,
+    /// Cancel drilling cycle
+    G80,
+    /// Drilling cycle (Z bottom, R retract plane, F feedrate)
+    G81(FPQRXYZ),
+    /// Drilling cycle with dwell at the bottom (P milliseconds)
+    G82(FPQRXYZ),
+    /// Peck drilling cycle (Q peck depth)
+    G83(FPQRXYZ),
+    /// Select work coordinate system 1 to 6
+    G54, G55, G56, G57, G58, G59,
+    /// Absolute positioning (default)
+    G90,
+    /// Relative positioning: G0/G1 coords are offsets from the current position
+    G91,
+    /// Set position (G92 offset). E sets the extruder position used after M82
+    G92(XYZ),
+    /// Clear the G92 offset
     #[strum(serialize = "G92.1")]
     G92_1,
+    /// Suspend the G92 offset, keeping its value
     #[strum(serialize = "G92.2")]
     G92_2, // Positioning
-    G93, G94, // Feed rate
+    /// Inverse time feed: F completes the move in 1/F minutes (F is then required in every G1)
+    G93,
+    /// Feed rate mode (default): F is the speed
+    G94,
+    /// Drilling cycles return to the initial Z
+    G98,
+    /// Drilling cycles return to the R plane
+    G99,
 
     /// List supported M-Codes
     M,
-    M0, M1, M2, // Program control
-    M3, M4, M5, // CNC/Laser
-    M6, M7, M8, M9, M10, M11, M13, M16, // CNC
-    M17, M18, // Stepper motors
+    /// Feed hold: the move in progress decelerates to a stop, keeping the queue. M24 or M108 resumes
+    M0,
+    M1, M2, // Program control
+    /// Spindle CW / Laser constant power (S: power, up to the configured max) / Pen down
+    M3(S),
+    /// Spindle CCW / Laser dynamic power, scaled with the instantaneous velocity
+    M4(S),
+    /// Spindle / Laser off / Pen up
+    M5,
+    /// Tool change: stop the spindle, go to the tool change position and wait for M108. `T<n> M6` is also accepted
+    M6(T),
+    M7, M8, M9, M10, M11, M13, M16, // CNC
+    /// Enable steppers (all when no axis is given). With S1, they are also kept enabled on idle timeout (S0 undoes it)
+    M17(XYZES),
+    /// Disable steppers (all when no axis is given) or, with S, set the idle timeout in seconds (S0 = never)
+    M18(XYZES),
     /// List SD
     M20(Option<String>),
     M21, M22,
     /// Set print job file
-    M23(Option<String>), M24, M25, M26, M27, M30, M31, M32, M33, // SD
+    M23(Option<String>),
+    /// Start print job. After a feed hold, resume motion (cycle start)
+    M24,
+    /// Feed hold (as M0)
+    M25,
+    M26, M27, M30, M31, M32, M33, // SD
     M37, // Simulation mode
     /// Set Print Progress
     M73,
@@ -187,10 +478,12 @@ pub enum GCode {
     M80,
     /// ATX Power OFF
     M81,
-    /// Settings
+    /// Absolute extrusion: E is a position
+    M82,
+    /// Relative extrusion (default): E is a distance
     M83,
-    /// Disable steppers
-    M84, M92,
+    /// Disable steppers (alias of M18)
+    M84(XYZES), M92,
     /// Show memory usage
     M100,
     /// Set Hotend Temperature
@@ -201,6 +494,8 @@ pub enum GCode {
     M106,
     /// Fan Off
     M107,
+    /// Continue after a user confirmation wait (M600/M125)
+    M108,
     /// Wait for hotend temp
     M109(S),
     M110, // Settings
@@ -208,8 +503,8 @@ pub enum GCode {
     M111,
     /// Full emergency stop
     M112,
-    /// Get current position
-    M114,
+    /// Get current position. With R, the realtime (stepper) position instead of the planned one
+    M114(R),
     /// Get Firmware Version and Capabilities
     M115,
     /// Wait
@@ -221,8 +516,12 @@ pub enum GCode {
     /// Get Endstop Status
     M119,
     M120, M121, // Endstops get/set
+    /// Park head
+    M125,
     /// Set bed temperature
     M140,
+    /// Auto-report the feedrate and flow percentages every S seconds (S0 = off)
+    M155(S),
     /// Wait for bed temperature
     M190,
     M200,
@@ -231,13 +530,27 @@ pub enum GCode {
     /// Set Max Feedrate
     M203, M204,
     /// Set Advanced Settings
-    M205, M206, M207, M208, M209, M210, M211, M212, M218, // Settings
+    M205, M206,
+    /// Set firmware retraction length, feedrate and Z-hop
+    M207(FSZ),
+    /// Set firmware recover extra length and feedrate
+    M208(FSZ),
+    /// Enable/disable auto-retract for E-only moves
+    M209(S),
+    M210, M211, M212, M218, // Settings
     /// Set Feedrate percentage
-    M220,
+    M220(S),
     /// Set Flow Percentage
-    M221,
+    M221(S),
     M290, // Babystepping
-    M302, M305, M350, M360, // Settings
+    /// Set (P I D) or report the hotend PID gains
+    M301(PID),
+    M302,
+    /// PID autotune: S target, C cycles, E-1 for the bed. U1 applies the result
+    M303(CESU),
+    /// Set (P I D) or report the bed PID gains
+    M304(PID),
+    M305, M350, M360, // Settings
     /// Wait for moves and finish
     M400,
     M401, M402, // Probing
@@ -251,7 +564,12 @@ pub enum GCode {
     M510, M511, M512, M513, // Password and locking
     /// Abort SD printing
     M524,
-    M555, M563, M851,
+    M555,
+    /// Define, remove (D-1 H-1) or report a tool (P)
+    M563(PDH),
+    /// Filament change
+    M600,
+    M851,
     /// Report the status of position encoder modules.
     #[strum(serialize = "M862.1")] M862_1,
     /// Perform an axis continuity test for position encoder modules.
@@ -262,7 +580,9 @@ pub enum GCode {
     M900,
     /// Set motor current
     M907,
-    M929 // Logging
+    M929, // Logging
+    /// Select tool
+    T(u8),
 }
 
 #[cfg(feature = "defmt")]
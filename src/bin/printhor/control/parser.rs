@@ -1,5 +1,9 @@
 use crate::hwa;
-use crate::control::{GCode, S, XYZ, XYZEFS, XYZW};
+use crate::control::{GCode, CESU, FPQRXYZ, FSZ, H, LPRSXYZ, PDH, PID, PS, R, S, T, XYZ, XYZEFS, XYZES, XYZW};
+#[cfg(feature = "with-grbl-protocol")]
+use crate::control::FGXYZ;
+#[cfg(feature = "with-laser")]
+use crate::control::DFSXY;
 use crate::helpers;
 use alloc::string::String;
 use futures::Stream;
@@ -87,6 +91,7 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             x: None,
                                                             y: None,
                                                             z: None,
+                                                            e: None,
                                                         }))
                                                     }
                                                     ('g', Some((1, 0))) => {
@@ -100,12 +105,48 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             z: None,
                                                         }))
                                                     }
+                                                    #[cfg(feature = "with-laser")]
+                                                    ('g', Some((7, 0))) => {
+                                                        Some(GCode::G7(DFSXY {
+                                                            ln: current_line_number.clone(),
+                                                            d: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                        }))
+                                                    }
                                                     ('g', Some((4, 0))) => {
-                                                        Some(GCode::G4)
+                                                        Some(GCode::G4(PS {
+                                                            ln: current_line_number.clone(),
+                                                            p: None,
+                                                            s: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((10, 0))) => {
+                                                        Some(GCode::G10(LPRSXYZ {
+                                                            ln: current_line_number.clone(),
+                                                            l: None,
+                                                            p: None,
+                                                            r: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((11, 0))) => {
+                                                        Some(GCode::G11)
                                                     }
                                                     ('g', Some((21, 0))) => {
                                                         Some(GCode::G21)
                                                     }
+                                                    ('g', Some((22, 0))) => {
+                                                        Some(GCode::G22)
+                                                    }
+                                                    ('g', Some((23, 0))) => {
+                                                        Some(GCode::G23)
+                                                    }
                                                     ('g', Some((28, 0))) => {
                                                         Some(GCode::G28(XYZW {
                                                             ln: current_line_number.clone(),
@@ -124,14 +165,149 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('g', Some((32, 0))) => {
                                                         Some(GCode::G32)
                                                     }
+                                                    ('g', Some((43, 0))) => {
+                                                        Some(GCode::G43(H {
+                                                            ln: current_line_number.clone(),
+                                                            h: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((49, 0))) => {
+                                                        Some(GCode::G49)
+                                                    }
                                                     ('g', Some((80, 0))) => {
                                                         Some(GCode::G80)
                                                     }
+                                                    ('g', Some((81, 0))) => {
+                                                        Some(GCode::G81(FPQRXYZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            p: None,
+                                                            q: None,
+                                                            r: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((82, 0))) => {
+                                                        Some(GCode::G82(FPQRXYZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            p: None,
+                                                            q: None,
+                                                            r: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((83, 0))) => {
+                                                        Some(GCode::G83(FPQRXYZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            p: None,
+                                                            q: None,
+                                                            r: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
                                                     ('g', Some((90, 0))) => {
                                                         Some(GCode::G90)
                                                     }
+                                                    ('g', Some((91, 0))) => {
+                                                        Some(GCode::G91)
+                                                    }
+                                                    ('g', Some((93, 0))) => {
+                                                        Some(GCode::G93)
+                                                    }
+                                                    ('g', Some((94, 0))) => {
+                                                        Some(GCode::G94)
+                                                    }
+                                                    ('g', Some((98, 0))) => {
+                                                        Some(GCode::G98)
+                                                    }
+                                                    ('g', Some((99, 0))) => {
+                                                        Some(GCode::G99)
+                                                    }
+                                                    ('g', Some((54, 0))) => {
+                                                        Some(GCode::G54)
+                                                    }
+                                                    ('g', Some((55, 0))) => {
+                                                        Some(GCode::G55)
+                                                    }
+                                                    ('g', Some((56, 0))) => {
+                                                        Some(GCode::G56)
+                                                    }
+                                                    ('g', Some((57, 0))) => {
+                                                        Some(GCode::G57)
+                                                    }
+                                                    ('g', Some((58, 0))) => {
+                                                        Some(GCode::G58)
+                                                    }
+                                                    ('g', Some((59, 0))) => {
+                                                        Some(GCode::G59)
+                                                    }
                                                     ('g', Some((92, 0))) => {
-                                                        Some(GCode::G92)
+                                                        Some(GCode::G92(XYZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                            e: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((921, 1))) => {
+                                                        Some(GCode::G92_1)
+                                                    }
+                                                    ('g', Some((922, 1))) => {
+                                                        Some(GCode::G92_2)
+                                                    }
+                                                    ('g', Some((382, 1))) => {
+                                                        Some(GCode::G38_2(XYZEFS {
+                                                            ln: current_line_number.clone(),
+                                                            e: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((383, 1))) => {
+                                                        Some(GCode::G38_3(XYZEFS {
+                                                            ln: current_line_number.clone(),
+                                                            e: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((384, 1))) => {
+                                                        Some(GCode::G38_4(XYZEFS {
+                                                            ln: current_line_number.clone(),
+                                                            e: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('g', Some((385, 1))) => {
+                                                        Some(GCode::G38_5(XYZEFS {
+                                                            ln: current_line_number.clone(),
+                                                            e: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
                                                     }
                                                     ('g', Some((291, 1))) => {
                                                         Some(GCode::G29_1)
@@ -142,6 +318,44 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', None) => {
                                                         Some(GCode::M)
                                                     }
+                                                    ('m', Some((3, 0))) => {
+                                                        Some(GCode::M3(S{ln: None, s: None}))
+                                                    }
+                                                    ('m', Some((4, 0))) => {
+                                                        Some(GCode::M4(S{ln: None, s: None}))
+                                                    }
+                                                    ('m', Some((5, 0))) => {
+                                                        Some(GCode::M5)
+                                                    }
+                                                    ('m', Some((6, 0))) => {
+                                                        Some(GCode::M6(T {
+                                                            ln: current_line_number.clone(),
+                                                            t: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((0, 0))) => {
+                                                        Some(GCode::M0)
+                                                    }
+                                                    ('m', Some((17, 0))) => {
+                                                        Some(GCode::M17(XYZES {
+                                                            ln: current_line_number.clone(),
+                                                            x: false,
+                                                            y: false,
+                                                            z: false,
+                                                            e: false,
+                                                            s: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((18, 0))) => {
+                                                        Some(GCode::M18(XYZES {
+                                                            ln: current_line_number.clone(),
+                                                            x: false,
+                                                            y: false,
+                                                            z: false,
+                                                            e: false,
+                                                            s: None,
+                                                        }))
+                                                    }
                                                     ('m', Some((20, 0))) => {
                                                         Some(GCode::M20(None))
                                                     }
@@ -151,6 +365,9 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((24, 0))) => {
                                                         Some(GCode::M24)
                                                     }
+                                                    ('m', Some((25, 0))) => {
+                                                        Some(GCode::M25)
+                                                    }
                                                     ('m', Some((73, 0))) => {
                                                         Some(GCode::M73)
                                                     }
@@ -163,11 +380,21 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((81, 0))) => {
                                                         Some(GCode::M81)
                                                     }
+                                                    ('m', Some((82, 0))) => {
+                                                        Some(GCode::M82)
+                                                    }
                                                     ('m', Some((83, 0))) => {
                                                         Some(GCode::M83)
                                                     }
                                                     ('m', Some((84, 0))) => {
-                                                        Some(GCode::M84)
+                                                        Some(GCode::M84(XYZES {
+                                                            ln: current_line_number.clone(),
+                                                            x: false,
+                                                            y: false,
+                                                            z: false,
+                                                            e: false,
+                                                            s: None,
+                                                        }))
                                                     }
                                                     ('m', Some((100, 0))) => {
                                                         Some(GCode::M100)
@@ -184,6 +411,9 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((107, 0))) => {
                                                         Some(GCode::M107)
                                                     }
+                                                    ('m', Some((108, 0))) => {
+                                                        Some(GCode::M108)
+                                                    }
                                                     ('m', Some((109, 0))) => {
                                                         Some(GCode::M109(
                                                             S {
@@ -193,7 +423,10 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                         )
                                                     }
                                                     ('m', Some((114, 0))) => {
-                                                        Some(GCode::M114)
+                                                        Some(GCode::M114(R {
+                                                            ln: current_line_number.clone(),
+                                                            r: false,
+                                                        }))
                                                     }
                                                     ('m', Some((115, 0))) => {
                                                         Some(GCode::M115)
@@ -204,9 +437,18 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((119, 0))) => {
                                                         Some(GCode::M119)
                                                     }
+                                                    ('m', Some((125, 0))) => {
+                                                        Some(GCode::M125)
+                                                    }
                                                     ('m', Some((140, 0))) => {
                                                         Some(GCode::M140)
                                                     }
+                                                    ('m', Some((155, 0))) => {
+                                                        Some(GCode::M155(S {
+                                                            ln: current_line_number.clone(),
+                                                            s: None,
+                                                        }))
+                                                    }
                                                     ('m', Some((190, 0))) => {
                                                         Some(GCode::M190)
                                                     }
@@ -222,12 +464,82 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((205, 0))) => {
                                                         Some(GCode::M205)
                                                     }
+                                                    ('m', Some((207, 0))) => {
+                                                        Some(GCode::M207(FSZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            s: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((208, 0))) => {
+                                                        Some(GCode::M208(FSZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            s: None,
+                                                            z: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((209, 0))) => {
+                                                        Some(GCode::M209(S {
+                                                            ln: current_line_number.clone(),
+                                                            s: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((220, 0))) => {
+                                                        Some(GCode::M220(S {
+                                                            ln: current_line_number.clone(),
+                                                            s: None,
+                                                        }))
+                                                    }
                                                     ('m', Some((221, 0))) => {
-                                                        Some(GCode::M221)
+                                                        Some(GCode::M221(S {
+                                                            ln: current_line_number.clone(),
+                                                            s: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((301, 0))) => {
+                                                        Some(GCode::M301(PID {
+                                                            ln: current_line_number.clone(),
+                                                            p: None,
+                                                            i: None,
+                                                            d: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((303, 0))) => {
+                                                        Some(GCode::M303(CESU {
+                                                            ln: current_line_number.clone(),
+                                                            c: None,
+                                                            e: None,
+                                                            s: None,
+                                                            u: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((304, 0))) => {
+                                                        Some(GCode::M304(PID {
+                                                            ln: current_line_number.clone(),
+                                                            p: None,
+                                                            i: None,
+                                                            d: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((410, 0))) => {
+                                                        Some(GCode::M410)
                                                     }
                                                     ('m', Some((502, 0))) => {
                                                         Some(GCode::M502)
                                                     }
+                                                    ('m', Some((563, 0))) => {
+                                                        Some(GCode::M563(PDH {
+                                                            ln: current_line_number.clone(),
+                                                            p: None,
+                                                            d: None,
+                                                            h: None,
+                                                        }))
+                                                    }
+                                                    ('m', Some((600, 0))) => {
+                                                        Some(GCode::M600)
+                                                    }
                                                     ('m', Some((8621, 1))) => {
                                                         Some(GCode::M862_1)
                                                     }
@@ -240,6 +552,35 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                     ('m', Some((907, 0))) => {
                                                         Some(GCode::M907)
                                                     }
+                                                    ('x', Some(val)) | ('y', Some(val)) | ('z', Some(val)) => {
+                                                        // Modal repeat. The first word holds a value too
+                                                        let v = Some(helpers::to_fixed(val));
+                                                        Some(GCode::MODAL(XYZEFS {
+                                                            ln: current_line_number.clone(),
+                                                            e: None,
+                                                            f: None,
+                                                            s: None,
+                                                            x: if ch == 'x' { v } else { None },
+                                                            y: if ch == 'y' { v } else { None },
+                                                            z: if ch == 'z' { v } else { None },
+                                                        }))
+                                                    }
+                                                    ('t', Some((n, 0))) if n >= 0 && n <= u8::MAX as i32 => {
+                                                        Some(GCode::T(n as u8))
+                                                    }
+                                                    #[cfg(feature = "with-grbl-protocol")]
+                                                    ('j', Some((0, 0))) => {
+                                                        // `$J=`, as rewritten by the Grbl input task
+                                                        Some(GCode::JOG(FGXYZ {
+                                                            ln: current_line_number.clone(),
+                                                            f: None,
+                                                            relative: false,
+                                                            machine: false,
+                                                            x: None,
+                                                            y: None,
+                                                            z: None,
+                                                        }))
+                                                    }
                                                     _ => {
                                                         skip_gcode = true;
                                                         None
@@ -248,7 +589,7 @@ impl<STREAM> GCodeLineParser<STREAM>
                                             }
                                             Some(current_gcode) => {
                                                 match current_gcode {
-                                                    GCode::G0(coord) => {
+                                                    GCode::G0(coord) | GCode::G92(coord) => {
                                                         match (ch, frx) {
                                                             ('x', Some(val)) => {
                                                                 coord.x.replace(helpers::to_fixed(val));
@@ -259,13 +600,18 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             ('z', Some(val)) => {
                                                                 coord.z.replace(helpers::to_fixed(val));
                                                             },
+                                                            ('e', Some(val)) => {
+                                                                coord.e.replace(helpers::to_fixed(val));
+                                                            },
                                                             ('f', Some(val)) => {
                                                                 coord.f.replace(helpers::to_fixed(val));
                                                             },
                                                             _ => {}
                                                         }
                                                     }
-                                                    GCode::G1(coord) => {
+                                                    GCode::G1(coord) | GCode::MODAL(coord)
+                                                    | GCode::G38_2(coord) | GCode::G38_3(coord)
+                                                    | GCode::G38_4(coord) | GCode::G38_5(coord) => {
                                                         match (ch, frx) {
                                                             ('x', Some(val)) => {
                                                                 coord.x.replace(helpers::to_fixed(val));
@@ -279,6 +625,23 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             ('e', Some(val)) => {
                                                                 coord.e.replace(helpers::to_fixed(val));
                                                             }
+                                                            ('f', Some(val)) => {
+                                                                coord.f.replace(helpers::to_fixed(val));
+                                                            }
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            }
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::G4(coord) => {
+                                                        match (ch, frx) {
+                                                            ('p', Some(val)) => {
+                                                                coord.p.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
                                                             _ => {}
                                                         }
                                                     }
@@ -303,6 +666,60 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             }
                                                         }
                                                     }
+                                                    #[cfg(feature = "with-laser")]
+                                                    GCode::G7(coord) => {
+                                                        match (ch, frx) {
+                                                            ('d', _) => {
+                                                                if let async_gcode::RealValue::Literal(async_gcode::Literal::String(mstr)) = fv {
+                                                                    coord.d.replace(mstr);
+                                                                }
+                                                            },
+                                                            ('f', Some(val)) => {
+                                                                coord.f.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('x', Some(val)) => {
+                                                                coord.x.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('y', Some(val)) => {
+                                                                coord.y.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M301(coord) | GCode::M304(coord) => {
+                                                        match (ch, frx) {
+                                                            ('p', Some(val)) => {
+                                                                coord.p.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('i', Some(val)) => {
+                                                                coord.i.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('d', Some(val)) => {
+                                                                coord.d.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M303(coord) => {
+                                                        match (ch, frx) {
+                                                            ('c', Some(val)) => {
+                                                                coord.c.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('e', Some(val)) => {
+                                                                coord.e.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('u', Some(val)) => {
+                                                                coord.u.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
                                                     GCode::M23(file) => {
                                                         if ch == 'f' {
                                                             if let async_gcode::RealValue::Literal(async_gcode::Literal::String(mstr)) = fv {
@@ -310,7 +727,153 @@ impl<STREAM> GCodeLineParser<STREAM>
                                                             }
                                                         }
                                                     }
-                                                    GCode::M104(coord) | GCode::M109(coord) => {
+                                                    GCode::G81(coord) | GCode::G82(coord) | GCode::G83(coord) => {
+                                                        match (ch, frx) {
+                                                            ('f', Some(val)) => {
+                                                                coord.f.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('p', Some(val)) => {
+                                                                coord.p.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('q', Some(val)) => {
+                                                                coord.q.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('r', Some(val)) => {
+                                                                coord.r.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('x', Some(val)) => {
+                                                                coord.x.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('y', Some(val)) => {
+                                                                coord.y.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('z', Some(val)) => {
+                                                                coord.z.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::G10(coord) => {
+                                                        match (ch, frx) {
+                                                            ('l', Some(val)) => {
+                                                                coord.l.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('p', Some(val)) => {
+                                                                coord.p.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('r', Some(val)) => {
+                                                                coord.r.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('x', Some(val)) => {
+                                                                coord.x.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('y', Some(val)) => {
+                                                                coord.y.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('z', Some(val)) => {
+                                                                coord.z.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M17(coord) | GCode::M18(coord) | GCode::M84(coord) => {
+                                                        match (ch, frx) {
+                                                            ('x', _) => coord.x = true,
+                                                            ('y', _) => coord.y = true,
+                                                            ('z', _) => coord.z = true,
+                                                            ('e', _) => coord.e = true,
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::T(n) => {
+                                                        // `T<n> M6`: the tool change, not a tool switch
+                                                        if let ('m', Some((6, 0))) = (ch, frx) {
+                                                            let tool = helpers::to_fixed((*n as i32, 0));
+                                                            *current_gcode = GCode::M6(T {
+                                                                ln: current_line_number.clone(),
+                                                                t: Some(tool),
+                                                            });
+                                                        }
+                                                    }
+                                                    GCode::M6(coord) => {
+                                                        match (ch, frx) {
+                                                            ('t', Some(val)) => {
+                                                                coord.t.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    #[cfg(feature = "with-grbl-protocol")]
+                                                    GCode::JOG(coord) => {
+                                                        match (ch, frx) {
+                                                            ('g', Some((90, 0))) => coord.relative = false,
+                                                            ('g', Some((91, 0))) => coord.relative = true,
+                                                            ('g', Some((53, 0))) => coord.machine = true,
+                                                            ('f', Some(val)) => {
+                                                                coord.f.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('x', Some(val)) => {
+                                                                coord.x.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('y', Some(val)) => {
+                                                                coord.y.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('z', Some(val)) => {
+                                                                coord.z.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::G43(coord) => {
+                                                        match (ch, frx) {
+                                                            ('h', Some(val)) => {
+                                                                coord.h.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M114(coord) => {
+                                                        if ch == 'r' {
+                                                            coord.r = true;
+                                                        }
+                                                    }
+                                                    GCode::M563(coord) => {
+                                                        match (ch, frx) {
+                                                            ('p', Some(val)) => {
+                                                                coord.p.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('d', Some(val)) => {
+                                                                coord.d.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('h', Some(val)) => {
+                                                                coord.h.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M207(coord) | GCode::M208(coord) => {
+                                                        match (ch, frx) {
+                                                            ('f', Some(val)) => {
+                                                                coord.f.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('s', Some(val)) => {
+                                                                coord.s.replace(helpers::to_fixed(val));
+                                                            },
+                                                            ('z', Some(val)) => {
+                                                                coord.z.replace(helpers::to_fixed(val));
+                                                            },
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                    GCode::M3(coord) | GCode::M4(coord)
+                                                    | GCode::M104(coord) | GCode::M109(coord) | GCode::M155(coord) | GCode::M209(coord)
+                                                    | GCode::M220(coord) | GCode::M221(coord) => {
                                                         match (ch, frx) {
                                                             ('s', Some(val)) => {
                                                                 coord.s.replace(helpers::to_fixed(val));
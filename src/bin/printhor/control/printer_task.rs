@@ -4,6 +4,7 @@ use crate::control::parser::{GCodeLineParser, GCodeLineParserError};
 use embassy_time::Timer;
 use embassy_time::Duration;
 use crate::ctrl::*;
+use crate::control::GCode;
 
 use crate::hwa::controllers::printer_controller::PrinterController;
 use crate::hwa::controllers::printer_controller::PrinterControllerEvent;
@@ -58,8 +59,23 @@ pub(crate) async fn printer_task(
                 };
 
                 let mut num_gcodes_processed: u32 = 0u32;
+                processor.event_bus.publish_event(EventStatus::containing(EventFlags::JOB_PRINTING)).await;
 
                 loop {
+                    // A pause request (e.g. from the display) parks the head as M125 does
+                    let park_request = match printer_controller.try_take() {
+                        Some(PrinterControllerEvent::Pause) => Some(GCode::M125),
+                        Some(PrinterControllerEvent::Abort) => {
+                            processor.write("E. M24 (Aborted)\n").await;
+                            break;
+                        }
+                        _ => None,
+                    };
+                    if let Some(gc) = park_request {
+                        if !wait_for_resume(&mut processor, &printer_controller, &gc).await {
+                            break;
+                        }
+                    }
                     match print_job_parser.next_gcode().await {
                         Err(_error) => {
                             match _error {
@@ -87,6 +103,21 @@ pub(crate) async fn printer_task(
                                 Some(gc) => {
                                     num_gcodes_processed += 1;
                                     match gc {
+                                        GCode::M125 | GCode::M600 | GCode::M6(_) => {
+                                            if !wait_for_resume(&mut processor, &printer_controller, &gc).await {
+                                                break;
+                                            }
+                                        }
+                                        #[cfg(all(feature = "with-sdcard", feature = "with-motion"))]
+                                        GCode::T(tool) => {
+                                            if let Err(_e) = crate::control::macros::change_tool(&mut processor, &card_controller, &mut subscriber, tool).await {
+                                                let s = alloc::format!("E. T{} (Tool change failed at line {})\n", tool, print_job_parser.current_line());
+                                                processor.write(s.as_str()).await;
+                                                if ABORT_ON_FAIL {
+                                                    break;
+                                                }
+                                            }
+                                        }
                                         _ => {
                                             hwa::debug!("Executing {}", gc);
                                             match processor.execute(&gc, true).await {
@@ -114,6 +145,12 @@ pub(crate) async fn printer_task(
                                                 Err(CodeExecutionFailure::HomingRequired) => {
                                                     hwa::debug!("E. (Homing required)");
                                                 },
+                                                Err(CodeExecutionFailure::SpindleStopped) => {
+                                                    hwa::error!("E. (Spindle stopped) at line {}", print_job_parser.current_line());
+                                                    if ABORT_ON_FAIL {
+                                                        break;
+                                                    }
+                                                },
                                             }
                                         }
                                     }
@@ -123,6 +160,7 @@ pub(crate) async fn printer_task(
                     }
                 }
                 print_job_parser.close().await;
+                processor.event_bus.publish_event(EventStatus::not_containing(EventFlags::JOB_PRINTING)).await;
                 hwa::info!("file done");
 
             }
@@ -136,4 +174,28 @@ pub(crate) async fn printer_task(
         }
         Timer::after(Duration::from_secs(2)).await;
     }
-}
\ No newline at end of file
+}
+
+/// Parks (M125/M600) or goes to the tool change position (M6) and waits for the Resume event (M108),
+/// then unparks or completes the tool change.
+/// Returns false when the job must be aborted
+async fn wait_for_resume(processor: &mut hwa::GCodeProcessor, printer_controller: &PrinterController, gc: &GCode) -> bool {
+    if let Err(_e) = processor.execute(gc, true).await {
+        processor.write("E. (Unable to pause)\n").await;
+        return false;
+    }
+    loop {
+        match printer_controller.wait().await {
+            PrinterControllerEvent::Resume => {
+                return processor.execute(&GCode::M108, true).await.is_ok();
+            }
+            PrinterControllerEvent::Abort => {
+                processor.event_bus.publish_event(EventStatus::not_containing(EventFlags::JOB_PAUSED)).await;
+                return false;
+            }
+            _ => {
+                hwa::debug!("Paused. Event ignored");
+            }
+        }
+    }
+}
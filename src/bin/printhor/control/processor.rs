@@ -2,14 +2,24 @@ use alloc::format;
 use printhor_hwa_common::{EventBusRef, EventFlags, EventStatus};
 use strum::{VariantNames};
 use crate::control::GCode;
+#[cfg(feature = "with-motion")]
+use crate::control::XYZES;
 use crate::machine::MACHINE_INFO;
 use crate::hwa;
 #[cfg(feature = "with-motion")]
-use crate::{hwa::controllers::{DeferEvent, DeferType}};
+use crate::{hwa::controllers::{CycleKind, CycleReturn, DeferEvent, DeferType, E_DRIVES, MAX_TOOLS, ToolConfig}};
+#[cfg(all(feature = "with-motion", feature = "with-hotend"))]
+use crate::hwa::controllers::HOTEND_HEATER;
+#[cfg(feature = "with-motion")]
+use crate::tgeo::{CoordSel, TVector};
 use crate::ctrl::{CodeExecutionFailure, CodeExecutionResult, CodeExecutionSuccess};
 use crate::math::Real;
+#[cfg(all(feature = "with-laser", feature = "with-motion"))]
+use crate::hwa::controllers::LaserMode;
 #[cfg(feature = "with-probe")]
 use crate::hwa::controllers::ProbeTrait;
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+use crate::{control::PID, hwa::controllers::PidGains};
 
 pub struct GCodeProcessorParams {
     pub event_bus: EventBusRef,
@@ -32,6 +42,8 @@ pub struct GCodeProcessorParams {
     pub fan1: hwa::controllers::Fan0PwmControllerRef,
     #[cfg(feature = "with-laser")]
     pub laser: hwa::controllers::LaserPwmControllerRef,
+    #[cfg(feature = "with-spindle")]
+    pub spindle: hwa::controllers::SpindleControllerRef,
 }
 
 #[derive(Clone)]
@@ -57,6 +69,8 @@ pub struct GCodeProcessor
     pub fan1: hwa::controllers::Fan1PwmControllerRef,
     #[cfg(feature = "with-laser")]
     pub laser: hwa::controllers::LaserPwmControllerRef,
+    #[cfg(feature = "with-spindle")]
+    pub spindle: hwa::controllers::SpindleControllerRef,
 }
 
 impl GCodeProcessor
@@ -83,6 +97,8 @@ impl GCodeProcessor
             fan1: params.fan1,
             #[cfg(feature = "with-laser")]
             laser: params.laser,
+            #[cfg(feature = "with-spindle")]
+            spindle: params.spindle,
 
             event_bus: params.event_bus,
         }
@@ -123,7 +139,48 @@ impl GCodeProcessor
                 Ok(CodeExecutionSuccess::OK)
             }
             #[cfg(feature = "with-motion")]
-            GCode::G0(_) | GCode::G1(_) => {
+            GCode::G10(t) if matches!(t.l.and_then(|l| l.to_i32()), Some(2) | Some(20)) => {
+                // P0 (or none) is the selected system, P1 to P6 are G54 to G59
+                let wcs = match t.p.map(|p| p.to_i32().ok_or(CodeExecutionFailure::NumericalError)).transpose()? {
+                    None | Some(0) => None,
+                    Some(p) if p > 0 => Some((p - 1) as u8),
+                    Some(_) => return Err(CodeExecutionFailure::ERR),
+                };
+                let coords = TVector { x: t.x, y: t.y, z: t.z, e: None };
+                match t.l.and_then(|l| l.to_i32()) {
+                    Some(2) => self.motion_planner.set_wcs_offset(wcs, &coords).await?,
+                    _ => self.motion_planner.set_wcs_position(wcs, &coords).await?,
+                }
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G10(t) if t.p.is_some() => {
+                let tool = t.p.and_then(|p| p.to_i32()).ok_or(CodeExecutionFailure::NumericalError)?;
+                let tool = u8::try_from(tool).map_err(|_| CodeExecutionFailure::ERR)?;
+                let mut cfg = self.motion_planner.get_tool(tool).await.ok_or(CodeExecutionFailure::ERR)?;
+                cfg.offset.assign_if_set(CoordSel::XYZ, &TVector { x: t.x, y: t.y, z: t.z, e: None });
+                if t.s.is_some() {
+                    cfg.active_temp = t.s;
+                }
+                if t.r.is_some() {
+                    cfg.standby_temp = t.r;
+                }
+                self.motion_planner.set_tool(tool, Some(cfg)).await?;
+                #[cfg(feature = "with-hotend")]
+                if self.motion_planner.get_current_tool().await == Some(tool) {
+                    self.apply_tool_temp(&cfg).await;
+                }
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G0(_) | GCode::G1(_) | GCode::G10(_) | GCode::G11 | GCode::G22 | GCode::G23 => {
+                #[cfg(feature = "with-spindle")]
+                if let GCode::G1(_) = gc {
+                    if !self.spindle.lock().await.is_running() {
+                        // Interlock: no feed moves with the spindle stopped
+                        return Err(CodeExecutionFailure::SpindleStopped);
+                    }
+                }
                 let result =  self.motion_planner.plan(&gc, _blocking).await?;
                 if !_blocking {
                     self.motion_planner.defer_channel.send(DeferEvent::LinearMove(DeferType::AwaitRequested)).await;
@@ -141,8 +198,24 @@ impl GCodeProcessor
                 }
                 Ok(CodeExecutionSuccess::OK)
             },
+            #[cfg(all(feature = "with-laser", feature = "with-motion"))]
+            GCode::G7(_) => {
+                let result = self.motion_planner.plan(&gc, _blocking).await?;
+                if !_blocking {
+                    self.motion_planner.defer_channel.send(DeferEvent::LinearMove(DeferType::AwaitRequested)).await;
+                }
+                Ok(result)
+            }
+            #[cfg(feature = "with-grbl-protocol")]
+            GCode::JOG(_) => {
+                let result = self.motion_planner.plan(&gc, _blocking).await?;
+                if !_blocking {
+                    self.motion_planner.defer_channel.send(DeferEvent::LinearMove(DeferType::AwaitRequested)).await;
+                }
+                Ok(result)
+            }
             #[cfg(feature = "with-motion")]
-            GCode::G4 => {
+            GCode::G4(_) => {
                 if !_blocking {
                     self.motion_planner.defer_channel.send(DeferEvent::Dwell(DeferType::AwaitRequested)).await;
                 }
@@ -187,13 +260,126 @@ impl GCodeProcessor
                     Ok(CodeExecutionSuccess::OK)
                 }
             }
+            #[cfg(feature = "with-motion")]
+            GCode::G38_2(t) | GCode::G38_3(t) | GCode::G38_4(t) | GCode::G38_5(t) => {
+                let (toward, must_succeed) = match gc {
+                    GCode::G38_2(_) => (true, true),
+                    GCode::G38_3(_) => (true, false),
+                    GCode::G38_4(_) => (false, true),
+                    _ => (false, false),
+                };
+                let (pos, triggered) = self.motion_planner.straight_probe(TVector {
+                    x: t.x, y: t.y, z: t.z, e: None,
+                }, t.f, toward).await?;
+                let pos = pos.rdp(3);
+                let z = format!("[PRB:{},{},{}:{}]\n",
+                    pos.x.unwrap_or(Real::zero()), pos.y.unwrap_or(Real::zero()), pos.z.unwrap_or(Real::zero()),
+                    if triggered { 1 } else { 0 },
+                );
+                let _ = self.write(z.as_str()).await;
+                match triggered || !must_succeed {
+                    true => Ok(CodeExecutionSuccess::OK),
+                    false => Err(CodeExecutionFailure::ERR),
+                }
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G43(t) => {
+                let tool = match t.h {
+                    None => None,
+                    Some(h) => Some(Self::tool_number(h)?),
+                };
+                self.motion_planner.set_tool_length_offset(tool).await?;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G49 => {
+                self.motion_planner.set_tool_length_offset(None).await?;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
             GCode::G80 => {
+                self.motion_planner.cancel_canned_cycle().await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G81(t) | GCode::G82(t) | GCode::G83(t) => {
+                #[cfg(feature = "with-spindle")]
+                if !self.spindle.lock().await.is_running() {
+                    return Err(CodeExecutionFailure::SpindleStopped);
+                }
+                let kind = match gc {
+                    GCode::G81(_) => CycleKind::Drill,
+                    GCode::G82(_) => CycleKind::DrillDwell,
+                    _ => CycleKind::Peck,
+                };
+                self.motion_planner.start_canned_cycle(kind, t).await
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::MODAL(t) => {
+                if self.motion_planner.get_canned_cycle().await.is_none() {
+                    hwa::warn!("Coordinates without command");
+                    return Err(CodeExecutionFailure::ERR);
+                }
+                #[cfg(feature = "with-spindle")]
+                if !self.spindle.lock().await.is_running() {
+                    return Err(CodeExecutionFailure::SpindleStopped);
+                }
+                self.motion_planner.repeat_canned_cycle(t.x, t.y).await
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G98 => {
+                self.motion_planner.set_cycle_return(CycleReturn::InitialZ).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G99 => {
+                self.motion_planner.set_cycle_return(CycleReturn::RPlane).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G90 | GCode::G91 => {
+                self.motion_planner.set_relative_positioning(matches!(gc, GCode::G91)).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G93 => {
+                self.motion_planner.set_inverse_time_feed(true).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G94 => {
+                self.motion_planner.set_inverse_time_feed(false).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G54 | GCode::G55 | GCode::G56 | GCode::G57 | GCode::G58 | GCode::G59 => {
+                let wcs = match gc {
+                    GCode::G54 => 0,
+                    GCode::G55 => 1,
+                    GCode::G56 => 2,
+                    GCode::G57 => 3,
+                    GCode::G58 => 4,
+                    _ => 5,
+                };
+                self.motion_planner.select_wcs(wcs).await?;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::G92(t) => {
+                if let Some(e) = t.e {
+                    self.motion_planner.set_e_position(e).await;
+                }
+                self.motion_planner.set_g92_position(&TVector { x: t.x, y: t.y, z: t.z, e: None }).await?;
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::G90 => {
+            #[cfg(feature = "with-motion")]
+            GCode::G92_1 => {
+                self.motion_planner.reset_g92(false).await;
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::G92 => {
+            #[cfg(feature = "with-motion")]
+            GCode::G92_2 => {
+                self.motion_planner.reset_g92(true).await;
                 Ok(CodeExecutionSuccess::OK)
             }
             GCode::M => {
@@ -203,6 +389,90 @@ impl GCodeProcessor
                 }
                 Ok(CodeExecutionSuccess::OK)
             }
+            #[cfg(all(feature = "with-laser", feature = "with-motion"))]
+            GCode::M3(t) => {
+                self.motion_planner.set_laser_mode(LaserMode::Constant, t.s).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(all(feature = "with-laser", feature = "with-motion"))]
+            GCode::M4(t) => {
+                self.motion_planner.set_laser_mode(LaserMode::Dynamic, t.s).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(all(feature = "with-laser", feature = "with-motion"))]
+            GCode::M5 => {
+                self.motion_planner.set_laser_mode(LaserMode::Off, None).await;
+                if self.event_bus.has_flags(EventFlags::MOV_QUEUE_EMPTY).await {
+                    // Nothing is moving, so the stepper will not do it
+                    self.laser.lock().await.set_power(0.0f32).await;
+                }
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(all(feature = "with-pen-plotter", not(feature = "with-laser"), not(feature = "with-spindle")))]
+            GCode::M3(_) => {
+                self.motion_planner.pen_to(true, _blocking).await?;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(all(feature = "with-pen-plotter", not(feature = "with-laser"), not(feature = "with-spindle")))]
+            GCode::M5 => {
+                self.motion_planner.pen_to(false, _blocking).await?;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-spindle")]
+            GCode::M3(t) => {
+                self.set_spindle(t.s, true).await
+            }
+            #[cfg(feature = "with-spindle")]
+            GCode::M4(t) => {
+                self.set_spindle(t.s, false).await
+            }
+            #[cfg(feature = "with-spindle")]
+            GCode::M5 => {
+                #[cfg(feature = "with-motion")]
+                self.motion_planner.synchronize().await;
+                self.spindle.lock().await.stop().await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M6(t) => {
+                let tool = match t.t {
+                    None => None,
+                    Some(t) => Some(Self::tool_number(t)?),
+                };
+                self.motion_planner.synchronize().await;
+                #[cfg(feature = "with-spindle")]
+                self.spindle.lock().await.stop().await;
+                self.motion_planner.begin_tool_change(tool).await?;
+                self.event_bus.publish_event(EventStatus::containing(EventFlags::JOB_PAUSED)).await;
+                let _ = self.write("echo:busy: tool change. Send M108 to resume\n").await;
+                // Completed on resume
+                Ok(CodeExecutionSuccess::DEFERRED(EventStatus::not_containing(EventFlags::JOB_PAUSED)))
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M17(t) => {
+                let axes = Self::selected_axes(t);
+                let axes = if axes.is_empty() { CoordSel::XYZE } else { axes };
+                if let Some(s) = t.s {
+                    // S1: also kept enabled on idle timeout. S0: powered off on idle timeout again
+                    let hold_axes = self.motion_planner.get_idle_hold_axes().await;
+                    self.motion_planner.set_idle_hold_axes(
+                        if s.is_zero() { hold_axes.difference(axes) } else { hold_axes | axes }
+                    ).await;
+                }
+                self.motion_planner.enable_steppers(axes).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M0 | GCode::M25 => {
+                self.motion_planner.feed_hold();
+                let _ = self.write("echo: feed hold. Send M24 or M108 to resume\n").await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M24 if self.motion_planner.is_feed_hold_requested() => {
+                self.motion_planner.cycle_start();
+                Ok(CodeExecutionSuccess::OK)
+            }
             GCode::M24 => {
                 Ok(CodeExecutionSuccess::OK)
             }
@@ -225,10 +495,22 @@ impl GCodeProcessor
                 self.event_bus.publish_event(EventStatus::not_containing(EventFlags::ATX_ON)).await;
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::M83 => {
+            #[cfg(feature = "with-motion")]
+            GCode::M82 | GCode::M83 => {
+                self.motion_planner.set_relative_extrusion(matches!(gc, GCode::M83)).await;
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::M84 => {
+            #[cfg(feature = "with-motion")]
+            GCode::M18(t) | GCode::M84(t) => {
+                let axes = Self::selected_axes(t);
+                if let Some(s) = t.s {
+                    self.motion_planner.set_stepper_idle_timeout(s.to_i32().ok_or(CodeExecutionFailure::NumericalError)?.max(0) as u32).await;
+                    if axes.is_empty() {
+                        return Ok(CodeExecutionSuccess::OK);
+                    }
+                }
+                self.motion_planner.synchronize().await;
+                self.motion_planner.disable_steppers(if axes.is_empty() { CoordSel::XYZE } else { axes }).await;
                 Ok(CodeExecutionSuccess::OK)
             }
             GCode::M100 => {
@@ -273,15 +555,67 @@ impl GCodeProcessor
                  self.fan0.lock().await.set_power(0.0f32).await;
                 Ok(CodeExecutionSuccess::OK)
             }
+            #[cfg(feature = "with-motion")]
+            GCode::M108 if self.motion_planner.is_feed_hold_requested() => {
+                self.motion_planner.cycle_start();
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M108 => {
+                if self.event_bus.has_flags(EventFlags::JOB_PAUSED).await {
+                    // The parked position (or the pending change) is taken anyway, so the pause is over even on failure
+                    let r = match self.motion_planner.finish_tool_change().await {
+                        Ok(Some(r)) => Ok(r),
+                        Ok(None) => self.motion_planner.unpark().await,
+                        Err(e) => Err(e),
+                    };
+                    self.event_bus.publish_event(EventStatus::not_containing(EventFlags::JOB_PAUSED)).await;
+                    let r = r?;
+                    let _ = self.write("echo: resumed\n").await;
+                    Ok(r)
+                }
+                else {
+                    Ok(CodeExecutionSuccess::OK)
+                }
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M125 | GCode::M600 => {
+                let unload = match gc {
+                    GCode::M600 => true,
+                    _ => false,
+                };
+                self.motion_planner.park(unload).await?;
+                self.event_bus.publish_event(EventStatus::containing(EventFlags::JOB_PAUSED)).await;
+                let _ = self.write("echo:busy: paused for user. Send M108 to resume\n").await;
+                // Completed on resume
+                Ok(CodeExecutionSuccess::DEFERRED(EventStatus::not_containing(EventFlags::JOB_PAUSED)))
+            }
             #[cfg(feature = "with-hotend")]
             GCode::M109(s) => {
                 self.hotend.lock().await.set_target_temp((s.s.and_then(|v| v.to_i32()).unwrap_or(0)) as f32).await;
                 Ok(CodeExecutionSuccess::DEFERRED(EventStatus::containing(EventFlags::HOTEND_TEMP_OK)))
             }
             #[cfg(feature = "with-motion")]
-            GCode::M114 => {
-                let _pos = self.motion_planner.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?.rdp(6);
-                let z = format!("M114 {}\n", _pos);
+            GCode::M114(r) => {
+                let machine_pos = match r.r {
+                    true => self.motion_planner.get_realtime_pos().await,
+                    false => self.motion_planner.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?,
+                };
+                // Reported in work coordinates
+                let work_coords = self.motion_planner.get_work_coords().await;
+                let pos = work_coords.to_work(&machine_pos).rdp(2);
+                let count = self.motion_planner.get_step_count().await;
+                let z = format!("X:{} Y:{} Z:{} E:{} Count X:{} Y:{} Z:{}\n",
+                    pos.x.unwrap_or(Real::zero()), pos.y.unwrap_or(Real::zero()),
+                    pos.z.unwrap_or(Real::zero()), pos.e.unwrap_or(Real::zero()),
+                    count.x.unwrap_or(0), count.y.unwrap_or(0), count.z.unwrap_or(0),
+                );
+                let _ = self.write(z.as_str()).await;
+                let offset = work_coords.offset().rdp(2);
+                let z = format!("echo:G{} Offset X:{} Y:{} Z:{}\n",
+                    work_coords.active_code(),
+                    offset.x.unwrap_or(Real::zero()), offset.y.unwrap_or(Real::zero()), offset.z.unwrap_or(Real::zero()),
+                );
                 let _ = self.write(z.as_str()).await;
                 Ok(CodeExecutionSuccess::OK)
             }
@@ -301,7 +635,11 @@ impl GCodeProcessor
                 let _ = self.write(" MACHINE_UUID: ").await;
                 let _ = self.write(MACHINE_INFO.machine_uuid).await;
                 let _ = self.write(" EXTRUDER_COUNT: ").await;
-                let _ = self.write(format!("{}\n", MACHINE_INFO.extruder_count).as_str()).await;
+                #[cfg(feature = "with-motion")]
+                let extruder_count = self.motion_planner.get_tool_count().await.max(MACHINE_INFO.extruder_count);
+                #[cfg(not(feature = "with-motion"))]
+                let extruder_count = MACHINE_INFO.extruder_count;
+                let _ = self.write(format!("{}\n", extruder_count).as_str()).await;
                 Ok(CodeExecutionSuccess::OK)
             }
             GCode::M117 => {
@@ -312,9 +650,9 @@ impl GCodeProcessor
             GCode::M119 => {
                 let d = self.motion_planner.motion_driver.lock().await;
                 let z = format!("M119 X {} Y {} Z {}\n",
-                    if d.pins.x_endstop_pin.is_high() {"1"} else {"0"},
-                    if d.pins.y_endstop_pin.is_high() {"1"} else {"0"},
-                    if d.pins.z_endstop_pin.is_high() {"1"} else {"0"},
+                    if d.endstop_triggered(CoordSel::X) {"1"} else {"0"},
+                    if d.endstop_triggered(CoordSel::Y) {"1"} else {"0"},
+                    if d.endstop_triggered(CoordSel::Z) {"1"} else {"0"},
                 );
                 drop(d);
                 let _ = self.write(z.as_str()).await;
@@ -323,6 +661,60 @@ impl GCodeProcessor
             GCode::M140 => {
                 Ok(CodeExecutionSuccess::OK)
             }
+            #[cfg(feature = "with-motion")]
+            GCode::M155(t) => {
+                let secs = t.s.and_then(|s| s.to_i32()).ok_or(CodeExecutionFailure::NumericalError)?;
+                self.motion_planner.set_auto_report_interval(secs.clamp(0, 60) as u8).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-hotend")]
+            GCode::M301(t) => {
+                let mut h = self.hotend.lock().await;
+                let gains = Self::update_pid_gains(h.get_pid_gains(), t);
+                h.set_pid_gains(gains);
+                drop(h);
+                self.report_pid_gains("", &gains).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-hotbed")]
+            GCode::M304(t) => {
+                let mut h = self.hotbed.lock().await;
+                let gains = Self::update_pid_gains(h.get_pid_gains(), t);
+                h.set_pid_gains(gains);
+                drop(h);
+                self.report_pid_gains("", &gains).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+            GCode::M303(t) => {
+                let bed = t.e.and_then(|e| e.to_i32()) == Some(-1);
+                let target = t.s.map(|s| s.to_f64() as f32).unwrap_or(if bed { 70.0f32 } else { 150.0f32 });
+                let cycles = t.c.and_then(|c| c.to_i32()).unwrap_or(5).clamp(3, 20) as u8;
+                let apply = t.u.and_then(|u| u.to_i32()).unwrap_or(0) != 0;
+                let _ = self.write("echo:PID Autotune start\n").await;
+                let gains = match bed {
+                    #[cfg(feature = "with-hotbed")]
+                    true => {
+                        let gains = hwa::controllers::HotbedController::autotune(&self.hotbed, target, cycles).await?;
+                        if apply {
+                            self.hotbed.lock().await.set_pid_gains(gains);
+                        }
+                        gains
+                    }
+                    #[cfg(feature = "with-hotend")]
+                    false => {
+                        let gains = hwa::controllers::HotendController::autotune(&self.hotend, target, cycles).await?;
+                        if apply {
+                            self.hotend.lock().await.set_pid_gains(gains);
+                        }
+                        gains
+                    }
+                    #[allow(unreachable_patterns)]
+                    _ => return Err(CodeExecutionFailure::ERR),
+                };
+                self.report_pid_gains("PID Autotune finished! ", &gains).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
             GCode::M190 => {
                 Ok(CodeExecutionSuccess::OK)
             }
@@ -338,10 +730,131 @@ impl GCodeProcessor
             GCode::M205 => {
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::M220 => {
+            #[cfg(feature = "with-motion")]
+            GCode::M207(t) => {
+                let mut cfg = self.motion_planner.get_retract_config().await;
+                if t.s.is_none() && t.f.is_none() && t.z.is_none() {
+                    let z = format!("M207 S{} F{} Z{}\n",
+                        cfg.length.rdp(4),
+                        cfg.speed.unwrap_or(self.motion_planner.get_default_travel_speed_as_real().await).rdp(4),
+                        cfg.z_hop.rdp(4),
+                    );
+                    let _ = self.write(z.as_str()).await;
+                    return Ok(CodeExecutionSuccess::OK);
+                }
+                if let Some(s) = t.s {
+                    cfg.length = s.abs();
+                }
+                if t.f.is_some() {
+                    cfg.speed = t.f;
+                }
+                if let Some(z) = t.z {
+                    cfg.z_hop = z.abs();
+                }
+                self.motion_planner.set_retract_config(cfg).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M208(t) => {
+                let mut cfg = self.motion_planner.get_retract_config().await;
+                if t.s.is_none() && t.f.is_none() {
+                    let z = format!("M208 S{} F{}\n",
+                        cfg.recover_extra_length.rdp(4),
+                        cfg.recover_speed.unwrap_or(self.motion_planner.get_default_travel_speed_as_real().await).rdp(4),
+                    );
+                    let _ = self.write(z.as_str()).await;
+                    return Ok(CodeExecutionSuccess::OK);
+                }
+                if let Some(s) = t.s {
+                    cfg.recover_extra_length = s;
+                }
+                if t.f.is_some() {
+                    cfg.recover_speed = t.f;
+                }
+                self.motion_planner.set_retract_config(cfg).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M209(t) => {
+                let mut cfg = self.motion_planner.get_retract_config().await;
+                match t.s {
+                    None => {
+                        let z = format!("M209 S{}\n", if cfg.auto_retract {1} else {0});
+                        let _ = self.write(z.as_str()).await;
+                    }
+                    Some(s) => {
+                        cfg.auto_retract = !s.is_zero();
+                        self.motion_planner.set_retract_config(cfg).await;
+                    }
+                }
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M220(t) => {
+                if let Some(s) = t.s {
+                    let rate = s.to_i32().ok_or(CodeExecutionFailure::NumericalError)?;
+                    self.motion_planner.set_speed_rate(rate.clamp(10, 255) as u8).await;
+                }
+                let z = format!("FR:{}%\n", self.motion_planner.get_speed_rate().await);
+                let _ = self.write(z.as_str()).await;
+                Ok(CodeExecutionSuccess::OK)
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M221(t) => {
+                if let Some(s) = t.s {
+                    let rate = s.to_i32().ok_or(CodeExecutionFailure::NumericalError)?;
+                    self.motion_planner.set_flow_rate(rate.clamp(10, 255) as u8).await;
+                }
+                let z = format!("echo:E0 Flow: {}%\n", self.motion_planner.get_flow_rate().await);
+                let _ = self.write(z.as_str()).await;
                 Ok(CodeExecutionSuccess::OK)
             }
-            GCode::M221 => {
+            #[cfg(feature = "with-motion")]
+            GCode::M410 => {
+                self.motion_planner.quick_stop().await
+            }
+            #[cfg(feature = "with-motion")]
+            GCode::M563(t) => {
+                let tools = match t.p {
+                    None => 0 .. MAX_TOOLS as u8,
+                    Some(p) => {
+                        let tool = u8::try_from(p.to_i32().ok_or(CodeExecutionFailure::NumericalError)?)
+                            .map_err(|_| CodeExecutionFailure::ERR)?;
+                        if t.d.is_some() || t.h.is_some() {
+                            // Negative values mean none. D-1 H-1 removes the tool
+                            let drive = t.d.and_then(|d| d.to_i32()).and_then(|d| u8::try_from(d).ok());
+                            let heater = t.h.and_then(|h| h.to_i32()).and_then(|h| u8::try_from(h).ok());
+                            if matches!(drive, Some(d) if d >= E_DRIVES) {
+                                return Err(CodeExecutionFailure::ERR);
+                            }
+                            let cfg = match (drive, heater) {
+                                (None, None) => None,
+                                _ => Some(match self.motion_planner.get_tool(tool).await {
+                                    Some(prev) => ToolConfig { drive, heater, ..prev },
+                                    None => ToolConfig::new(drive, heater),
+                                }),
+                            };
+                            self.motion_planner.set_tool(tool, cfg).await?;
+                            return Ok(CodeExecutionSuccess::OK);
+                        }
+                        tool .. tool + 1
+                    }
+                };
+                for tool in tools {
+                    if let Some(cfg) = self.motion_planner.get_tool(tool).await {
+                        let z = format!("Tool {} - drive: {}; heater: {} ({}/{}); offset: X{} Y{} Z{}\n",
+                            tool,
+                            cfg.drive.map_or(-1, |d| d as i32),
+                            cfg.heater.map_or(-1, |h| h as i32),
+                            cfg.active_temp.unwrap_or(Real::zero()).rdp(1),
+                            cfg.standby_temp.unwrap_or(Real::zero()).rdp(1),
+                            cfg.offset.x.unwrap_or(Real::zero()).rdp(3),
+                            cfg.offset.y.unwrap_or(Real::zero()).rdp(3),
+                            cfg.offset.z.unwrap_or(Real::zero()).rdp(3),
+                        );
+                        let _ = self.write(z.as_str()).await;
+                    }
+                }
                 Ok(CodeExecutionSuccess::OK)
             }
             #[cfg(feature = "with-trinamic")]
@@ -370,12 +883,84 @@ impl GCodeProcessor
             GCode::M907 => {
                 Ok(CodeExecutionSuccess::OK)
             }
+            #[cfg(feature = "with-motion")]
+            GCode::T(tool) => {
+                let r = self.motion_planner.change_tool(*tool).await?;
+                #[cfg(feature = "with-hotend")]
+                if let Some(cfg) = self.motion_planner.get_tool(*tool).await {
+                    self.apply_tool_temp(&cfg).await;
+                }
+                let z = format!("echo:Active Extruder: {}\n", tool);
+                let _ = self.write(z.as_str()).await;
+                Ok(r)
+            }
             _ => {
                 Err(CodeExecutionFailure::NotYetImplemented)
             }
         };
         result
     }
+
+    /// M3/M4: waits for the queued moves, then sets the speed (the last one when no S is given)
+    #[cfg(feature = "with-spindle")]
+    async fn set_spindle(&self, s: Option<Real>, forward: bool) -> CodeExecutionResult {
+        #[cfg(feature = "with-motion")]
+        self.motion_planner.synchronize().await;
+        let mut spindle = self.spindle.lock().await;
+        let rpm = match s {
+            Some(s) => s.to_i32().ok_or(CodeExecutionFailure::NumericalError)?.max(0) as u32,
+            None => spindle.get_last_rpm(),
+        };
+        spindle.set_speed(rpm, forward).await;
+        Ok(CodeExecutionSuccess::OK)
+    }
+
+    /// M301/M304: the given gains with the params set
+    #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+    fn update_pid_gains(mut gains: PidGains, t: &PID) -> PidGains {
+        if let Some(p) = t.p {
+            gains.kp = p.to_f64() as f32;
+        }
+        if let Some(i) = t.i {
+            gains.ki = i.to_f64() as f32;
+        }
+        if let Some(d) = t.d {
+            gains.kd = d.to_f64() as f32;
+        }
+        gains
+    }
+
+    #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+    async fn report_pid_gains(&self, prefix: &str, gains: &PidGains) {
+        let z = format!("echo:{}Kp:{} Ki:{} Kd:{}\n", prefix,
+            Real::from_f32(gains.kp).rdp(3), Real::from_f32(gains.ki).rdp(3), Real::from_f32(gains.kd).rdp(3));
+        let _ = self.write(z.as_str()).await;
+    }
+
+    /// A tool number given as a G-Code param (T, H)
+    #[cfg(feature = "with-motion")]
+    fn tool_number(v: Real) -> Result<u8, CodeExecutionFailure> {
+        u8::try_from(v.to_i32().ok_or(CodeExecutionFailure::NumericalError)?)
+            .map_err(|_| CodeExecutionFailure::ERR)
+    }
+
+    #[cfg(feature = "with-motion")]
+    fn selected_axes(t: &XYZES) -> CoordSel {
+        let mut axes = CoordSel::empty();
+        if t.x { axes |= CoordSel::X; }
+        if t.y { axes |= CoordSel::Y; }
+        if t.z { axes |= CoordSel::Z; }
+        if t.e { axes |= CoordSel::E; }
+        axes
+    }
+
+    /// Sets the hotend to the active temperature of the tool, if it has one and uses the hotend
+    #[cfg(all(feature = "with-motion", feature = "with-hotend"))]
+    async fn apply_tool_temp(&self, tool: &ToolConfig) {
+        if let (Some(HOTEND_HEATER), Some(temp)) = (tool.heater, tool.active_temp) {
+            self.hotend.lock().await.set_target_temp(temp.to_i32().unwrap_or(0) as f32).await;
+        }
+    }
 }
 
 impl Drop for GCodeProcessor {
@@ -0,0 +1,26 @@
+//! Host auto-report (M155): the feedrate (M220) and flow (M221) percentages, sent periodically
+use crate::hwa;
+use alloc::format;
+
+#[embassy_executor::task(pool_size=1)]
+pub async fn report_task(
+    processor: hwa::GCodeProcessor,
+) -> ! {
+    hwa::info!("report_task started");
+    loop {
+        match processor.motion_planner.get_auto_report_interval().await {
+            // Disabled: check again later
+            0 => embassy_time::Timer::after_secs(1).await,
+            secs => {
+                embassy_time::Timer::after_secs(secs as u64).await;
+                if processor.motion_planner.get_auto_report_interval().await > 0 {
+                    let z = format!("FR:{}% Flow:{}%\n",
+                                    processor.motion_planner.get_speed_rate().await,
+                                    processor.motion_planner.get_flow_rate().await,
+                    );
+                    processor.write(z.as_str()).await;
+                }
+            }
+        }
+    }
+}
@@ -65,6 +65,9 @@ use num_traits::float::FloatCore;
 use printhor_hwa_common::EventBusRef;
 use printhor_hwa_common::EventStatus;
 use printhor_hwa_common::EventFlags;
+use printhor_hwa_common::ControllerRef;
+use crate::hwa::controllers::HeaterController;
+use crate::hwa::devices::{AdcPinTrait, AdcTrait};
 
 use pid;
 
@@ -79,48 +82,94 @@ enum State {
     Maintaining,
 }
 
+/// Control period, in seconds
+const PERIOD_SECS: u64 = 2;
+
+#[cfg(feature = "with-hotend")]
 #[embassy_executor::task(pool_size=1)]
 pub async fn temp_task(
     event_bus: EventBusRef,
     hotend_controller: hwa::controllers::HotendControllerRef
 ) -> ! {
     hwa::info!("D; temperature_task started");
+    heater_loop(event_bus, hotend_controller, EventFlags::HOTEND_TEMP_OK).await
+}
+
+/// Same control loop as the hotend, with the gains set by M304 (or M303 E-1)
+#[cfg(feature = "with-hotbed")]
+#[embassy_executor::task(pool_size=1)]
+pub async fn hotbed_temp_task(
+    event_bus: EventBusRef,
+    hotbed_controller: hwa::controllers::HotbedControllerRef
+) -> ! {
+    hwa::info!("D; hotbed temperature_task started");
+    heater_loop(event_bus, hotbed_controller, EventFlags::HOTBED_TEMP_OK).await
+}
 
+/// Drives the heater towards its target temperature and publishes `temp_ok` while it is maintained
+async fn heater_loop<AdcPeri, AdcPin, PwmHwaDevice>(
+    event_bus: EventBusRef,
+    heater_controller: ControllerRef<HeaterController<AdcPeri, AdcPin, PwmHwaDevice>>,
+    temp_ok: EventFlags,
+) -> !
+    where
+        AdcPeri: AdcTrait + 'static,
+        AdcPin: AdcPinTrait<AdcPeri>,
+        PwmHwaDevice: embedded_hal::Pwm<Duty = u16> + 'static,
+        <PwmHwaDevice as embedded_hal::Pwm>::Channel: Copy
+{
     let mut pid: pid::Pid<f32> = pid::Pid::new(0.0f32, 100.0f32);
-    pid.p(5.0f32, 100.0f32);
-    pid.i(0.01f32, 100.0f32);
-    pid.d(1.5f32, 100.0f32);
+    // Gains set by M301/M304 or M303 are applied on the next cycle
+    let mut gains: Option<hwa::controllers::PidGains> = None;
 
-    let mut ticker = Ticker::every(Duration::from_secs(2));
+    let mut ticker = Ticker::every(Duration::from_secs(PERIOD_SECS));
     let mut _t0 = embassy_time::Instant::now();
 
     let mut current_temp = 0f32;
     let mut last_temp = current_temp;
 
-    hotend_controller.lock().await.init().await;
+    heater_controller.lock().await.init().await;
 
     let mut state = State::Dutty;
     loop {
         ticker.next().await;
         let new_state = {
-            let mut m = hotend_controller.lock().await;
+            let mut m = heater_controller.lock().await;
+            if m.is_autotuning() {
+                // M303 reads the sensor and drives the heater meanwhile
+                continue;
+            }
+
+            let new_gains = m.get_pid_gains();
+            if gains != Some(new_gains) {
+                // Per control period, as the pid crate works on samples
+                pid.p(new_gains.kp, 100.0f32);
+                pid.i(new_gains.ki * PERIOD_SECS as f32, 100.0f32);
+                pid.d(new_gains.kd / PERIOD_SECS as f32, 100.0f32);
+                gains = Some(new_gains);
+            }
 
             current_temp = m.read_temp().await;
             #[cfg(feature = "native")]
-            if t0.elapsed().as_secs() > 5 {
+            if _t0.elapsed().as_secs() > 5 {
                 current_temp = (m.get_target_temp()) as f32;
             }
 
             hwa::trace!("MEASURED_TEMP: {}", current_temp);
+            m.set_current_temp(current_temp);
 
-            if m.is_on() {
+            if current_temp.is_nan() {
+                // Faulty sensor: keep the heater off, out of the PID
+                m.set_power(0.0f32).await;
+                State::Dutty
+            }
+            else if m.get_target_temp() > 0.0f32 {
                 let target_temp = m.get_target_temp() as f32;
                 pid.setpoint(target_temp);
 
                 last_temp = current_temp;
 
                 let delta = pid.next_control_output(current_temp).output;
-                m.set_current_temp(current_temp);
                 let power = if delta > 0.0f32 {
                     if delta < 100.0f32 {
                         delta / 100.0f32
@@ -153,14 +202,14 @@ pub async fn temp_task(
             hwa::trace!("Temp changed to {:?}", new_state);
             match new_state {
                 State::Dutty => {
-                    event_bus.publish_event(EventStatus::not_containing(EventFlags::HOTEND_TEMP_OK)).await;
+                    event_bus.publish_event(EventStatus::not_containing(temp_ok)).await;
                 }
                 State::Maintaining => {
-                    event_bus.publish_event(EventStatus::containing(EventFlags::HOTEND_TEMP_OK)).await;
+                    event_bus.publish_event(EventStatus::containing(temp_ok)).await;
                 }
                 State::Targeting => {
                     _t0 = embassy_time::Instant::now();
-                    event_bus.publish_event(EventStatus::not_containing(EventFlags::HOTEND_TEMP_OK)).await;
+                    event_bus.publish_event(EventStatus::not_containing(temp_ok)).await;
                 }
             }
             state = new_state;
@@ -25,5 +25,7 @@ pub enum CodeExecutionFailure {
     NumericalError,
     /// The GCode is considered, but not yet implemented
     NotYetImplemented,
+    /// Cannot perform a feed move while the spindle is stopped
+    SpindleStopped,
 }
 pub type CodeExecutionResult = Result<CodeExecutionSuccess, CodeExecutionFailure>;
@@ -4,11 +4,15 @@ use printhor_hwa_common::EventBusRef;
 use crate::display::ui::MainUI;
 
 #[embassy_executor::task(pool_size=1)]
-pub async fn display_task(display_dev: hwa::display::DisplayDevice, event_bus: EventBusRef) -> ! {
+pub async fn display_task(display_dev: hwa::display::DisplayDevice, event_bus: EventBusRef,
+                          #[cfg(feature = "with-motion")] motion_planner: hwa::controllers::MotionPlannerRef,
+                          #[cfg(feature = "with-printjob")] printer_controller: hwa::controllers::printer_controller::PrinterController) -> ! {
 
     hwa::info!("Display task started");
     let t0 = embassy_time::Instant::now();
-    let main_ui = MainUI::new(event_bus.clone()).await;
+    let main_ui = MainUI::new(event_bus.clone(),
+                              #[cfg(feature = "with-motion")] motion_planner,
+                              #[cfg(feature = "with-printjob")] printer_controller).await;
     let mut tft = hwa::device::DisplayScreen::new(display_dev, main_ui).await;
     hwa::info!("D; display init done in {} ms ", t0.elapsed().as_millis());
 
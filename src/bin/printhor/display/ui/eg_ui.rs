@@ -16,10 +16,16 @@ pub struct EmbeddedGraphicsUI {
     event_bus: &'static EventBusRef,
     #[allow(unused)]
     subscriber: &'static mut EventBusSubscriber<'static>,
+    #[cfg(feature = "with-motion")]
+    motion_planner: crate::hwa::controllers::MotionPlannerRef,
+    #[cfg(feature = "with-printjob")]
+    printer_controller: crate::hwa::controllers::printer_controller::PrinterController,
 }
 
 impl EmbeddedGraphicsUI {
-    pub async fn new(event_bus: EventBusRef) -> Self {
+    pub async fn new(event_bus: EventBusRef,
+                     #[cfg(feature = "with-motion")] motion_planner: crate::hwa::controllers::MotionPlannerRef,
+                     #[cfg(feature = "with-printjob")] printer_controller: crate::hwa::controllers::printer_controller::PrinterController) -> Self {
         static EVENT_BUS: TrackedStaticCell<EventBusRef> = TrackedStaticCell::new();
         let bus = EVENT_BUS.init("UIEventBusRef", event_bus);
         static UI_SUBSCRIBER: TrackedStaticCell<EventBusSubscriber<'static>> = TrackedStaticCell::new();
@@ -27,6 +33,10 @@ impl EmbeddedGraphicsUI {
         Self {
             event_bus: bus,
             subscriber,
+            #[cfg(feature = "with-motion")]
+            motion_planner,
+            #[cfg(feature = "with-printjob")]
+            printer_controller,
         }
     }
 }
@@ -46,6 +56,9 @@ impl DisplayScreenUI for EmbeddedGraphicsUI {
         else if current_status.contains(EventFlags::HOMMING) {
             "HOMMING"
         }
+        else if current_status.contains(EventFlags::JOB_PAUSED) {
+            "PAUSED"
+        }
         else if current_status.contains(EventFlags::SYS_READY) {
             "READY"
         }
@@ -55,7 +68,29 @@ impl DisplayScreenUI for EmbeddedGraphicsUI {
         let _t0 = embassy_time::Instant::now();
         //raw_display.retain().await;
         let _ = Text::new(state, Point::new(20, 30), style).draw(raw_display);
+        #[cfg(feature = "with-motion")]
+        {
+            let rates = alloc::format!("FR {}% FLOW {}%",
+                self.motion_planner.get_speed_rate().await,
+                self.motion_planner.get_flow_rate().await,
+            );
+            let _ = Text::new(rates.as_str(), Point::new(20, 45), style).draw(raw_display);
+        }
         //raw_display.release().await;
         //crate::info!("text upd in {} ms", _t0.elapsed().as_millis());
     }
+
+    /// Pauses the running print job (parking the head) or resumes the paused one
+    async fn pause_resume(&mut self) {
+        #[cfg(feature = "with-printjob")]
+        {
+            let current_status = self.subscriber.get_status().await;
+            if current_status.contains(EventFlags::JOB_PAUSED) {
+                self.printer_controller.resume();
+            }
+            else if current_status.contains(EventFlags::JOB_PRINTING) {
+                self.printer_controller.pause();
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -7,6 +7,32 @@ pub(crate) fn to_fixed(val: (i32, u8)) -> Real {
     Real::new(val.0.into(), val.1 as u32)
 }
 
+/// Standard base64 (padding optional). None on invalid input
+#[cfg(feature = "with-laser")]
+pub(crate) fn decode_base64(s: &str) -> Option<alloc::vec::Vec<u8>> {
+    let mut out = alloc::vec::Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0u8;
+    for c in s.bytes().take_while(|c| *c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
 
 pub struct RingBuffer<'d, T, const N: usize> {
     pub buf: &'d mut [T],
@@ -2,6 +2,10 @@ use crate::hwa;
 use printhor_hwa_common::ControllerRef;
 use crate::hwa::controllers::pwm_controller::PwmController;
 use crate::hwa::VREF_SAMPLE;
+use crate::hwa::controllers::thermistor::{TempFilter, ThermistorConfig, VREFINT_MV};
+use crate::hwa::controllers::pid_autotune::{AutotuneStep, PidGains, RelayAutotune};
+use crate::ctrl::CodeExecutionFailure;
+use embassy_time::{Duration, Instant, Ticker};
 
 use crate::hwa::devices::AdcImpl;
 use crate::hwa::devices::AdcPinTrait;
@@ -21,6 +25,14 @@ pub struct HeaterController<AdcPeri, AdcPin, PwmHwaDevice>
     adc: ControllerRef<AdcImpl<AdcPeri>>,
     adc_pin: AdcPin,
     vref_sample: u16,
+    thermistor: ThermistorConfig,
+    filter: TempFilter,
+    pid_gains: PidGains,
+    autotuning: bool,
+    /// ºC. Zero when off
+    target_temp: f32,
+    /// Last reading of the temperature task, in ºC
+    current_temp: f32,
     pwm: PwmController<PwmHwaDevice>,
 }
 
@@ -29,7 +41,7 @@ impl<AdcPeri, AdcPin, PwmHwaDevice> HeaterController<AdcPeri, AdcPin, PwmHwaDevi
 where
     AdcPeri: AdcTrait + 'static,
     AdcPin: AdcPinTrait<AdcPeri>,
-    PwmHwaDevice: embedded_hal::Pwm + 'static,
+    PwmHwaDevice: embedded_hal::Pwm<Duty = u16> + 'static,
     <PwmHwaDevice as embedded_hal::Pwm>::Channel: Copy
 {
     pub fn new(adc: AdcControllerRef<AdcPeri>, adc_pin: AdcPin, pwm: PwmController<PwmHwaDevice>) -> Self {
@@ -37,6 +49,12 @@ where
             adc,
             adc_pin,
             vref_sample: VREF_SAMPLE,
+            thermistor: ThermistorConfig::new(),
+            filter: TempFilter::new(),
+            pid_gains: PidGains::new(),
+            autotuning: false,
+            target_temp: 0.0f32,
+            current_temp: 0.0f32,
             pwm,
         }
     }
@@ -53,24 +71,102 @@ where
         hwa::info!("ADC Initiallized: vref_sample = {}", self.vref_sample);
     }
 
+    pub fn get_thermistor(&self) -> ThermistorConfig {
+        self.thermistor
+    }
+
+    pub fn set_thermistor(&mut self, thermistor: ThermistorConfig) {
+        self.thermistor = thermistor;
+        self.filter = TempFilter::new();
+    }
+
+    /// Oversampled and filtered temperature, in ºC. NaN when the sensor is open or shorted
     pub async fn read_temp(&mut self) -> f32 {
-        let mut bus  = self.adc.lock().await;
-        bus.read(&mut self.adc_pin);
-        0.0f32
+        let samples = self.thermistor.oversampling.max(1) as u32;
+        let mut sum = 0u32;
+        {
+            let mut bus  = self.adc.lock().await;
+            for _ in 0..samples {
+                sum += bus.read(&mut self.adc_pin) as u32;
+            }
+        }
+        // Calibrated with the reading of the internal reference
+        let v_adc_mv = (sum as f32 / samples as f32) * VREFINT_MV / self.vref_sample as f32;
+        let temp = match self.thermistor.resistance(v_adc_mv) {
+            Some(r) => self.thermistor.model.to_celsius(r),
+            None => {
+                hwa::warn!("Thermistor open or shorted: {} mV", v_adc_mv);
+                f32::NAN
+            }
+        };
+        self.filter.update(temp, self.thermistor.filter_alpha)
     }
-    pub fn get_target_temp(&self) -> f32 {
-        0.0f32
+    pub fn get_pid_gains(&self) -> PidGains {
+        self.pid_gains
+    }
+
+    /// Picked up by the temperature task on its next cycle
+    pub fn set_pid_gains(&mut self, gains: PidGains) {
+        self.pid_gains = gains;
     }
 
-    pub async fn set_target_temp(&mut self, _target_temp: f32) {
+    /// Set while M303 drives the heater, so the temperature task leaves it alone
+    #[inline]
+    pub fn is_autotuning(&self) -> bool {
+        self.autotuning
+    }
+
+    /***
+    M303: Relay feedback autotune around the target temperature. The controller is only locked while each step is
+    taken, so M105 and the display keep working. The heater is off when done
+     */
+    pub async fn autotune(heater: &ControllerRef<Self>, target: f32, cycles: u8) -> Result<PidGains, CodeExecutionFailure>
+        where Self: 'static
+    {
+        let mut tuner = RelayAutotune::new(target, cycles);
+        heater.lock().await.autotuning = true;
+        let t0 = Instant::now();
+        let mut ticker = Ticker::every(Duration::from_millis(250));
+        let result = loop {
+            {
+                let mut h = heater.lock().await;
+                let temp = h.read_temp().await;
+                h.set_current_temp(temp);
+                match tuner.update(temp, t0.elapsed().as_millis()) {
+                    AutotuneStep::Power(power) => h.set_power(power).await,
+                    AutotuneStep::Done(gains) => break Ok(gains),
+                    AutotuneStep::Failed => {
+                        hwa::warn!("PID autotune failed at {}", temp);
+                        break Err(CodeExecutionFailure::ERR);
+                    }
+                }
+            }
+            ticker.next().await;
+        };
+        let mut h = heater.lock().await;
+        h.set_power(0.0f32).await;
+        h.autotuning = false;
+        result
+    }
 
+    pub fn get_target_temp(&self) -> f32 {
+        self.target_temp
+    }
+
+    /// Picked up by the temperature task on its next cycle. Zero turns the heater off
+    pub async fn set_target_temp(&mut self, target_temp: f32) {
+        self.target_temp = target_temp.max(0.0f32);
+        if self.target_temp == 0.0f32 {
+            self.set_power(0.0f32).await;
+        }
     }
 
-    pub fn set_current_temp(&mut self, _target_temp: f32) {
+    pub fn set_current_temp(&mut self, current_temp: f32) {
+        self.current_temp = current_temp;
     }
 
     pub fn get_current_temp(&mut self) -> f32 {
-        0.0f32
+        self.current_temp
     }
 
     pub async fn set_power(&mut self, _power: f32) {
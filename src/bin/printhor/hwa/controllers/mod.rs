@@ -15,9 +15,18 @@ mod servo_controller;
 #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
 mod heater_controller;
 
-#[cfg(any(feature = "with-hotend", feature = "with-hotbed", feature = "with-fan0", feature = "with-fan1"))]
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+mod thermistor;
+
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+mod pid_autotune;
+
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed", feature = "with-fan0", feature = "with-fan1", feature = "with-laser", feature = "with-spindle"))]
 mod pwm_controller;
 
+#[cfg(feature = "with-spindle")]
+mod spindle_controller;
+
 // Use
 
 #[cfg(feature = "with-trinamic")]
@@ -38,6 +47,12 @@ pub use servo_controller::ProbeTrait;
 #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
 pub use heater_controller::HeaterController;
 
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+pub use thermistor::{ThermistorConfig, ThermistorModel};
+
+#[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+pub use pid_autotune::PidGains;
+
 ////
 
 #[cfg(any(feature = "with-hotend"))]
@@ -76,5 +91,14 @@ pub type Fan1PwmControllerRef = printhor_hwa_common::ControllerRef<Fan1PwmContro
 #[cfg(any(feature = "with-laser"))]
 pub type LaserPwmController = pwm_controller::PwmController<crate::hwa::devices::PwmLaser>;
 
-#[cfg(any(feature = "with-fan1"))]
-pub type LaserPwmControllerRef = printhor_hwa_common::ControllerRef<LaserPwmController>;
\ No newline at end of file
+#[cfg(any(feature = "with-laser"))]
+pub type LaserPwmControllerRef = printhor_hwa_common::ControllerRef<LaserPwmController>;
+
+#[cfg(feature = "with-spindle")]
+pub type SpindlePwmController = pwm_controller::PwmController<crate::hwa::devices::PwmSpindle>;
+
+#[cfg(feature = "with-spindle")]
+pub use spindle_controller::{SpindleController, SpindleConfig};
+
+#[cfg(feature = "with-spindle")]
+pub type SpindleControllerRef = printhor_hwa_common::ControllerRef<SpindleController>;
\ No newline at end of file
@@ -0,0 +1,153 @@
+//! Canned drilling cycles (G81/G82/G83), modal until G80
+use alloc::vec::Vec;
+use crate::math::Real;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CycleKind {
+    /// G81
+    Drill,
+    /// G82: Dwell at the bottom
+    DrillDwell,
+    /// G83: Peck drilling, full retract to R after each peck
+    Peck,
+}
+
+#[derive(Clone, Copy)]
+pub struct CannedCycle {
+    pub(crate) kind: CycleKind,
+    /// Bottom of the hole, in work coordinates
+    pub(crate) z: Real,
+    /// Retract plane, in work coordinates
+    pub(crate) r: Real,
+    /// Feedrate of the drilling moves. When None, default travel speed is used
+    pub(crate) feed: Option<Real>,
+    /// Dwell at the bottom in milliseconds (G82)
+    pub(crate) dwell_ms: u32,
+    /// Depth of each peck (G83)
+    pub(crate) peck: Real,
+    /// Z (work coordinates) when the cycle was started. The G98 return plane
+    pub(crate) initial_z: Real,
+}
+
+/// The moves drilling one hole. Z is in work coordinates
+#[derive(Clone, Copy, PartialEq)]
+pub enum CycleMove {
+    /// Rapid to the XY of the hole
+    RapidXY,
+    RapidZ(Real),
+    /// Feed move along Z at the cycle feedrate
+    FeedZ(Real),
+    /// Dwell in milliseconds
+    Dwell(u32),
+}
+
+impl CannedCycle {
+    /***
+    One hole starting at `current_z`: XY at a safe height, rapid to R, drill (with dwell or pecks) and
+    retract to `return_z`
+     */
+    pub fn hole_moves(&self, current_z: Real, return_z: Real) -> Vec<CycleMove> {
+        let mut moves = Vec::new();
+        // Never travel in XY below the R plane
+        if current_z < self.r {
+            moves.push(CycleMove::RapidZ(self.r));
+        }
+        moves.push(CycleMove::RapidXY);
+        moves.push(CycleMove::RapidZ(self.r));
+        match self.kind {
+            CycleKind::Peck => {
+                // Rapid back down stops this far above the previous peck
+                let clearance = Real::from_lit(25, 2);
+                let mut depth = self.r;
+                while depth > self.z {
+                    if depth < self.r {
+                        moves.push(CycleMove::RapidZ(depth + clearance));
+                    }
+                    depth = core::cmp::max(depth - self.peck, self.z);
+                    moves.push(CycleMove::FeedZ(depth));
+                    if depth > self.z {
+                        moves.push(CycleMove::RapidZ(self.r));
+                    }
+                }
+            }
+            CycleKind::Drill | CycleKind::DrillDwell => {
+                moves.push(CycleMove::FeedZ(self.z));
+                if self.kind == CycleKind::DrillDwell && self.dwell_ms > 0 {
+                    moves.push(CycleMove::Dwell(self.dwell_ms));
+                }
+            }
+        }
+        moves.push(CycleMove::RapidZ(return_z));
+        moves
+    }
+}
+
+/// Where the tool goes after each hole
+#[derive(Clone, Copy, PartialEq)]
+pub enum CycleReturn {
+    /// G98: Back to the Z the cycle started at
+    InitialZ,
+    /// G99: Back to the R plane
+    RPlane,
+}
+
+#[cfg(test)]
+fn test_cycle(kind: CycleKind) -> CannedCycle {
+    CannedCycle {
+        kind,
+        z: Real::from_lit(-5, 0),
+        r: Real::from_lit(2, 0),
+        feed: None,
+        dwell_ms: 500,
+        peck: Real::from_lit(25, 1),
+        initial_z: Real::from_lit(10, 0),
+    }
+}
+
+#[test]
+pub fn g81_hole_test() {
+    let moves = test_cycle(CycleKind::Drill).hole_moves(Real::from_lit(10, 0), Real::from_lit(10, 0));
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+        CycleMove::FeedZ(Real::from_lit(-5, 0)),
+        CycleMove::RapidZ(Real::from_lit(10, 0)),
+    ]);
+    // Below R, it goes up before moving in XY
+    let moves = test_cycle(CycleKind::Drill).hole_moves(Real::from_lit(-1, 0), Real::from_lit(2, 0));
+    assert!(moves[0] == CycleMove::RapidZ(Real::from_lit(2, 0)));
+    assert!(moves[1] == CycleMove::RapidXY);
+}
+
+#[test]
+pub fn g82_hole_test() {
+    let moves = test_cycle(CycleKind::DrillDwell).hole_moves(Real::from_lit(10, 0), Real::from_lit(2, 0));
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+        CycleMove::FeedZ(Real::from_lit(-5, 0)),
+        CycleMove::Dwell(500),
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+    ]);
+    // No dwell without P
+    let mut cycle = test_cycle(CycleKind::DrillDwell);
+    cycle.dwell_ms = 0;
+    assert!(!cycle.hole_moves(Real::from_lit(10, 0), Real::from_lit(2, 0)).contains(&CycleMove::Dwell(0)));
+}
+
+#[test]
+pub fn g83_hole_test() {
+    let moves = test_cycle(CycleKind::Peck).hole_moves(Real::from_lit(10, 0), Real::from_lit(10, 0));
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+        CycleMove::FeedZ(Real::from_lit(-5, 1)),
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+        CycleMove::RapidZ(Real::from_lit(-25, 2)),
+        CycleMove::FeedZ(Real::from_lit(-3, 0)),
+        CycleMove::RapidZ(Real::from_lit(2, 0)),
+        CycleMove::RapidZ(Real::from_lit(-275, 2)),
+        CycleMove::FeedZ(Real::from_lit(-5, 0)),
+        CycleMove::RapidZ(Real::from_lit(10, 0)),
+    ]);
+}
@@ -1,5 +1,21 @@
+pub(in crate::hwa) mod canned_cycle;
 pub(in crate::hwa) mod motion_controller;
 pub(in crate::hwa) mod motion_segment;
+#[cfg(feature = "with-pen-plotter")]
+pub(in crate::hwa) mod pen_plotter;
+#[cfg(feature = "with-laser")]
+pub(in crate::hwa) mod raster;
+pub(in crate::hwa) mod tool_length;
+pub(in crate::hwa) mod tool_table;
+pub(in crate::hwa) mod work_coords;
 
+pub use canned_cycle::*;
 pub use motion_controller::*;
-pub use motion_segment::*;
\ No newline at end of file
+pub use motion_segment::*;
+#[cfg(feature = "with-pen-plotter")]
+pub use pen_plotter::*;
+#[cfg(feature = "with-laser")]
+pub use raster::*;
+pub use tool_length::*;
+pub use tool_table::*;
+pub use work_coords::*;
\ No newline at end of file
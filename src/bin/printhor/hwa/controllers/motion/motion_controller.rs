@@ -1,18 +1,33 @@
 //! TODO: This feature is still very experimental
 use crate::hwa;
+#[cfg(feature = "with-laser")]
+use alloc::vec::Vec;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
 use printhor_hwa_common::{EventBusRef, EventFlags, EventStatus};
-use crate::control::GCode;
+use crate::control::{FPQRXYZ, GCode};
+#[cfg(feature = "with-laser")]
+use crate::control::DFSXY;
 use crate::planner::{Constraints, SCurveMotionProfile};
-use crate::math::{ONE_HUNDRED, Real, ZERO};
+use crate::math::{ONE_HUNDRED, ONE_THOUSAND, Real, ZERO};
 use crate::sync::config::Config;
 use crate::tgeo::TVector;
 use crate::tgeo::CoordSel;
+use micromath::F32;
 
 use crate::ctrl::*;
-use crate::hwa::controllers::motion::motion_segment::{Segment, SegmentData};
+use crate::hwa::controllers::motion::motion_segment::{ProbeMode, Segment, SegmentData};
+#[cfg(feature = "with-laser")]
+use crate::hwa::controllers::motion::motion_segment::LaserPower;
+use crate::hwa::controllers::motion::tool_table::{ToolChangeConfig, ToolConfig, ToolTable};
+use crate::hwa::controllers::motion::work_coords::{WCS_COUNT, WorkCoords};
+use crate::hwa::controllers::motion::canned_cycle::{CannedCycle, CycleKind, CycleMove, CycleReturn};
+use crate::hwa::controllers::motion::tool_length::{MAX_TOOL_LENGTHS, ManualToolChangeConfig, ToolLengths};
+#[cfg(feature = "with-laser")]
+use crate::hwa::controllers::motion::raster::{RasterLines, RasterScan};
+#[cfg(feature = "with-pen-plotter")]
+use crate::hwa::controllers::motion::pen_plotter::PenConfig;
 
 /// The maximum number of movements that can be queued. Warning! each one takes too memory as of now
 const SEGMENT_QUEUE_SIZE: u8 = 4;
@@ -35,7 +50,170 @@ pub enum DeferEvent {
 pub enum ScheduledMove {
     Move(SegmentData, SCurveMotionProfile),
     Homing,
-    Dwell,
+    /// Dwell for the given milliseconds. None just synchronizes with the queue
+    Dwell(Option<u32>),
+    /// Lower (true) or lift the pen
+    #[cfg(feature = "with-pen-plotter")]
+    Pen(bool),
+}
+
+/// What a planned move does with the laser
+#[derive(Clone, Copy, PartialEq)]
+enum Cut {
+    /// Non-cutting move (laser off)
+    Travel,
+    /// Cutting move, with the current laser power
+    Feed,
+    /// Raster scanline (G7)
+    #[cfg(feature = "with-laser")]
+    Raster(RasterScan),
+}
+
+/// What the stepper task has to execute next
+pub enum ExecPlan {
+    Segment(Segment),
+    Homing,
+    /// Dwell for the given milliseconds
+    Dwell(u32),
+    /// Lower (true) or lift the pen
+    #[cfg(feature = "with-pen-plotter")]
+    Pen(bool),
+}
+
+/// Firmware retraction settings (M207/M208/M209)
+#[allow(unused)]
+#[derive(Clone, Copy)]
+pub struct RetractConfig {
+    /// Filament length pulled back on retract
+    pub(crate) length: Real,
+    /// Retract feedrate. When None, default travel speed is used
+    pub(crate) speed: Option<Real>,
+    /// Z lift applied together with the retract
+    pub(crate) z_hop: Real,
+    /// Extra filament length pushed on recover
+    pub(crate) recover_extra_length: Real,
+    /// Recover feedrate. When None, default travel speed is used
+    pub(crate) recover_speed: Option<Real>,
+    /// When set, E-only moves are treated as retract/recover
+    pub(crate) auto_retract: bool,
+}
+
+impl RetractConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            length: Real::zero(),
+            speed: None,
+            z_hop: Real::zero(),
+            recover_extra_length: Real::zero(),
+            recover_speed: None,
+            auto_retract: false,
+        }
+    }
+}
+
+/// Parking and filament change settings (M125/M600)
+#[allow(unused)]
+#[derive(Clone, Copy)]
+pub struct ParkConfig {
+    /// XY park position. Unset coords are left untouched
+    pub(crate) position: TVector<Real>,
+    /// Z lift applied before moving to the park position
+    pub(crate) z_lift: Real,
+    /// Filament length pulled out on M600
+    pub(crate) unload_length: Real,
+    /// Filament length pushed back on resume after M600
+    pub(crate) load_length: Real,
+    /// Extra filament extruded after load to purge the old color
+    pub(crate) purge_length: Real,
+    /// Feedrate for unload/load/purge. When None, default travel speed is used
+    pub(crate) change_speed: Option<Real>,
+}
+
+impl ParkConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            position: TVector::new(),
+            z_lift: Real::zero(),
+            unload_length: Real::zero(),
+            load_length: Real::zero(),
+            purge_length: Real::zero(),
+            change_speed: None,
+        }
+    }
+}
+
+/// Modal state set by G-codes and applied to the following moves
+#[derive(Clone, Copy)]
+pub struct ModalState {
+    /// Last F given to G1. Used by the G1 moves without F
+    pub(crate) feedrate: Option<Real>,
+    /// Relative positioning (G91). Absolute (G90) by default
+    pub(crate) relative: bool,
+    /// Relative extrusion (M83, the default). E is absolute after M82
+    pub(crate) relative_e: bool,
+    /// Logical E position with absolute extrusion (set by G92 E)
+    pub(crate) e_pos: Real,
+    /// Inverse time feed mode (G93): F is the inverse of the time to complete the move, in minutes
+    pub(crate) inverse_time_feed: bool,
+}
+
+impl ModalState {
+    pub const fn new() -> Self {
+        Self {
+            feedrate: None,
+            relative: false,
+            relative_e: true,
+            e_pos: Real::zero(),
+            inverse_time_feed: false,
+        }
+    }
+}
+
+/// The state saved when parking, restored on resume
+#[derive(Clone, Copy)]
+pub struct ParkedState {
+    pub(crate) pos: TVector<Real>,
+    /// Work coordinates (WCS, G92 and tool offsets) when parking
+    pub(crate) work_coords: WorkCoords,
+    /// Selected tool when parking
+    pub(crate) tool: Option<u8>,
+    /// Feedrate and positioning modes when parking
+    pub(crate) modal: ModalState,
+    /// Retraction status before parking
+    pub(crate) retracted: Option<Real>,
+    /// Whether filament was unloaded (M600)
+    pub(crate) unloaded: bool,
+}
+
+impl ParkedState {
+    /// Work coordinates to resume with. When the tool was changed while parked, its offsets are kept
+    pub(crate) fn resume_coords(&self, current: &WorkCoords, current_tool: Option<u8>) -> WorkCoords {
+        let mut work_coords = self.work_coords;
+        if current_tool != self.tool {
+            work_coords.tool = current.tool;
+            work_coords.tool_length = current.tool_length;
+        }
+        work_coords
+    }
+
+    /// Machine position to return to with the given work offset. The workpiece position is kept, so a tool
+    /// change while parked returns the new nozzle where the old one was
+    pub(crate) fn return_pos(&self, work_offset: &TVector<Real>) -> TVector<Real> {
+        let mut pos = self.pos;
+        pos.assign_if_set(CoordSel::XYZ, &(self.pos - self.work_coords.offset() + *work_offset));
+        pos
+    }
+}
+
+/// Laser mode (M3/M4/M5)
+#[cfg(feature = "with-laser")]
+#[derive(Clone, Copy, PartialEq)]
+pub enum LaserMode {
+    Off,
+    /// Constant power (M3)
+    Constant,
+    /// Power scaled to the instantaneous velocity (M4)
+    Dynamic,
 }
 
 /////
@@ -48,6 +226,27 @@ pub struct MotionConfig {
     pub(crate) default_travel_speed: u16,
     pub(crate) flow_rate: u8,
     pub(crate) speed_rate: u8,
+    /// Seconds between auto-reports of the speed and flow rates (M155). 0 = off
+    pub(crate) auto_report_secs: u8,
+    /// Microsteps per unit (mm), same for every axis so far
+    pub(crate) usteps_per_unit: u16,
+    /// Seconds without motion after which the steppers are powered off. 0 = never
+    pub(crate) stepper_idle_timeout: u32,
+    /// Axes kept enabled on idle timeout (e.g. Z on a heavy gantry)
+    pub(crate) idle_hold_axes: CoordSel,
+    pub(crate) retract: RetractConfig,
+    pub(crate) park: ParkConfig,
+    pub(crate) tools: ToolTable,
+    /// M6 settings
+    pub(crate) manual_tool_change: ManualToolChangeConfig,
+    /// S value giving full laser power
+    #[cfg(feature = "with-laser")]
+    pub(crate) laser_s_max: u16,
+    /// Distance run with the laser off before and after a raster scanline (G7), to reach the cruise speed
+    #[cfg(feature = "with-laser")]
+    pub(crate) raster_overscan: u16,
+    #[cfg(feature = "with-pen-plotter")]
+    pub(crate) pen: PenConfig,
 }
 
 impl MotionConfig {
@@ -60,6 +259,20 @@ impl MotionConfig {
             default_travel_speed: 1,
             flow_rate: 100,
             speed_rate: 100,
+            auto_report_secs: 0,
+            usteps_per_unit: 16 * 8,
+            stepper_idle_timeout: 10,
+            idle_hold_axes: CoordSel::empty(),
+            retract: RetractConfig::new(),
+            park: ParkConfig::new(),
+            tools: ToolTable::new(),
+            manual_tool_change: ManualToolChangeConfig::new(),
+            #[cfg(feature = "with-laser")]
+            laser_s_max: 255,
+            #[cfg(feature = "with-laser")]
+            raster_overscan: 5,
+            #[cfg(feature = "with-pen-plotter")]
+            pen: PenConfig::new(),
         }
     }
 }
@@ -67,12 +280,74 @@ impl MotionConfig {
 #[allow(unused)]
 pub struct MotionStatus {
     pub(crate) last_planned_pos: Option<TVector<Real>>,
+    /// Z lift applied by the last retract. None when not retracted
+    pub(crate) retracted: Option<Real>,
+    /// Saved state while parked (M125/M600). None when not parked
+    pub(crate) parked: Option<ParkedState>,
+    /// Signed microsteps emitted by the stepper task since last homing
+    pub(crate) step_count: TVector<i32>,
+    /// Selected tool. None when no tool is selected
+    pub(crate) current_tool: Option<u8>,
+    /// Axes whose driver is powered on
+    pub(crate) enabled_axes: CoordSel,
+    /// Outcome of the last probe move: where it stopped and whether the probe changed
+    pub(crate) probe_result: Option<(TVector<Real>, bool)>,
+    /// Work coordinate systems and G92 offset
+    pub(crate) work_coords: WorkCoords,
+    /// Feedrate, positioning and feed rate modes
+    pub(crate) modal: ModalState,
+    /// Active drilling cycle (G81/G82/G83). None after G80
+    pub(crate) canned_cycle: Option<CannedCycle>,
+    /// G98/G99
+    pub(crate) cycle_return: CycleReturn,
+    /// Measured tool lengths and M6 state
+    pub(crate) tool_lengths: ToolLengths,
+    /// The last planned move is a jog ($J=)
+    #[cfg(feature = "with-grbl-protocol")]
+    pub(crate) jogging: bool,
+    /// Cruise speed of the segment being executed
+    #[cfg(feature = "with-grbl-protocol")]
+    pub(crate) realtime_speed: Real,
+    #[cfg(feature = "with-laser")]
+    pub(crate) laser_mode: LaserMode,
+    /// Last S given to M3/M4 or inline in G1
+    #[cfg(feature = "with-laser")]
+    pub(crate) laser_s: Real,
+    /// Pixels of the queued raster scanlines (G7)
+    #[cfg(feature = "with-laser")]
+    pub(crate) raster_lines: RasterLines,
+    /// Pen state after the planned moves. None until the first pen move
+    #[cfg(feature = "with-pen-plotter")]
+    pub(crate) pen_down: Option<bool>,
 }
 
 impl MotionStatus {
     pub const fn new() -> Self {
         Self {
             last_planned_pos: None,
+            retracted: None,
+            parked: None,
+            step_count: TVector::from_coords(Some(0), Some(0), Some(0), Some(0)),
+            current_tool: None,
+            enabled_axes: CoordSel::empty(),
+            probe_result: None,
+            work_coords: WorkCoords::new(),
+            modal: ModalState::new(),
+            canned_cycle: None,
+            cycle_return: CycleReturn::InitialZ,
+            tool_lengths: ToolLengths::new(),
+            #[cfg(feature = "with-grbl-protocol")]
+            jogging: false,
+            #[cfg(feature = "with-grbl-protocol")]
+            realtime_speed: Real::zero(),
+            #[cfg(feature = "with-laser")]
+            laser_mode: LaserMode::Off,
+            #[cfg(feature = "with-laser")]
+            laser_s: Real::zero(),
+            #[cfg(feature = "with-laser")]
+            raster_lines: RasterLines::new(),
+            #[cfg(feature = "with-pen-plotter")]
+            pen_down: None,
         }
     }
 }
@@ -85,6 +360,14 @@ pub struct MotionPlanner {
     pub(self) ringbuffer: Mutex<CriticalSectionRawMutex, RingBuffer>,
     pub(self) move_planned: Config<CriticalSectionRawMutex, bool>,
     pub(self) available: Config<CriticalSectionRawMutex, bool>,
+    /// Set by M410 to make the stepper task abort the executing segment
+    pub(self) quick_stop_requested: Config<CriticalSectionRawMutex, bool>,
+    /// Set by the stepper task once the executing segment has been aborted
+    pub(self) quick_stop_done: Config<CriticalSectionRawMutex, bool>,
+    /// Set by a feed hold. The stepper task ramps the executing segment down to a stop and waits for the cycle start
+    pub(self) feed_hold: Config<CriticalSectionRawMutex, bool>,
+    /// Set by the cycle start to release the stepper task
+    pub(self) cycle_start: Config<CriticalSectionRawMutex, bool>,
     pub(self) motion_cfg: Mutex<CriticalSectionRawMutex, MotionConfig>,
     pub(self) motion_st: Mutex<CriticalSectionRawMutex, MotionStatus>,
     pub motion_driver: Mutex<CriticalSectionRawMutex, hwa::drivers::MotionDriver>,
@@ -100,6 +383,10 @@ impl MotionPlanner {
             ringbuffer: Mutex::new(RingBuffer::new()),
             move_planned: Config::new(),
             available: Config::new(),
+            quick_stop_requested: Config::new(),
+            quick_stop_done: Config::new(),
+            feed_hold: Config::new(),
+            cycle_start: Config::new(),
             motion_cfg: Mutex::new(MotionConfig::new()),
             motion_st: Mutex::new(MotionStatus::new()),
             motion_driver: Mutex::new(motion_driver),
@@ -112,7 +399,7 @@ impl MotionPlanner {
         self.event_bus.publish_event(EventStatus::containing(EventFlags::MOV_QUEUE_EMPTY)).await;
     }
 
-    pub async fn get_current_segment_data(&self) -> Option<Segment> {
+    pub async fn get_current_segment_data(&self) -> ExecPlan {
         loop {
             let _ = self.move_planned.wait().await;
             let mut do_dwell = false;
@@ -123,19 +410,28 @@ impl MotionPlanner {
                     PlanEntry::Empty => {
                         self.move_planned.reset();
                     },
-                    PlanEntry::Dwell => {
+                    PlanEntry::Dwell(Some(ms)) if ms > 0 => {
+                        rb.data[head] = PlanEntry::Executing(MovType::Dwell);
+                        return ExecPlan::Dwell(ms);
+                    },
+                    PlanEntry::Dwell(_) => {
                         rb.data[head] = PlanEntry::Executing(MovType::Dwell);
                         do_dwell = true;
                     },
                     PlanEntry::PlannedMove(planned_data) => {
                         hwa::debug!("Exec starting: {} / {} h={}", rb.used, SEGMENT_QUEUE_SIZE, head);
                         rb.data[head] = PlanEntry::Executing(MovType::Move);
-                        return Some(planned_data);
+                        return ExecPlan::Segment(planned_data);
                     },
                     PlanEntry::Homing => {
                         self.event_bus.publish_event(EventStatus::containing(EventFlags::HOMMING)).await;
                         rb.data[head] = PlanEntry::Executing(MovType::Homing);
-                        return None;
+                        return ExecPlan::Homing;
+                    },
+                    #[cfg(feature = "with-pen-plotter")]
+                    PlanEntry::Pen(down) => {
+                        rb.data[head] = PlanEntry::Executing(MovType::Pen);
+                        return ExecPlan::Pen(down);
                     },
                     PlanEntry::Executing(_) => {
                         self.move_planned.reset();
@@ -163,6 +459,15 @@ impl MotionPlanner {
                 self.defer_channel.send(DeferEvent::Dwell(DeferType::Completed)).await;
             }
             PlanEntry::Executing(MovType::Move) => {
+                if self.quick_stop_requested.signaled() {
+                    // The segment ended before the stepper task saw the quick stop: nothing left to abort
+                    self.quick_stop_requested.reset();
+                    self.quick_stop_done.signal(true);
+                }
+            }
+            #[cfg(feature = "with-pen-plotter")]
+            PlanEntry::Executing(MovType::Pen) => {
+                self.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
             }
             _ => {
                 panic!("cound not happen")
@@ -181,6 +486,203 @@ impl MotionPlanner {
         self.available.signal(true);
     }
 
+    /***
+    Quick stop (M410): Flushes every queued entry sending its Completed deferral. If a move is being
+    executed, the stepper task is requested to abort it and this call waits until it reports the stopped position
+    or the segment ends by itself. The request is only made (and cleared) under the queue lock, so it cannot be
+    left over for the next segment. The deferrals and the status are handled once the queue is released
+     */
+    pub async fn quick_stop(&self) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let mut flushed_entries = [PlanEntry::Empty; SEGMENT_QUEUE_SIZE as usize];
+        let mut flushed = 0usize;
+        let queue_empty = {
+            let mut rb = self.ringbuffer.lock().await;
+            let mut idx = rb.head;
+            let mut remaining = rb.used;
+            if remaining > 0 {
+                if let PlanEntry::Executing(_) = rb.data[idx as usize] {
+                    idx = (idx + 1) % SEGMENT_QUEUE_SIZE;
+                    remaining -= 1;
+                }
+            }
+            while remaining > 0 {
+                flushed_entries[flushed] = rb.data[idx as usize];
+                rb.data[idx as usize] = PlanEntry::Empty;
+                idx = (idx + 1) % SEGMENT_QUEUE_SIZE;
+                remaining -= 1;
+                flushed += 1;
+            }
+            rb.used -= flushed as u8;
+            if rb.used == 0 {
+                self.move_planned.reset();
+            }
+            rb.used == 0
+        };
+        hwa::info!("Quick stop: {} entries flushed", flushed);
+
+        // None: Keep last planned position. Some(None): Position lost. Some(Some(p)): Restore p
+        let mut restore_pos: Option<Option<TVector<Real>>> = None;
+        for entry in &flushed_entries[..flushed] {
+            match entry {
+                PlanEntry::PlannedMove(segment) => {
+                    restore_pos.get_or_insert(Some(segment.segment_data.src_pos));
+                    #[cfg(feature = "with-laser")]
+                    if let LaserPower::Raster(scan) = segment.segment_data.laser {
+                        self.take_raster_line(scan.slot).await;
+                    }
+                    self.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
+                }
+                PlanEntry::Homing => {
+                    restore_pos.get_or_insert(None);
+                    self.event_bus.publish_event(EventStatus::not_containing(EventFlags::HOMMING)).await;
+                    self.defer_channel.send(DeferEvent::Homing(DeferType::Completed)).await;
+                }
+                PlanEntry::Dwell(_) => {
+                    self.defer_channel.send(DeferEvent::Dwell(DeferType::Completed)).await;
+                }
+                #[cfg(feature = "with-pen-plotter")]
+                PlanEntry::Pen(_) => {
+                    // Pen state unknown: the next pen move is always done
+                    self.motion_st.lock().await.pen_down = None;
+                    self.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
+                }
+                _ => {}
+            }
+        }
+        // Where the executing move ends. Overwritten with the stopped position if it gets aborted, as the abort is
+        // requested after this
+        match restore_pos {
+            Some(Some(pos)) => self.set_last_planned_pos(&pos).await,
+            Some(None) => self.motion_st.lock().await.last_planned_pos = None,
+            None => {}
+        }
+        if queue_empty {
+            self.event_bus.publish_event(EventStatus::containing(EventFlags::MOV_QUEUE_EMPTY)).await;
+        }
+        self.available.signal(true);
+
+        // The executing move, unless it ended meanwhile
+        let executing_move = {
+            let rb = self.ringbuffer.lock().await;
+            let executing_move = rb.used > 0 && matches!(rb.data[rb.head as usize], PlanEntry::Executing(MovType::Move));
+            if executing_move {
+                self.quick_stop_done.reset();
+                self.quick_stop_requested.signal(true);
+                // A held segment must be released to be aborted
+                self.cycle_start();
+            }
+            executing_move
+        };
+        if executing_move {
+            self.quick_stop_done.wait().await;
+            self.quick_stop_done.reset();
+        }
+        Ok(CodeExecutionSuccess::OK)
+    }
+
+    /// Polled by the stepper task on every tick
+    #[inline]
+    pub fn is_quick_stop_requested(&self) -> bool {
+        self.quick_stop_requested.signaled()
+    }
+
+    /***
+    Feed hold: the executing segment decelerates to a stop within the acceleration and jerk limits, keeping the
+    queue. Motion continues from that point on [MotionPlanner::cycle_start]
+     */
+    pub fn feed_hold(&self) {
+        self.cycle_start.reset();
+        self.feed_hold.signal(true);
+    }
+
+    /// Releases a feed hold
+    pub fn cycle_start(&self) {
+        if self.feed_hold.signaled() {
+            self.feed_hold.reset();
+            self.cycle_start.signal(true);
+        }
+    }
+
+    #[inline]
+    pub fn is_feed_hold_requested(&self) -> bool {
+        self.feed_hold.signaled()
+    }
+
+    /// Called by the stepper task when stopped by a feed hold. Returns at once unless a feed hold is requested
+    pub async fn wait_cycle_start(&self) {
+        if self.feed_hold.signaled() {
+            self.cycle_start.wait().await;
+            self.cycle_start.reset();
+        }
+    }
+
+    #[cfg(feature = "with-grbl-protocol")]
+    pub async fn is_jogging(&self) -> bool {
+        self.motion_st.lock().await.jogging
+    }
+
+    #[cfg(feature = "with-grbl-protocol")]
+    pub async fn get_realtime_speed(&self) -> Real {
+        self.motion_st.lock().await.realtime_speed
+    }
+
+    /// Called by the stepper task when a segment starts or the live speed factor changes. Units per second
+    #[cfg(feature = "with-grbl-protocol")]
+    pub async fn set_realtime_speed(&self, speed: Real) {
+        self.motion_st.lock().await.realtime_speed = speed;
+    }
+
+    /***
+    Called by the stepper task when the executing segment has been aborted, with the position reached.
+    Releases the segment and the pending quick_stop()
+     */
+    pub async fn quick_stop_completed(&self, stopped_pos: &TVector<Real>) {
+        self.set_last_planned_pos(stopped_pos).await;
+        self.quick_stop_requested.reset();
+        self.consume_current_segment_data().await;
+        self.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
+        self.quick_stop_done.signal(true);
+    }
+
+    /***
+    Called by the stepper task when the probe changed during a probe move, with the position reached.
+    The segment is aborted and the reached position becomes the planned one
+     */
+    pub async fn probe_completed(&self, stopped_pos: &TVector<Real>) {
+        self.set_last_planned_pos(stopped_pos).await;
+        self.motion_st.lock().await.probe_result.replace((*stopped_pos, true));
+        self.consume_current_segment_data().await;
+        self.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
+    }
+
+    /// Called by the stepper task when a probe move ends without the probe changing
+    pub async fn probe_failed(&self, pos: &TVector<Real>) {
+        self.motion_st.lock().await.probe_result.replace((*pos, false));
+    }
+
+    /***
+    Straight probe (G38.x): moves toward p1 (work coords) until the probe triggers (toward) or is released (away).
+    Waits for the queued moves before and the probe move after. Returns the position reached and whether the probe changed.
+    Fails if the probe is already in the expected state before moving
+     */
+    pub async fn straight_probe(&self, p1: TVector<Real>, requested_motion_speed: Option<Real>, toward: bool) -> Result<(TVector<Real>, bool), CodeExecutionFailure> {
+        let p1 = self.to_machine(&p1).await;
+        self.straight_probe_machine(p1, requested_motion_speed, toward).await
+    }
+
+    /// [MotionPlanner::straight_probe] with p1 in machine coords
+    async fn straight_probe_machine(&self, p1: TVector<Real>, requested_motion_speed: Option<Real>, toward: bool) -> Result<(TVector<Real>, bool), CodeExecutionFailure> {
+        self.synchronize().await;
+        if self.motion_driver.lock().await.probe_triggered() == toward {
+            hwa::warn!("Probe fail: initial state");
+            return Err(CodeExecutionFailure::ERR);
+        }
+        self.motion_st.lock().await.probe_result = None;
+        self.schedule_segment(p1, requested_motion_speed, Cut::Travel, Some(ProbeMode { toward }), true).await?;
+        self.synchronize().await;
+        self.motion_st.lock().await.probe_result.take().ok_or(CodeExecutionFailure::ERR)
+    }
+
     pub async fn schedule_raw_move(&self, move_type: ScheduledMove, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
 
         loop {
@@ -192,13 +694,8 @@ impl MotionPlanner {
                 let mut must_defer = true;
 
                 if rb.used < (SEGMENT_QUEUE_SIZE as u8) {
-                    let used = rb.used;
-                    let head = rb.head;
-                    let mut could_replan = false;
-
                     let (entry, event) = match move_type {
                         ScheduledMove::Move(segment_data, motion_profile) => {
-                            could_replan = true;
                             must_defer = false;
                             self.update_last_planned_pos(&segment_data.dest_pos).await;
                             (PlanEntry::PlannedMove(Segment::new(segment_data, motion_profile)), EventStatus::new())
@@ -207,43 +704,17 @@ impl MotionPlanner {
                             self.set_last_planned_pos(&TVector::zero()).await;
                             (PlanEntry::Homing, EventStatus::not_containing(EventFlags::HOMMING))
                         }
-                        ScheduledMove::Dwell => {
-                            (PlanEntry::Dwell, EventStatus::containing(EventFlags::MOV_QUEUE_EMPTY))
+                        ScheduledMove::Dwell(ms) => {
+                            (PlanEntry::Dwell(ms), EventStatus::containing(EventFlags::MOV_QUEUE_EMPTY))
+                        }
+                        #[cfg(feature = "with-pen-plotter")]
+                        ScheduledMove::Pen(down) => {
+                            (PlanEntry::Pen(down), EventStatus::new())
                         }
                     };
 
-                    let index = head as u16 + used as u16;
-
-                    let index = match index < SEGMENT_QUEUE_SIZE as u16 {
-                        true => index,
-                        false => index - SEGMENT_QUEUE_SIZE as u16,
-                    } as u8;
-
-                    rb.data[index as usize] = entry;
-
-                    hwa::debug!("Mov queued @{} ({} / {})", index, rb.used + 1, SEGMENT_QUEUE_SIZE);
-                    if could_replan && used > 0 {
-                        let last_inserted_idx = rb.head as u16 + rb.used as u16 - 1;
-                        let last_inserted_idx = match last_inserted_idx < (SEGMENT_QUEUE_SIZE as u16) {
-                            true => last_inserted_idx,
-                            false => 0,
-                        };
-                        hwa::debug!(" - check_replan {}", last_inserted_idx);
-                        let current_slot = &mut rb.data[last_inserted_idx as usize];
-                        match current_slot {
-                            PlanEntry::Executing(_) | PlanEntry::Homing | PlanEntry::Empty => {
-                                hwa::debug!(" -- not chained")
-                            }
-                            PlanEntry::PlannedMove(old_data) => {
-                                old_data.motion_profile.recalculate();
-                                hwa::debug!(" -- chained")
-                            }
-                            _ => {
-                                unreachable!("Could not happen");
-                            }
-                        }
-                    }
-                    rb.used += 1;
+                    let index = rb.enqueue(entry);
+                    hwa::debug!("Mov queued @{} ({} / {})", index, rb.used, SEGMENT_QUEUE_SIZE);
                     self.event_bus.publish_event(EventStatus::not_containing(EventFlags::MOV_QUEUE_EMPTY)).await;
                     self.move_planned.signal(true);
                     if must_defer || (rb.used == (SEGMENT_QUEUE_SIZE as u8)) {
@@ -286,6 +757,305 @@ impl MotionPlanner {
         }
     }
 
+    pub async fn get_usteps_per_unit(&self) -> Real {
+        Real::new(self.motion_cfg.lock().await.usteps_per_unit as i64, 0)
+    }
+
+    /// Accumulates the microsteps just emitted. Called by the stepper task only
+    pub async fn add_step_count(&self, steps: &TVector<i32>) {
+        let mut st = self.motion_st.lock().await;
+        st.step_count = st.step_count + steps.map_nan(0);
+    }
+
+    pub async fn reset_step_count(&self) {
+        self.motion_st.lock().await.step_count = TVector::from_coords(Some(0), Some(0), Some(0), Some(0));
+    }
+
+    pub async fn get_step_count(&self) -> TVector<i32> {
+        self.motion_st.lock().await.step_count
+    }
+
+    /***
+    The position the steppers are at right now, derived from the step counters (homing position is the origin).
+    Unlike [MotionPlanner::get_last_planned_pos], it does not include queued moves
+     */
+    pub async fn get_realtime_pos(&self) -> TVector<Real> {
+        let usteps_per_unit = self.get_usteps_per_unit().await;
+        self.get_step_count().await.map_coords(|c| Some(Real::new(c as i64, 0))) / usteps_per_unit
+    }
+
+    pub async fn get_work_coords(&self) -> WorkCoords {
+        self.motion_st.lock().await.work_coords
+    }
+
+    /// Converts the given work coords to machine coords
+    pub async fn to_machine(&self, pos: &TVector<Real>) -> TVector<Real> {
+        self.motion_st.lock().await.work_coords.to_machine(pos)
+    }
+
+    /// Converts the given machine coords to work coords
+    pub async fn to_work(&self, pos: &TVector<Real>) -> TVector<Real> {
+        self.motion_st.lock().await.work_coords.to_work(pos)
+    }
+
+    /// Selects the work coordinate system (0 is G54)
+    pub async fn select_wcs(&self, wcs: u8) -> Result<(), CodeExecutionFailure> {
+        if wcs as usize >= WCS_COUNT {
+            return Err(CodeExecutionFailure::ERR);
+        }
+        self.motion_st.lock().await.work_coords.active = wcs;
+        Ok(())
+    }
+
+    /// G10 L2: Sets the origin of the given system (the selected one when None). Unset coords are left untouched
+    pub async fn set_wcs_offset(&self, wcs: Option<u8>, offset: &TVector<Real>) -> Result<(), CodeExecutionFailure> {
+        let mut st = self.motion_st.lock().await;
+        let wcs = wcs.unwrap_or(st.work_coords.active) as usize;
+        let origin = st.work_coords.offsets.get_mut(wcs).ok_or(CodeExecutionFailure::ERR)?;
+        origin.assign_if_set(CoordSel::XYZ, offset);
+        Ok(())
+    }
+
+    /***
+    G10 L20: Sets the origin of the given system (the selected one when None) so that the current position
+    gets the given work coords
+     */
+    pub async fn set_wcs_position(&self, wcs: Option<u8>, pos: &TVector<Real>) -> Result<(), CodeExecutionFailure> {
+        let mut st = self.motion_st.lock().await;
+        let machine_pos = st.last_planned_pos.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let g92 = match st.work_coords.g92_enabled {
+            true => st.work_coords.g92.map_nan(Real::zero()),
+            false => TVector::zero(),
+        };
+        let tool_offset = st.work_coords.tool_offset();
+        let wcs = wcs.unwrap_or(st.work_coords.active) as usize;
+        let origin = st.work_coords.offsets.get_mut(wcs).ok_or(CodeExecutionFailure::ERR)?;
+        origin.assign_if_set(CoordSel::XYZ, &(machine_pos - g92 - tool_offset - *pos));
+        Ok(())
+    }
+
+    /// G92: Sets the G92 offset so that the current position gets the given work coords
+    pub async fn set_g92_position(&self, pos: &TVector<Real>) -> Result<(), CodeExecutionFailure> {
+        let mut st = self.motion_st.lock().await;
+        let machine_pos = st.last_planned_pos.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let wcs = st.work_coords.offsets[st.work_coords.active as usize].map_nan(Real::zero());
+        if !st.work_coords.g92_enabled {
+            // Setting a new one discards the suspended offset
+            st.work_coords.g92 = TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None);
+            st.work_coords.g92_enabled = true;
+        }
+        let tool_offset = st.work_coords.tool_offset();
+        st.work_coords.g92.assign_if_set(CoordSel::XYZ, &(machine_pos - wcs - tool_offset - *pos));
+        Ok(())
+    }
+
+    /// G92.1 clears the G92 offset. G92.2 suspends it, keeping its value
+    pub async fn reset_g92(&self, keep: bool) {
+        let mut st = self.motion_st.lock().await;
+        if !keep {
+            st.work_coords.g92 = TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None);
+        }
+        st.work_coords.g92_enabled = false;
+    }
+
+    /// G93 (true) / G94 (false)
+    pub async fn set_inverse_time_feed(&self, enabled: bool) {
+        self.motion_st.lock().await.modal.inverse_time_feed = enabled;
+    }
+
+    pub async fn is_inverse_time_feed(&self) -> bool {
+        self.motion_st.lock().await.modal.inverse_time_feed
+    }
+
+    /// G91 (true) / G90 (false)
+    pub async fn set_relative_positioning(&self, relative: bool) {
+        self.motion_st.lock().await.modal.relative = relative;
+    }
+
+    /// M83 (true) / M82 (false)
+    pub async fn set_relative_extrusion(&self, relative: bool) {
+        self.motion_st.lock().await.modal.relative_e = relative;
+    }
+
+    /// G92 E: sets the logical E position used with absolute extrusion
+    pub async fn set_e_position(&self, e: Real) {
+        self.motion_st.lock().await.modal.e_pos = e;
+    }
+
+    /// Target of a G0/G1 in machine coords. XYZ are offsets from the last planned position with G91.
+    /// E is always returned as the distance to extrude, converted from the logical position after M82
+    pub async fn modal_target(&self, pos: &TVector<Real>) -> Result<TVector<Real>, CodeExecutionFailure> {
+        let mut st = self.motion_st.lock().await;
+        let mut p1 = match st.modal.relative {
+            true => st.last_planned_pos.ok_or(CodeExecutionFailure::HomingRequired)? + *pos,
+            false => st.work_coords.to_machine(pos),
+        };
+        p1.e = match (pos.e, st.modal.relative_e) {
+            (Some(e), false) => {
+                let distance = e - st.modal.e_pos;
+                st.modal.e_pos = e;
+                Some(distance)
+            }
+            (e, _) => e,
+        };
+        Ok(p1)
+    }
+
+    /***
+    The speed that completes the move from the last planned position to p1 (machine coords) in 1/f minutes.
+    In inverse time mode, f is mandatory in every feed move
+     */
+    async fn inverse_time_speed(&self, p1: &TVector<Real>, f: Option<Real>) -> Result<Real, CodeExecutionFailure> {
+        let f = f.ok_or(CodeExecutionFailure::ERR)?;
+        let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let distance = (*p1 - p0).norm2().unwrap_or(ZERO);
+        Ok(distance * f / Real::from_lit(60, 0))
+    }
+
+    /// G98/G99
+    pub async fn set_cycle_return(&self, mode: CycleReturn) {
+        self.motion_st.lock().await.cycle_return = mode;
+    }
+
+    /// G80
+    pub async fn cancel_canned_cycle(&self) {
+        self.motion_st.lock().await.canned_cycle = None;
+    }
+
+    pub async fn get_canned_cycle(&self) -> Option<CannedCycle> {
+        self.motion_st.lock().await.canned_cycle
+    }
+
+    /***
+    G81/G82/G83: Makes the cycle the active one and drills the first hole at X Y (current XY when not given).
+    Z, R, F and Q are taken from the previous cycle when not given. P is the dwell in milliseconds
+     */
+    pub async fn start_canned_cycle(&self, kind: CycleKind, t: &FPQRXYZ) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let machine_pos = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let initial_z = self.to_work(&machine_pos).await.z.unwrap_or(ZERO);
+        let prev = self.get_canned_cycle().await;
+        let cycle = CannedCycle {
+            kind,
+            z: t.z.or(prev.map(|c| c.z)).ok_or(CodeExecutionFailure::ERR)?,
+            r: t.r.or(prev.map(|c| c.r)).ok_or(CodeExecutionFailure::ERR)?,
+            feed: t.f.or(prev.and_then(|c| c.feed)),
+            dwell_ms: t.p.and_then(|p| p.to_i32()).map(|ms| ms.max(0) as u32).unwrap_or(0),
+            peck: t.q.or(prev.map(|c| c.peck)).unwrap_or(ZERO),
+            initial_z,
+        };
+        if cycle.z > cycle.r || (kind == CycleKind::Peck && cycle.peck <= ZERO) {
+            return Err(CodeExecutionFailure::ERR);
+        }
+        self.motion_st.lock().await.canned_cycle.replace(cycle);
+        self.drill_hole(&cycle, t.x, t.y).await
+    }
+
+    /// A line with only coordinates while a drilling cycle is active: drills another hole at X Y
+    pub async fn repeat_canned_cycle(&self, x: Option<Real>, y: Option<Real>) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let cycle = self.get_canned_cycle().await.ok_or(CodeExecutionFailure::ERR)?;
+        self.drill_hole(&cycle, x, y).await
+    }
+
+    /// Rapid move to the given work coords. Always queued in blocking mode
+    async fn rapid_to(&self, x: Option<Real>, y: Option<Real>, z: Option<Real>) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let p1 = self.to_machine(&TVector { x, y, z, e: None }).await;
+        self.schedule_move(p1, None, true).await
+    }
+
+    /// Feed move along Z to the given work coord. Always queued in blocking mode
+    async fn feed_to_z(&self, z: Real, feed: Option<Real>) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let p1 = self.to_machine(&TVector { x: None, y: None, z: Some(z), e: None }).await;
+        self.schedule_segment(p1, feed, Cut::Feed, None, true).await
+    }
+
+    /// One hole of the cycle at X Y. Moves are always queued in blocking mode
+    async fn drill_hole(&self, cycle: &CannedCycle, x: Option<Real>, y: Option<Real>) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let machine_pos = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let current_z = self.to_work(&machine_pos).await.z.unwrap_or(cycle.r);
+        let return_z = match self.motion_st.lock().await.cycle_return {
+            CycleReturn::InitialZ => core::cmp::max(cycle.initial_z, cycle.r),
+            CycleReturn::RPlane => cycle.r,
+        };
+        let mut result = CodeExecutionSuccess::OK;
+        for mov in cycle.hole_moves(current_z, return_z) {
+            result = match mov {
+                CycleMove::RapidXY => self.rapid_to(x, y, None).await?,
+                CycleMove::RapidZ(z) => self.rapid_to(None, None, Some(z)).await?,
+                CycleMove::FeedZ(z) => self.feed_to_z(z, cycle.feed).await?,
+                CycleMove::Dwell(ms) => self.schedule_raw_move(ScheduledMove::Dwell(Some(ms)), true).await?,
+            };
+        }
+        Ok(result)
+    }
+
+    /// The event bus flags telling the given axes are enabled
+    fn enabled_flags(axes: CoordSel) -> EventFlags {
+        let mut flags = EventFlags::empty();
+        if axes.contains(CoordSel::X) { flags |= EventFlags::X_ENABLED; }
+        if axes.contains(CoordSel::Y) { flags |= EventFlags::Y_ENABLED; }
+        if axes.contains(CoordSel::Z) { flags |= EventFlags::Z_ENABLED; }
+        if axes.contains(CoordSel::E) { flags |= EventFlags::E_ENABLED; }
+        flags
+    }
+
+    /// Powers on the given axes (if not already) and publishes their *_ENABLED flags
+    pub async fn enable_steppers(&self, axes: CoordSel) {
+        let newly_enabled = {
+            let mut st = self.motion_st.lock().await;
+            let newly_enabled = axes.difference(st.enabled_axes);
+            st.enabled_axes |= axes;
+            newly_enabled
+        };
+        if !newly_enabled.is_empty() {
+            self.motion_driver.lock().await.enable_steppers(newly_enabled);
+            self.event_bus.publish_event(EventStatus::containing(Self::enabled_flags(newly_enabled))).await;
+        }
+    }
+
+    /// Powers off the given axes and clears their *_ENABLED flags
+    pub async fn disable_steppers(&self, axes: CoordSel) {
+        self.motion_st.lock().await.enabled_axes.remove(axes);
+        self.motion_driver.lock().await.disable_steppers(axes);
+        self.event_bus.publish_event(EventStatus::not_containing(Self::enabled_flags(axes))).await;
+    }
+
+    pub async fn get_enabled_axes(&self) -> CoordSel {
+        self.motion_st.lock().await.enabled_axes
+    }
+
+    /// Idle timeout expired: Powers off every enabled axis but the held ones
+    pub async fn disable_idle_steppers(&self) {
+        let hold_axes = self.get_idle_hold_axes().await;
+        let axes = self.get_enabled_axes().await.difference(hold_axes);
+        if !axes.is_empty() {
+            hwa::info!("Idle timeout. Powering steppers off");
+            self.disable_steppers(axes).await;
+        }
+    }
+
+    /// Idle timeout in seconds. 0 = never
+    pub async fn get_stepper_idle_timeout(&self) -> u32 {
+        self.motion_cfg.lock().await.stepper_idle_timeout
+    }
+
+    pub async fn set_stepper_idle_timeout(&self, secs: u32) {
+        self.motion_cfg.lock().await.stepper_idle_timeout = secs;
+    }
+
+    pub async fn get_idle_hold_axes(&self) -> CoordSel {
+        self.motion_cfg.lock().await.idle_hold_axes
+    }
+
+    pub async fn set_idle_hold_axes(&self, axes: CoordSel) {
+        self.motion_cfg.lock().await.idle_hold_axes = axes;
+    }
+
+    /// Waits until every queued move has been executed
+    pub async fn synchronize(&self) {
+        let mut subscriber = self.event_bus.subscriber().await;
+        subscriber.wait_until(EventStatus::containing(EventFlags::MOV_QUEUE_EMPTY)).await;
+    }
+
     pub async fn get_flow_rate(&self) -> u8 {
         self.motion_cfg.lock().await.flow_rate
     }
@@ -294,6 +1064,16 @@ impl MotionPlanner {
         self.motion_cfg.lock().await.flow_rate = rate;
     }
 
+    /// Flow rate as a factor (1.0 = 100%)
+    pub async fn get_flow_factor(&self) -> Real {
+        self.get_flow_rate_as_real().await / ONE_HUNDRED
+    }
+
+    /// Speed rate as a factor (1.0 = 100%)
+    pub async fn get_speed_factor(&self) -> Real {
+        self.get_speed_rate_as_real().await / ONE_HUNDRED
+    }
+
     pub async fn get_flow_rate_as_real(&self) -> Real {
         Real::new(self.motion_cfg.lock().await.flow_rate as i64, 0)
     }
@@ -310,6 +1090,14 @@ impl MotionPlanner {
         Real::new(self.motion_cfg.lock().await.speed_rate as i64, 0)
     }
 
+    pub async fn get_auto_report_interval(&self) -> u8 {
+        self.motion_cfg.lock().await.auto_report_secs
+    }
+
+    pub async fn set_auto_report_interval(&self, secs: u8) {
+        self.motion_cfg.lock().await.auto_report_secs = secs;
+    }
+
     pub async fn get_default_travel_speed(&self) -> u16 {
         self.motion_cfg.lock().await.default_travel_speed
     }
@@ -347,22 +1135,540 @@ impl MotionPlanner {
         self.motion_cfg.lock().await.max_jerk.assign(CoordSel::all(), &jerk);
     }
 
+    pub async fn get_retract_config(&self) -> RetractConfig {
+        self.motion_cfg.lock().await.retract
+    }
+
+    pub async fn set_retract_config(&self, retract: RetractConfig) {
+        self.motion_cfg.lock().await.retract = retract;
+    }
+
+    /***
+    Firmware retraction. Schedules a single E (and optional Z-hop) move. Ignored if already retracted
+     */
+    pub async fn retract(&self, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        if self.motion_st.lock().await.retracted.is_some() {
+            return Ok(CodeExecutionSuccess::OK);
+        }
+        let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let cfg = self.get_retract_config().await;
+        let z = match cfg.z_hop.is_zero() {
+            true => None,
+            false => p0.z.map(|z| z + cfg.z_hop),
+        };
+        let r = self.schedule_move(TVector {
+            x: None, y: None, z, e: Some(-cfg.length),
+        }, cfg.speed, blocking).await?;
+        self.motion_st.lock().await.retracted.replace(cfg.z_hop);
+        Ok(r)
+    }
+
+    /***
+    Firmware recover. Undoes the Z-hop and pushes back the retracted length plus the configured extra
+     */
+    pub async fn recover(&self, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let z_hop = match self.motion_st.lock().await.retracted {
+            None => return Ok(CodeExecutionSuccess::OK),
+            Some(z_hop) => z_hop,
+        };
+        let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let cfg = self.get_retract_config().await;
+        let z = match z_hop.is_zero() {
+            true => None,
+            false => p0.z.map(|z| z - z_hop),
+        };
+        let r = self.schedule_move(TVector {
+            x: None, y: None, z, e: Some(cfg.length + cfg.recover_extra_length),
+        }, cfg.recover_speed, blocking).await?;
+        self.motion_st.lock().await.retracted = None;
+        Ok(r)
+    }
+
+    pub async fn get_park_config(&self) -> ParkConfig {
+        self.motion_cfg.lock().await.park
+    }
+
+    pub async fn set_park_config(&self, park: ParkConfig) {
+        self.motion_cfg.lock().await.park = park;
+    }
+
+    pub async fn is_parked(&self) -> bool {
+        self.motion_st.lock().await.parked.is_some()
+    }
+
+    /***
+    Saves the planned position, the work coordinates, the modal state and the retraction state, then retracts, lifts Z and moves to the park position.
+    When unload is set (M600), the filament is also unloaded. Moves are always queued in blocking mode
+     */
+    pub async fn park(&self, unload: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        if self.is_parked().await {
+            return Ok(CodeExecutionSuccess::OK);
+        }
+        let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let saved = {
+            let st = self.motion_st.lock().await;
+            ParkedState {
+                pos: p0,
+                work_coords: st.work_coords,
+                tool: st.current_tool,
+                modal: st.modal,
+                retracted: st.retracted,
+                unloaded: false,
+            }
+        };
+        let cfg = self.get_park_config().await;
+        hwa::info!("Parking from {}", p0.rdp(4));
+        self.retract(true).await?;
+        if !cfg.z_lift.is_zero() {
+            let p = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+            self.schedule_move(TVector {
+                x: None, y: None, z: p.z.map(|z| z + cfg.z_lift), e: None,
+            }, None, true).await?;
+        }
+        let mut r = self.schedule_move(TVector {
+            x: cfg.position.x, y: cfg.position.y, z: None, e: None,
+        }, None, true).await?;
+        let unloaded = unload && !cfg.unload_length.is_zero();
+        if unloaded {
+            r = self.schedule_move(TVector {
+                x: None, y: None, z: None, e: Some(-cfg.unload_length),
+            }, cfg.change_speed, true).await?;
+        }
+        self.motion_st.lock().await.parked.replace(ParkedState { unloaded, ..saved });
+        Ok(r)
+    }
+
+    /***
+    Undoes a park: restores the work coordinates and the modal state, loads and purges if filament was unloaded,
+    moves back to the saved position and restores the retraction state
+     */
+    pub async fn unpark(&self) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let saved = match self.motion_st.lock().await.parked.take() {
+            None => return Ok(CodeExecutionSuccess::OK),
+            Some(saved) => saved,
+        };
+        let cfg = self.get_park_config().await;
+        let pos = {
+            let mut st = self.motion_st.lock().await;
+            st.work_coords = saved.resume_coords(&st.work_coords, st.current_tool);
+            st.modal = saved.modal;
+            saved.return_pos(&st.work_coords.offset())
+        };
+        if saved.unloaded {
+            self.schedule_move(TVector {
+                x: None, y: None, z: None, e: Some(cfg.load_length + cfg.purge_length),
+            }, cfg.change_speed, true).await?;
+        }
+        self.schedule_move(TVector {
+            x: pos.x, y: pos.y, z: None, e: None,
+        }, None, true).await?;
+        let mut r = self.schedule_move(TVector {
+            x: None, y: None, z: pos.z, e: None,
+        }, None, true).await?;
+        if saved.retracted.is_none() {
+            // Z-hop was already undone by the move above
+            self.motion_st.lock().await.retracted.replace(Real::zero());
+            r = self.recover(true).await?;
+        }
+        hwa::info!("Unparked to {}", pos.rdp(4));
+        Ok(r)
+    }
+
+    pub async fn get_manual_tool_change_config(&self) -> ManualToolChangeConfig {
+        self.motion_cfg.lock().await.manual_tool_change
+    }
+
+    pub async fn set_manual_tool_change_config(&self, config: ManualToolChangeConfig) {
+        self.motion_cfg.lock().await.manual_tool_change = config;
+    }
+
+    /// Tool in the spindle after the last M6. None when unknown
+    pub async fn get_spindle_tool(&self) -> Option<u8> {
+        self.motion_st.lock().await.tool_lengths.current
+    }
+
+    pub async fn get_tool_length(&self, tool: u8) -> Option<Real> {
+        self.motion_st.lock().await.tool_lengths.get(tool)
+    }
+
+    pub async fn is_tool_change_pending(&self) -> bool {
+        self.motion_st.lock().await.tool_lengths.pending.is_some()
+    }
+
+    /***
+    G43: Applies the measured length of the given tool (the one in the spindle when None) to Z.
+    Fails if the tool was never measured. G49 is `set_tool_length_offset(None)`
+     */
+    pub async fn set_tool_length_offset(&self, tool: Option<u8>) -> Result<(), CodeExecutionFailure> {
+        let mut st = self.motion_st.lock().await;
+        st.work_coords.tool_length = match tool.or(st.tool_lengths.current) {
+            None => Real::zero(),
+            Some(tool) => st.tool_lengths.get(tool).ok_or(CodeExecutionFailure::ERR)?,
+        };
+        Ok(())
+    }
+
+    /***
+    M6: Lifts to the Z of the tool change position, then moves to its XY (machine coords) and leaves the change
+    pending until [MotionPlanner::finish_tool_change]. The tool in the spindle is requested when None.
+    Moves are always queued in blocking mode
+     */
+    pub async fn begin_tool_change(&self, tool: Option<u8>) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let tool = tool.or(self.get_spindle_tool().await).unwrap_or(0);
+        if tool as usize >= MAX_TOOL_LENGTHS {
+            return Err(CodeExecutionFailure::ERR);
+        }
+        let cfg = self.get_manual_tool_change_config().await;
+        if cfg.position.z.is_some() {
+            self.schedule_move(TVector {
+                x: None, y: None, z: cfg.position.z, e: None,
+            }, None, true).await?;
+        }
+        let r = self.schedule_move(TVector {
+            x: cfg.position.x, y: cfg.position.y, z: None, e: None,
+        }, None, true).await?;
+        self.motion_st.lock().await.tool_lengths.pending.replace(tool);
+        hwa::info!("Tool change to T{}", tool);
+        Ok(r)
+    }
+
+    /***
+    Completes a pending M6 once the user confirms. When configured, the new tool is probed over the tool setter and
+    its length (relative to the first tool probed) stored, then Z goes back to the tool change position.
+    The length offset is not applied until G43. Returns None when no tool change was pending
+     */
+    pub async fn finish_tool_change(&self) -> Result<Option<CodeExecutionSuccess>, CodeExecutionFailure> {
+        let tool = match self.motion_st.lock().await.tool_lengths.pending.take() {
+            None => return Ok(None),
+            Some(tool) => tool,
+        };
+        let cfg = self.get_manual_tool_change_config().await;
+        let mut r = CodeExecutionSuccess::OK;
+        if cfg.probe {
+            self.schedule_move(TVector {
+                x: cfg.probe_position.x, y: cfg.probe_position.y, z: None, e: None,
+            }, None, true).await?;
+            let (pos, triggered) = self.straight_probe_machine(TVector {
+                x: None, y: None, z: Some(cfg.probe_z), e: None,
+            }, cfg.probe_speed, true).await?;
+            if !triggered {
+                hwa::warn!("Tool length probe of T{} failed", tool);
+                return Err(CodeExecutionFailure::ERR);
+            }
+            let trigger_z = pos.z.ok_or(CodeExecutionFailure::ERR)?;
+            let length = {
+                let mut st = self.motion_st.lock().await;
+                let length = trigger_z - *st.tool_lengths.reference_z.get_or_insert(trigger_z);
+                st.tool_lengths.lengths[tool as usize].replace(length);
+                length
+            };
+            hwa::info!("T{} length offset: {}", tool, length.rdp(4));
+            if cfg.position.z.is_some() {
+                r = self.schedule_move(TVector {
+                    x: None, y: None, z: cfg.position.z, e: None,
+                }, None, true).await?;
+            }
+        }
+        self.motion_st.lock().await.tool_lengths.current.replace(tool);
+        Ok(Some(r))
+    }
+
+    pub async fn get_tool(&self, tool: u8) -> Option<ToolConfig> {
+        self.motion_cfg.lock().await.tools.get(tool)
+    }
+
+    /***
+    Defines (Some) or removes (None) a tool. If the tool is the selected one, a change of its offset applies to
+    the next move
+     */
+    pub async fn set_tool(&self, tool: u8, config: Option<ToolConfig>) -> Result<(), CodeExecutionFailure> {
+        {
+            let mut cfg = self.motion_cfg.lock().await;
+            let slot = cfg.tools.tools.get_mut(tool as usize).ok_or(CodeExecutionFailure::ERR)?;
+            *slot = config;
+        }
+        if self.get_current_tool().await == Some(tool) {
+            match config {
+                None => {
+                    self.set_tool_offset(&TVector::zero()).await;
+                    self.motion_st.lock().await.current_tool = None;
+                }
+                Some(new_tool) => {
+                    self.set_tool_offset(&new_tool.offset).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of defined tools
+    pub async fn get_tool_count(&self) -> u8 {
+        self.motion_cfg.lock().await.tools.count()
+    }
+
+    pub async fn get_current_tool(&self) -> Option<u8> {
+        self.motion_st.lock().await.current_tool
+    }
+
+    /// Extruder drive of the selected tool. The first one when no tool is selected or it has no drive
+    pub async fn get_e_drive(&self) -> u8 {
+        match self.get_current_tool().await {
+            None => 0,
+            Some(tool) => self.get_tool(tool).await.and_then(|t| t.drive).unwrap_or(0),
+        }
+    }
+
+    pub async fn get_tool_change_config(&self) -> ToolChangeConfig {
+        self.motion_cfg.lock().await.tools.change
+    }
+
+    pub async fn set_tool_change_config(&self, change: ToolChangeConfig) {
+        self.motion_cfg.lock().await.tools.change = change;
+    }
+
+    /// Machine position does not change, so user position moves by the offset difference
+    async fn set_tool_offset(&self, offset: &TVector<Real>) {
+        self.motion_st.lock().await.work_coords.tool.assign_if_set(CoordSel::XYZ, &offset.map_nan(Real::zero()));
+    }
+
+    /***
+    Makes the given tool the selected one, retracting or parking around the switch as configured.
+    Moves are always queued in blocking mode
+     */
+    pub async fn change_tool(&self, tool: u8) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let new_tool = self.get_tool(tool).await.ok_or(CodeExecutionFailure::ERR)?;
+        if self.get_current_tool().await == Some(tool) {
+            return Ok(CodeExecutionSuccess::OK);
+        }
+        let change = self.get_tool_change_config().await;
+        if change.park {
+            self.park(false).await?;
+        }
+        else if change.retract {
+            self.retract(true).await?;
+        }
+        self.set_tool_offset(&new_tool.offset).await;
+        self.motion_st.lock().await.current_tool.replace(tool);
+        hwa::info!("Tool {} selected", tool);
+        if change.park {
+            self.unpark().await
+        }
+        else if change.retract {
+            self.recover(true).await
+        }
+        else {
+            Ok(CodeExecutionSuccess::OK)
+        }
+    }
+
+    /// Sets the laser mode for the next cutting moves. S, when given, also sets the power
+    #[cfg(feature = "with-laser")]
+    pub async fn set_laser_mode(&self, mode: LaserMode, s: Option<Real>) {
+        let mut st = self.motion_st.lock().await;
+        st.laser_mode = mode;
+        if let Some(s) = s {
+            st.laser_s = s;
+        }
+    }
+
+    #[cfg(feature = "with-laser")]
+    pub async fn get_laser_mode(&self) -> LaserMode {
+        self.motion_st.lock().await.laser_mode
+    }
+
+    /// The laser power a cutting move planned now gets
+    #[cfg(feature = "with-laser")]
+    async fn get_cutting_laser_power(&self) -> LaserPower {
+        let s_max = Real::new(self.motion_cfg.lock().await.laser_s_max as i64, 0);
+        let st = self.motion_st.lock().await;
+        let power = (st.laser_s / s_max).clamp(Real::zero(), Real::one());
+        match (st.laser_mode, power.is_zero()) {
+            (_, true) | (LaserMode::Off, _) => LaserPower::Off,
+            (LaserMode::Constant, false) => LaserPower::Constant(power),
+            (LaserMode::Dynamic, false) => LaserPower::Dynamic(power),
+        }
+    }
+
+    /// Distance run with the laser off before and after a raster scanline
+    #[cfg(feature = "with-laser")]
+    pub async fn get_raster_overscan(&self) -> u16 {
+        self.motion_cfg.lock().await.raster_overscan
+    }
+
+    #[cfg(feature = "with-laser")]
+    pub async fn set_raster_overscan(&self, overscan: u16) {
+        self.motion_cfg.lock().await.raster_overscan = overscan;
+    }
+
+    /// Takes the pixels of a raster scanline out, freeing its slot
+    #[cfg(feature = "with-laser")]
+    pub async fn take_raster_line(&self, slot: u8) -> Option<Vec<u8>> {
+        self.motion_st.lock().await.raster_lines.take(slot)
+    }
+
+    /***
+    G7: Burns the pixels evenly along a line ending at the given work coords (XY), in a single constant speed move.
+    The head runs the overscan from the current position before the first pixel and past the end after the last one,
+    with the laser off. So a scanline ends where the next one, in the opposite direction, starts
+     */
+    #[cfg(feature = "with-laser")]
+    async fn raster_line(&self, t: &DFSXY, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        let pixels = t.d.as_ref().and_then(|d| crate::helpers::decode_base64(d.as_str())).ok_or(CodeExecutionFailure::ERR)?;
+        if let Some(s) = t.s {
+            // Power of a 255 pixel (modal, as in G1)
+            self.motion_st.lock().await.laser_s = s;
+        }
+        let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let mut p1 = p0;
+        p1.assign_if_set(CoordSel::X | CoordSel::Y, &self.to_machine(&TVector { x: t.x, y: t.y, z: None, e: None }).await);
+        let (vdir, distance) = (p1 - p0)
+            .map_coord(CoordSel::all(), |coord_value, _coord_idx| {
+                match coord_value.is_zero() {
+                    true => None,
+                    false => Some(coord_value),
+                }
+            }).decompose_normal();
+        let overscan = Real::new(self.get_raster_overscan().await as i64, 0);
+        let length = distance - overscan;
+        if length <= ZERO {
+            hwa::warn!("Raster line shorter than the overscan");
+            return Err(CodeExecutionFailure::ERR);
+        }
+        let mut p2 = p1;
+        p2.assign_if_set(CoordSel::X | CoordSel::Y, &(p1 + vdir * overscan));
+
+        let s_max = Real::new(self.motion_cfg.lock().await.laser_s_max as i64, 0);
+        let mut st = self.motion_st.lock().await;
+        let power = match st.laser_mode {
+            LaserMode::Off => ZERO,
+            _ => (st.laser_s / s_max).clamp(ZERO, Real::one()),
+        };
+        let slot = st.raster_lines.store(pixels).ok_or(CodeExecutionFailure::BUSY)?;
+        drop(st);
+        let scan = RasterScan { slot, power, start: overscan, length };
+        let r = self.schedule_segment(p2, t.f, Cut::Raster(scan), None, blocking).await;
+        if r.is_err() {
+            self.take_raster_line(slot).await;
+        }
+        r
+    }
+
+    #[cfg(feature = "with-pen-plotter")]
+    pub async fn get_pen_config(&self) -> PenConfig {
+        self.motion_cfg.lock().await.pen
+    }
+
+    #[cfg(feature = "with-pen-plotter")]
+    pub async fn set_pen_config(&self, config: PenConfig) {
+        self.motion_cfg.lock().await.pen = config;
+    }
+
+    /// Queues a pen move (M3/M5, Z), unless the pen is already there after the planned moves
+    #[cfg(feature = "with-pen-plotter")]
+    pub async fn pen_to(&self, down: bool, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        if self.motion_st.lock().await.pen_down == Some(down) {
+            return Ok(CodeExecutionSuccess::OK);
+        }
+        let r = self.schedule_raw_move(ScheduledMove::Pen(down), blocking).await?;
+        self.motion_st.lock().await.pen_down = Some(down);
+        Ok(r)
+    }
+
+    /// Z moves the pen instead of the axis, so it is taken out of the move
+    #[cfg(feature = "with-pen-plotter")]
+    async fn pen_from_z(&self, z: Option<Real>, blocking: bool) -> Result<Option<Real>, CodeExecutionFailure> {
+        if let Some(z) = z {
+            let down = z <= self.get_pen_config().await.z_down;
+            self.pen_to(down, blocking).await?;
+        }
+        Ok(None)
+    }
+
     pub async fn plan(&self, gc: &GCode, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure>{
+        #[cfg(feature = "with-grbl-protocol")]
+        {
+            self.motion_st.lock().await.jogging = matches!(gc, GCode::JOG(_));
+        }
         match gc {
             GCode::G0(t) => {
-                Ok(self.schedule_move(TVector{
-                    x: t.x, y: t.y, z: t.z, e: None,
-                }, t.f, blocking).await?)
+                #[cfg(feature = "with-pen-plotter")]
+                let z = self.pen_from_z(t.z, blocking).await?;
+                #[cfg(not(feature = "with-pen-plotter"))]
+                let z = t.z;
+                let p1 = self.modal_target(&TVector{
+                    x: t.x, y: t.y, z, e: None,
+                }).await?;
+                Ok(self.schedule_move(p1, t.f, blocking).await?)
             }
             GCode::G1(t) => {
-                Ok(self.schedule_move(TVector{
-                    x: t.x, y: t.y, z: t.z, e: t.e
-                }, t.f, blocking).await?)
+                if t.x.is_none() && t.y.is_none() && t.z.is_none() && self.get_retract_config().await.auto_retract {
+                    if let Some(e) = t.e {
+                        return match e < ZERO {
+                            true => self.retract(blocking).await,
+                            false => self.recover(blocking).await,
+                        }
+                    }
+                }
+                #[cfg(feature = "with-laser")]
+                if let Some(s) = t.s {
+                    // Inline power (modal)
+                    self.motion_st.lock().await.laser_s = s;
+                }
+                #[cfg(feature = "with-pen-plotter")]
+                let z = self.pen_from_z(t.z, blocking).await?;
+                #[cfg(not(feature = "with-pen-plotter"))]
+                let z = t.z;
+                let p1 = self.modal_target(&TVector{
+                    x: t.x, y: t.y, z, e: t.e
+                }).await?;
+                let speed = match self.is_inverse_time_feed().await {
+                    true => Some(self.inverse_time_speed(&p1, t.f).await?),
+                    false => {
+                        // F is modal
+                        let mut st = self.motion_st.lock().await;
+                        if t.f.is_some() {
+                            st.modal.feedrate = t.f;
+                        }
+                        st.modal.feedrate
+                    }
+                };
+                Ok(self.schedule_segment(p1, speed, Cut::Feed, None, blocking).await?)
+            }
+            #[cfg(feature = "with-laser")]
+            GCode::G7(t) => {
+                self.raster_line(t, blocking).await
             }
-            GCode::G4 => {
-                Ok(self.schedule_raw_move(ScheduledMove::Dwell, blocking).await?)
+            #[cfg(feature = "with-grbl-protocol")]
+            GCode::JOG(t) => {
+                // F is mandatory. Unset coords are left untouched
+                let f = t.f.ok_or(CodeExecutionFailure::ERR)?;
+                let target = TVector { x: t.x, y: t.y, z: t.z, e: None };
+                let p1 = match (t.machine, t.relative) {
+                    (true, _) => target,
+                    (false, true) => self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)? + target,
+                    (false, false) => self.to_machine(&target).await,
+                };
+                Ok(self.schedule_move(p1, Some(f), blocking).await?)
+            }
+            GCode::G4(t) => {
+                // P (milliseconds) takes precedence over S (seconds)
+                let ms = match (t.p, t.s) {
+                    (Some(p), _) => p.to_i32(),
+                    (None, Some(s)) => (s * ONE_THOUSAND).to_i32(),
+                    (None, None) => None,
+                }.map(|ms| ms.max(0) as u32);
+                Ok(self.schedule_raw_move(ScheduledMove::Dwell(ms), blocking).await?)
+            }
+            GCode::G10(_) | GCode::G22 => {
+                self.retract(blocking).await
+            }
+            GCode::G11 | GCode::G23 => {
+                self.recover(blocking).await
             }
             GCode::G28(_x) => {
+                #[cfg(feature = "with-pen-plotter")]
+                self.pen_to(false, blocking).await?;
                 // FIXME Remove when complete
                 self.defer_channel.send(DeferEvent::Homing(DeferType::AwaitRequested)).await;
                 self.event_bus.publish_event(EventStatus::containing(EventFlags::HOMMING)).await;
@@ -383,18 +1689,24 @@ impl MotionPlanner {
         }
     }
 
+    /// Schedules a non-cutting move (laser off)
     async fn schedule_move(&self, p1: TVector<Real>, requested_motion_speed: Option<Real>, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
+        self.schedule_segment(p1, requested_motion_speed, Cut::Travel, None, blocking).await
+    }
+
+    /// Schedules a move. Cutting moves (G1) get the current laser power. Probe moves stop when the probe changes
+    #[cfg_attr(not(feature = "with-laser"), allow(unused_variables))]
+    async fn schedule_segment(&self, p1: TVector<Real>, requested_motion_speed: Option<Real>, cut: Cut, probe: Option<ProbeMode>, blocking: bool) -> Result<CodeExecutionSuccess, CodeExecutionFailure> {
 
         let t0 = embassy_time::Instant::now();
 
         let p0 = self.get_last_planned_pos().await.ok_or(CodeExecutionFailure::HomingRequired)?;
+        let e_drive = self.get_e_drive().await;
 
         let cfg = self.motion_cfg();
         let cfg_g = cfg.lock().await;
         //----
         let dts = Real::from_lit(cfg_g.default_travel_speed as i64, 0);
-        let flow_rate = Real::from_lit(cfg_g.flow_rate as i64, 0) / ONE_HUNDRED;
-        let speed_rate = Real::from_lit(cfg_g.speed_rate as i64, 0) / ONE_HUNDRED;
         let max_speed = cfg_g.max_speed.map_coords(|c| Some(Real::from_lit(c as i64, 0)));
         let max_accel = cfg_g.max_accel.map_coords(|c| Some(Real::from_lit(c as i64, 0)));
         let max_jerk = cfg_g.max_jerk.map_coords(|c| Some(Real::from_lit(c as i64, 0)));
@@ -405,28 +1717,18 @@ impl MotionPlanner {
 
         // Compute distance and decompose as unit vector and module.
         // When dist is zero, value is map to None (NaN).
-        // In case o E dimension, flow rate factor is applied
+        // Flow rate and speed rate are not applied here but live, at execution time,
+        // so M220/M221 also take effect on the already queued moves
         let (vdir, module_target_distance) = (p1 - p0)
-            .map_coord(CoordSel::all(), |coord_value, coord_idx| {
-                match coord_idx {
-                    CoordSel::X | CoordSel::Y | CoordSel::Z => {
-                        match coord_value.is_zero() {
-                            true => None,
-                            false => Some(coord_value),
-                        }
-                    },
-                    CoordSel::E => {
-                        match coord_value.is_zero() {
-                            true => None,
-                            false => Some(coord_value * flow_rate),
-                        }
-                    },
-                    _ => None,
+            .map_coord(CoordSel::all(), |coord_value, _coord_idx| {
+                match coord_value.is_zero() {
+                    true => None,
+                    false => Some(coord_value),
                 }
             }).decompose_normal();
 
-        // Compute the speed module applying speed_rate factor
-        let speed_module = requested_motion_speed.unwrap_or(dts) * speed_rate;
+        // Compute the nominal speed module
+        let speed_module = requested_motion_speed.unwrap_or(dts);
         // Compute per-axis target speed
         let speed_vector: TVector<Real> = vdir.abs() * speed_module;
         // Clamp per-axis target speed to the physical restrictions
@@ -483,9 +1785,34 @@ impl MotionPlanner {
                     let segment_data = SegmentData {
                         speed_enter_sps: 0,
                         speed_exit_sps: 0,
-                        total_steps: module_target_distance.to_i32().unwrap_or(0) as u32,
+                        total_steps: module_target_distance.round().to_i32().unwrap_or(0) as u32,
                         vdir,
-                        dest_pos: Default::default(),
+                        src_pos: p0,
+                        dest_pos: p1,
+                        a_max: module_target_accel,
+                        j_max: module_target_jerk,
+                        speed_factor_max: {
+                            // Machine limits along the move direction
+                            let axis_rate = vdir.abs();
+                            speed_factor_limit(
+                                (max_speed / axis_rate).min().unwrap_or(ZERO) / module_target_speed,
+                                (max_accel / axis_rate).min().unwrap_or(ZERO) / module_target_accel,
+                                (max_jerk / axis_rate).min().unwrap_or(ZERO) / module_target_jerk,
+                            )
+                        },
+                        e_drive,
+                        #[cfg(feature = "with-laser")]
+                        laser: match cut {
+                            Cut::Travel => LaserPower::Off,
+                            Cut::Feed => self.get_cutting_laser_power().await,
+                            Cut::Raster(scan) => {
+                                if profile.eval_position(profile.t_a) > scan.start {
+                                    hwa::warn!("Raster overscan shorter than the acceleration");
+                                }
+                                LaserPower::Raster(scan)
+                            }
+                        },
+                        probe,
                     };
                     let r = self.schedule_raw_move(
                         ScheduledMove::Move(segment_data, profile),
@@ -529,10 +1856,14 @@ impl MotionPlanner {
     #[inline(always)]
     pub async fn do_homing(&self) -> Result<(), ()>{
         hwa::info!("Homing start");
+        self.enable_steppers(CoordSel::Z).await;
         let r = self.motion_driver.lock().await.homing_action().await;
         hwa::info!("Homing end");
-        if r.is_err() {
-            self.event_bus.publish_event(EventStatus::containing(EventFlags::SYS_ALARM));
+        if r.is_ok() {
+            self.reset_step_count().await;
+        }
+        else {
+            self.event_bus.publish_event(EventStatus::containing(EventFlags::SYS_ALARM)).await;
         }
         r
     }
@@ -554,6 +1885,39 @@ impl RingBuffer {
             used: 0,
         }
     }
+
+    #[inline]
+    fn wrap(index: u16) -> u8 {
+        (index % SEGMENT_QUEUE_SIZE as u16) as u8
+    }
+
+    /// Puts the entry at the tail (the queue must not be full) and returns its index.
+    /// A move chains onto the previous entry when that one is a planned move too
+    pub(self) fn enqueue(&mut self, entry: PlanEntry) -> u8 {
+        let index = Self::wrap(self.head as u16 + self.used as u16);
+        let could_replan = matches!(entry, PlanEntry::PlannedMove(_));
+        self.data[index as usize] = entry;
+        if could_replan && self.used > 0 {
+            let last_inserted_idx = Self::wrap(self.head as u16 + self.used as u16 - 1);
+            hwa::debug!(" - check_replan {}", last_inserted_idx);
+            match &mut self.data[last_inserted_idx as usize] {
+                PlanEntry::PlannedMove(old_data) => {
+                    old_data.motion_profile.recalculate();
+                    hwa::debug!(" -- chained")
+                }
+                // Starts from rest
+                PlanEntry::Executing(_) | PlanEntry::Homing | PlanEntry::Dwell(_) | PlanEntry::Empty => {
+                    hwa::debug!(" -- not chained")
+                }
+                #[cfg(feature = "with-pen-plotter")]
+                PlanEntry::Pen(_) => {
+                    hwa::debug!(" -- not chained")
+                }
+            }
+        }
+        self.used += 1;
+        index
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -561,6 +1925,8 @@ pub enum MovType {
     Move,
     Homing,
     Dwell,
+    #[cfg(feature = "with-pen-plotter")]
+    Pen,
 }
 
 #[allow(unused)]
@@ -569,7 +1935,9 @@ pub enum PlanEntry {
     Empty,
     PlannedMove(Segment),
     Homing,
-    Dwell,
+    Dwell(Option<u32>),
+    #[cfg(feature = "with-pen-plotter")]
+    Pen(bool),
     Executing(MovType),
 }
 
@@ -595,4 +1963,396 @@ impl core::ops::Deref for MotionPlannerRef {
     fn deref(&self) -> &Self::Target {
         self.inner
     }
-}
\ No newline at end of file
+}
+
+/***
+Largest live speed factor for a move, given the ratios of the machine limits to its planned velocity,
+acceleration and jerk. Scaling the profile time by k scales the velocity by k, the acceleration by k^2 and the
+jerk by k^3. Never below 1, as the move was planned within the limits
+ */
+fn speed_factor_limit(v_ratio: Real, a_ratio: Real, j_ratio: Real) -> Real {
+    let a_factor = a_ratio.sqrt().unwrap_or(ZERO);
+    let j_factor = Real::from_f32(F32::from(j_ratio.to_f64() as f32).cbrt().into());
+    core::cmp::max(core::cmp::min(v_ratio, core::cmp::min(a_factor, j_factor)), Real::one())
+}
+
+#[test]
+pub fn speed_factor_limit_test() {
+    let close = |a: Real, b: Real| (a - b).abs() < Real::from_lit(1, 3);
+    // Speed bound
+    assert!(close(speed_factor_limit(Real::from_lit(15, 1), Real::from_lit(4, 0), Real::from_lit(8, 0)), Real::from_lit(15, 1)));
+    // Acceleration bound
+    assert!(close(speed_factor_limit(Real::from_lit(3, 0), Real::from_lit(225, 2), Real::from_lit(8, 0)), Real::from_lit(15, 1)));
+    // Jerk bound
+    assert!(close(speed_factor_limit(Real::from_lit(3, 0), Real::from_lit(9, 0), Real::from_lit(8, 0)), Real::from_lit(2, 0)));
+    // Planned at the limits: no speed up
+    assert!(close(speed_factor_limit(Real::one(), Real::one(), Real::one()), Real::one()));
+}
+
+#[cfg(test)]
+fn test_segment(distance: i64) -> Segment {
+    let constraints = Constraints {
+        v_max: Real::new(100, 0),
+        a_max: Real::new(1000, 0),
+        j_max: Real::new(5000, 0),
+    };
+    let q1 = Real::new(distance, 0);
+    Segment::new(
+        SegmentData {
+            speed_enter_sps: 0,
+            speed_exit_sps: 0,
+            total_steps: distance as u32,
+            vdir: TVector::from_coords(Some(Real::one()), None, None, None),
+            src_pos: TVector::zero(),
+            dest_pos: TVector::from_coords(Some(q1), Some(ZERO), Some(ZERO), Some(ZERO)),
+            a_max: constraints.a_max,
+            j_max: constraints.j_max,
+            speed_factor_max: Real::one(),
+            e_drive: 0,
+            #[cfg(feature = "with-laser")]
+            laser: LaserPower::Off,
+            probe: None,
+        },
+        SCurveMotionProfile::compute(q1, ZERO, ZERO, &constraints).unwrap(),
+    )
+}
+
+/// A planner on a driver with untraced native pins and its own event bus
+#[cfg(all(test, feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+fn test_planner() -> MotionPlanner {
+    use printhor_hwa_common::{ControllerMutex, ControllerRef, EventBus, PubSubType};
+    let bus: &'static PubSubType = alloc::boxed::Box::leak(alloc::boxed::Box::new(PubSubType::new()));
+    let event_bus = EventBusRef::new(ControllerRef::new(
+        alloc::boxed::Box::leak(alloc::boxed::Box::new(ControllerMutex::new(EventBus::new(bus))))
+    ));
+    MotionPlanner::new(event_bus, hwa::drivers::motion_driver::test_driver(hwa::drivers::PinPolarity::new()))
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn idle_hold_axes_test() {
+    embassy_futures::block_on(async {
+        let planner = test_planner();
+        planner.enable_steppers(CoordSel::XYZE).await;
+        // M17 Z S1
+        planner.set_idle_hold_axes(CoordSel::Z).await;
+        planner.disable_idle_steppers().await;
+        assert!(planner.get_enabled_axes().await == CoordSel::Z);
+        assert!(planner.event_bus.has_flags(EventFlags::Z_ENABLED).await);
+        assert!(!planner.event_bus.has_flags(EventFlags::X_ENABLED).await);
+        {
+            // Enable pins are active low
+            let mut drv = planner.motion_driver.lock().await;
+            assert!(drv.pins.z_enable_pin.is_set_low());
+            assert!(drv.pins.x_enable_pin.is_set_high());
+            assert!(drv.pins.e_enable_pin.is_set_high());
+        }
+        // M17 Z S0: powered off on the next idle timeout
+        planner.set_idle_hold_axes(CoordSel::empty()).await;
+        planner.disable_idle_steppers().await;
+        assert!(planner.get_enabled_axes().await.is_empty());
+        assert!(planner.motion_driver.lock().await.pins.z_enable_pin.is_set_high());
+    });
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn planned_pos_tracking_test() {
+    embassy_futures::block_on(async {
+        let planner = test_planner();
+        planner.start().await;
+        planner.set_last_planned_pos(&TVector::from_coords(Some(ZERO), Some(ZERO), Some(ZERO), Some(Real::new(5, 0)))).await;
+
+        let segment = test_segment(10);
+        let queued = planner.schedule_raw_move(ScheduledMove::Move(segment.segment_data, segment.motion_profile), false).await;
+        assert!(matches!(queued, Ok(CodeExecutionSuccess::QUEUED)));
+        let pos = planner.get_last_planned_pos().await.unwrap();
+        assert_eq!(pos.x, Some(Real::new(10, 0)));
+        assert_eq!(pos.y, Some(ZERO));
+        assert_eq!(pos.z, Some(ZERO));
+        // E is relative and never tracked
+        assert_eq!(pos.e, Some(Real::new(5, 0)));
+
+        // The next move starts where the queued one ends, not where the stepper is
+        let mut segment = test_segment(20);
+        segment.segment_data.dest_pos = TVector::from_coords(Some(Real::new(30, 0)), None, Some(Real::new(2, 0)), None);
+        planner.schedule_raw_move(ScheduledMove::Move(segment.segment_data, segment.motion_profile), false).await.unwrap();
+        let pos = planner.get_last_planned_pos().await.unwrap();
+        assert_eq!(pos.x, Some(Real::new(30, 0)));
+        assert_eq!(pos.y, Some(ZERO));
+        assert_eq!(pos.z, Some(Real::new(2, 0)));
+
+        // Homing resets it
+        planner.schedule_raw_move(ScheduledMove::Homing, false).await.unwrap();
+        let pos = planner.get_last_planned_pos().await.unwrap();
+        assert_eq!(pos.x, Some(ZERO));
+        assert_eq!(pos.z, Some(ZERO));
+    });
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn quick_stop_flush_test() {
+    embassy_futures::block_on(async {
+        let planner = test_planner();
+        planner.start().await;
+        planner.set_last_planned_pos(&TVector::zero()).await;
+        for distance in [10, 20] {
+            let segment = test_segment(distance);
+            planner.schedule_raw_move(ScheduledMove::Move(segment.segment_data, segment.motion_profile), false).await.unwrap();
+        }
+        assert!(!planner.event_bus.has_flags(EventFlags::MOV_QUEUE_EMPTY).await);
+        assert_eq!(planner.get_last_planned_pos().await.unwrap().x, Some(Real::new(20, 0)));
+
+        // Nothing executing: both are flushed and the next move starts where the first one would
+        planner.quick_stop().await.unwrap();
+        assert_eq!(planner.get_last_planned_pos().await.unwrap().x, Some(ZERO));
+        assert!(matches!(planner.defer_channel.try_receive(), Ok(DeferEvent::LinearMove(DeferType::Completed))));
+        assert!(matches!(planner.defer_channel.try_receive(), Ok(DeferEvent::LinearMove(DeferType::Completed))));
+        assert!(planner.defer_channel.try_receive().is_err());
+        // Returns at once, as the queue is empty
+        planner.synchronize().await;
+        assert!(planner.event_bus.has_flags(EventFlags::MOV_QUEUE_EMPTY).await);
+    });
+}
+
+#[test]
+pub fn enqueue_move_dwell_move_test() {
+    let mut rb = RingBuffer::new();
+    // Wraps around the end of the buffer, behind an executing move
+    rb.head = SEGMENT_QUEUE_SIZE - 1;
+    assert_eq!(rb.enqueue(PlanEntry::Executing(MovType::Move)), SEGMENT_QUEUE_SIZE - 1);
+    assert_eq!(rb.enqueue(PlanEntry::PlannedMove(test_segment(10))), 0);
+    assert_eq!(rb.enqueue(PlanEntry::Dwell(Some(500))), 1);
+    // Must not chain onto the dwell
+    assert_eq!(rb.enqueue(PlanEntry::PlannedMove(test_segment(20))), 2);
+    assert_eq!(rb.used, SEGMENT_QUEUE_SIZE);
+    assert!(matches!(rb.data[1], PlanEntry::Dwell(Some(500))));
+    assert!(matches!(rb.data[2], PlanEntry::PlannedMove(_)));
+}
+
+/// Takes the next entry as the stepper task does, recorded as the cycle move it comes from (Z in machine coords)
+#[cfg(all(test, feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+async fn step_cycle_move(planner: &MotionPlanner, moves: &mut alloc::vec::Vec<CycleMove>) {
+    match planner.get_current_segment_data().await {
+        ExecPlan::Segment(segment) => moves.push(match segment.segment_data.dest_pos.z {
+            None => CycleMove::RapidXY,
+            // The test feedrate is 2, far below the rapids
+            Some(z) if segment.motion_profile.v_lim > Real::from_lit(3, 0) => CycleMove::RapidZ(z),
+            Some(z) => CycleMove::FeedZ(z),
+        }),
+        ExecPlan::Dwell(ms) => moves.push(CycleMove::Dwell(ms)),
+        _ => {}
+    }
+    planner.consume_current_segment_data().await;
+}
+
+/// Drills a hole at each X (Y5) with a planner at Z10, Z-5 R2 F2, and returns what the stepper task got
+#[cfg(all(test, feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+fn drilled_moves(kind: CycleKind, cycle_return: CycleReturn, p: Option<Real>, q: Option<Real>, holes: &[Real]) -> alloc::vec::Vec<CycleMove> {
+    embassy_futures::block_on(async {
+        let planner = test_planner();
+        planner.start().await;
+        {
+            let mut cfg = planner.motion_cfg().lock().await;
+            cfg.max_speed = TVector::from_coords(Some(100), Some(100), Some(100), Some(100));
+            cfg.max_accel = TVector::from_coords(Some(60000), Some(60000), Some(60000), Some(60000));
+            cfg.max_jerk = TVector::from_coords(Some(60000), Some(60000), Some(60000), Some(60000));
+            cfg.default_travel_speed = 100;
+        }
+        planner.set_last_planned_pos(&TVector::from_coords(Some(ZERO), Some(ZERO), Some(Real::from_lit(10, 0)), Some(ZERO))).await;
+        planner.set_cycle_return(cycle_return).await;
+
+        let mut moves = alloc::vec::Vec::new();
+        for (i, x) in holes.iter().enumerate() {
+            let drill = async {
+                match i {
+                    0 => planner.start_canned_cycle(kind, &FPQRXYZ {
+                        ln: None,
+                        f: Some(Real::from_lit(2, 0)),
+                        p,
+                        q,
+                        r: Some(Real::from_lit(2, 0)),
+                        x: Some(*x),
+                        y: Some(Real::from_lit(5, 0)),
+                        z: Some(Real::from_lit(-5, 0)),
+                    }).await,
+                    _ => planner.repeat_canned_cycle(Some(*x), None).await,
+                }
+            };
+            // A hole does not fit in the queue: the stepper side runs while it is planned
+            let stepper = async {
+                loop {
+                    step_cycle_move(&planner, &mut moves).await;
+                }
+            };
+            let result = embassy_futures::select::select(drill, stepper).await;
+            assert!(matches!(result, embassy_futures::select::Either::First(Ok(_))));
+            while planner.ringbuffer.lock().await.used > 0 {
+                step_cycle_move(&planner, &mut moves).await;
+            }
+        }
+        moves
+    })
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn g81_queue_test() {
+    let (r, z) = (Real::from_lit(2, 0), Real::from_lit(-5, 0));
+    // G98: back to the initial Z
+    let moves = drilled_moves(CycleKind::Drill, CycleReturn::InitialZ, None, None, &[Real::from_lit(10, 0)]);
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(r),
+        CycleMove::FeedZ(z),
+        CycleMove::RapidZ(Real::from_lit(10, 0)),
+    ]);
+    // G99: back to R. The next hole starts there, so there is no rapid to R
+    let moves = drilled_moves(CycleKind::Drill, CycleReturn::RPlane, None, None, &[Real::from_lit(10, 0), Real::from_lit(20, 0)]);
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(r),
+        CycleMove::FeedZ(z),
+        CycleMove::RapidZ(r),
+        CycleMove::RapidXY,
+        CycleMove::FeedZ(z),
+        CycleMove::RapidZ(r),
+    ]);
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn g82_queue_test() {
+    let (r, z) = (Real::from_lit(2, 0), Real::from_lit(-5, 0));
+    let moves = drilled_moves(CycleKind::DrillDwell, CycleReturn::RPlane, Some(Real::from_lit(500, 0)), None, &[Real::from_lit(10, 0)]);
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(r),
+        CycleMove::FeedZ(z),
+        CycleMove::Dwell(500),
+        CycleMove::RapidZ(r),
+    ]);
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn g83_queue_test() {
+    let r = Real::from_lit(2, 0);
+    let moves = drilled_moves(CycleKind::Peck, CycleReturn::InitialZ, None, Some(Real::from_lit(25, 1)), &[Real::from_lit(10, 0)]);
+    assert!(moves == [
+        CycleMove::RapidXY,
+        CycleMove::RapidZ(r),
+        CycleMove::FeedZ(Real::from_lit(-5, 1)),
+        CycleMove::RapidZ(r),
+        CycleMove::RapidZ(Real::from_lit(-25, 2)),
+        CycleMove::FeedZ(Real::from_lit(-3, 0)),
+        CycleMove::RapidZ(r),
+        CycleMove::RapidZ(Real::from_lit(-275, 2)),
+        CycleMove::FeedZ(Real::from_lit(-5, 0)),
+        CycleMove::RapidZ(Real::from_lit(10, 0)),
+    ]);
+}
+
+#[cfg(feature = "with-pen-plotter")]
+#[test]
+pub fn enqueue_move_pen_move_test() {
+    let mut rb = RingBuffer::new();
+    rb.enqueue(PlanEntry::PlannedMove(test_segment(10)));
+    rb.enqueue(PlanEntry::Pen(true));
+    // Must not chain onto the pen lift
+    assert_eq!(rb.enqueue(PlanEntry::PlannedMove(test_segment(20))), 2);
+    assert_eq!(rb.used, 3);
+    assert!(matches!(rb.data[1], PlanEntry::Pen(true)));
+}
+
+#[test]
+pub fn unpark_keeps_workpiece_position_test() {
+    let saved = ParkedState {
+        pos: TVector::from_coords(Some(Real::from_lit(50, 0)), Some(Real::from_lit(40, 0)), Some(Real::from_lit(2, 0)), None),
+        work_coords: WorkCoords::new(),
+        tool: Some(0),
+        modal: ModalState::new(),
+        retracted: None,
+        unloaded: false,
+    };
+    // Same offset: back to the same machine position
+    let pos = saved.return_pos(&saved.work_coords.offset());
+    assert_eq!(pos.x, saved.pos.x);
+    assert_eq!(pos.z, saved.pos.z);
+    // T1 is 20 mm to the right of T0: the head returns 20 mm to the left
+    let mut tool = WorkCoords::new();
+    tool.tool = TVector::from_coords(Some(Real::from_lit(20, 0)), Some(ZERO), Some(ZERO), None);
+    let pos = saved.return_pos(&tool.offset());
+    assert_eq!(pos.x, Some(Real::from_lit(30, 0)));
+    assert_eq!(pos.y, Some(Real::from_lit(40, 0)));
+    assert_eq!(pos.z, Some(Real::from_lit(2, 0)));
+    assert!(pos.e.is_none());
+}
+
+#[test]
+pub fn unpark_restores_work_coords_test() {
+    let mut work_coords = WorkCoords::new();
+    work_coords.active = 1;
+    work_coords.tool_length = Real::from_lit(5, 0);
+    let saved = ParkedState {
+        pos: TVector::from_coords(Some(ZERO), Some(ZERO), Some(ZERO), None),
+        work_coords,
+        tool: Some(0),
+        modal: ModalState::new(),
+        retracted: None,
+        unloaded: false,
+    };
+    // G54 and G49 while parked are undone
+    let current = WorkCoords::new();
+    let resumed = saved.resume_coords(&current, Some(0));
+    assert_eq!(resumed.active, 1);
+    assert_eq!(resumed.tool_length, Real::from_lit(5, 0));
+    // After a tool change, the offsets of the new tool are kept
+    let mut current = WorkCoords::new();
+    current.tool_length = Real::from_lit(7, 0);
+    current.tool = TVector::from_coords(Some(Real::from_lit(20, 0)), Some(ZERO), Some(ZERO), None);
+    let resumed = saved.resume_coords(&current, Some(1));
+    assert_eq!(resumed.active, 1);
+    assert_eq!(resumed.tool_length, Real::from_lit(7, 0));
+    assert_eq!(resumed.tool.x, Some(Real::from_lit(20, 0)));
+}
+
+#[cfg(all(feature = "native",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn modal_target_test() {
+    embassy_futures::block_on(async {
+        let planner = test_planner();
+        planner.motion_st.lock().await.last_planned_pos.replace(
+            TVector::from_coords(Some(Real::from_lit(10, 0)), Some(Real::from_lit(10, 0)), Some(Real::from_lit(1, 0)), Some(ZERO))
+        );
+        let target = TVector::from_coords(Some(Real::from_lit(5, 0)), None, None, Some(Real::from_lit(2, 0)));
+        // G90 M83
+        let p1 = planner.modal_target(&target).await.unwrap();
+        assert_eq!(p1.x, Some(Real::from_lit(5, 0)));
+        assert!(p1.y.is_none());
+        assert_eq!(p1.e, Some(Real::from_lit(2, 0)));
+        // G91 M82 G92 E1
+        planner.set_relative_positioning(true).await;
+        planner.set_relative_extrusion(false).await;
+        planner.set_e_position(Real::from_lit(1, 0)).await;
+        let p1 = planner.modal_target(&target).await.unwrap();
+        assert_eq!(p1.x, Some(Real::from_lit(15, 0)));
+        assert!(p1.y.is_none());
+        assert!(p1.z.is_none());
+        assert_eq!(p1.e, Some(Real::from_lit(1, 0)));
+        // Same E again: nothing to extrude
+        let p1 = planner.modal_target(&target).await.unwrap();
+        assert_eq!(p1.e, Some(ZERO));
+    });
+}
@@ -2,6 +2,29 @@
 use crate::math::Real;
 use crate::planner::SCurveMotionProfile;
 use crate::tgeo::TVector;
+#[cfg(feature = "with-laser")]
+use crate::hwa::controllers::motion::raster::RasterScan;
+
+/// Laser power applied while a segment executes
+#[cfg(feature = "with-laser")]
+#[derive(Clone, Copy, PartialEq)]
+pub enum LaserPower {
+    /// Travel move or laser mode off
+    Off,
+    /// Fraction of the max power (M3)
+    Constant(Real),
+    /// Fraction of the max power at cruise speed, scaled to the instantaneous velocity (M4)
+    Dynamic(Real),
+    /// Per pixel power of a raster scanline (G7)
+    Raster(RasterScan),
+}
+
+/// Straight probe move (G38.x): the segment stops as soon as the probe input changes
+#[derive(Clone, Copy, PartialEq)]
+pub struct ProbeMode {
+    /// Stop when the probe triggers (G38.2/G38.3). Otherwise, when it is released (G38.4/G38.5)
+    pub toward: bool,
+}
 
 #[allow(unused)]
 #[derive(Clone, Copy)]
@@ -14,7 +37,21 @@ pub struct SegmentData {
     pub total_steps: u32,
 
     pub vdir: TVector<Real>,
+    /// Planned position when the segment starts
+    pub src_pos: TVector<Real>,
+    /// Planned position when the segment ends. Becomes the last planned position once queued, so the next move starts from it
     pub dest_pos: TVector<Real>,
+    /// Acceleration and jerk limits of the move, also honored when a feed hold stops it midway
+    pub a_max: Real,
+    pub j_max: Real,
+    /// Largest live speed factor (M220) keeping the move within the machine speed, acceleration and jerk
+    pub speed_factor_max: Real,
+    /// Extruder drive moved by E: the one of the tool selected when planned
+    pub e_drive: u8,
+    #[cfg(feature = "with-laser")]
+    pub laser: LaserPower,
+    /// Set for probe moves
+    pub probe: Option<ProbeMode>,
 }
 
 #[allow(unused)]
@@ -0,0 +1,36 @@
+//! Pen plotter: a servo lifts and lowers the pen, driven by the Z moves or M3/M5
+use crate::math::Real;
+
+#[derive(Clone, Copy)]
+pub struct PenConfig {
+    /// Servo angle with the pen up
+    pub(crate) up_angle: u16,
+    /// Servo angle with the pen down
+    pub(crate) down_angle: u16,
+    /// Wait after lifting the pen, in milliseconds
+    pub(crate) up_delay_ms: u32,
+    /// Wait after lowering the pen, in milliseconds
+    pub(crate) down_delay_ms: u32,
+    /// Z (work coordinates) at or below which the pen is down
+    pub(crate) z_down: Real,
+}
+
+impl PenConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            up_angle: 90,
+            down_angle: 30,
+            up_delay_ms: 150,
+            down_delay_ms: 150,
+            z_down: Real::zero(),
+        }
+    }
+
+    /// The servo angle and settle delay (milliseconds) of the given pen state
+    pub fn target(&self, down: bool) -> (u16, u32) {
+        match down {
+            true => (self.down_angle, self.down_delay_ms),
+            false => (self.up_angle, self.up_delay_ms),
+        }
+    }
+}
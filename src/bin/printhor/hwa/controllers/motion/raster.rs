@@ -0,0 +1,61 @@
+//! Raster laser engraving (G7): a scanline of pixel intensities burned in one constant velocity pass
+use alloc::vec::Vec;
+use crate::math::Real;
+
+/// Scanlines held while their segments are queued. One more than the queued segments, so that a slot is always
+/// free for the line being planned
+pub const RASTER_SLOTS: usize = 5;
+
+/// Where the pixels of a raster segment are
+#[derive(Clone, Copy, PartialEq)]
+pub struct RasterScan {
+    /// Slot of the pixels in [RasterLines]
+    pub(crate) slot: u8,
+    /// Fraction of the max power for a 255 pixel
+    pub(crate) power: Real,
+    /// Distance along the segment where the first pixel starts. The overscan before it is for the acceleration
+    pub(crate) start: Real,
+    /// Distance covered by the pixels
+    pub(crate) length: Real,
+}
+
+impl RasterScan {
+    /// Power at the given distance along the segment. Zero in the overscan
+    pub fn power_at(&self, pixels: &[u8], pos: Real) -> Real {
+        if pixels.is_empty() || self.length.is_zero() || pos < self.start {
+            return Real::zero();
+        }
+        let n = Real::new(pixels.len() as i64, 0);
+        match ((pos - self.start) * n / self.length).floor().to_i32() {
+            Some(idx) if (idx as usize) < pixels.len() => {
+                self.power * Real::new(pixels[idx as usize] as i64, 0) / Real::new(255, 0)
+            }
+            _ => Real::zero(),
+        }
+    }
+}
+
+pub struct RasterLines {
+    lines: [Option<Vec<u8>>; RASTER_SLOTS],
+}
+
+impl RasterLines {
+    pub(crate) const fn new() -> Self {
+        const EMPTY: Option<Vec<u8>> = None;
+        Self {
+            lines: [EMPTY; RASTER_SLOTS],
+        }
+    }
+
+    /// Keeps the pixels in a free slot
+    pub fn store(&mut self, pixels: Vec<u8>) -> Option<u8> {
+        let slot = self.lines.iter().position(|l| l.is_none())?;
+        self.lines[slot] = Some(pixels);
+        Some(slot as u8)
+    }
+
+    /// Takes the pixels out, freeing the slot
+    pub fn take(&mut self, slot: u8) -> Option<Vec<u8>> {
+        self.lines.get_mut(slot as usize).and_then(|l| l.take())
+    }
+}
@@ -0,0 +1,59 @@
+//! CNC tool length offsets (G43/G49) and the manual tool change (M6)
+use crate::math::Real;
+use crate::tgeo::TVector;
+
+/// Max number of tools with a measured length
+pub const MAX_TOOL_LENGTHS: usize = 16;
+
+/// What M6 does
+#[derive(Clone, Copy)]
+pub struct ManualToolChangeConfig {
+    /// Where to wait for the user, in machine coordinates. Z is reached first. Unset coords are left untouched
+    pub(crate) position: TVector<Real>,
+    /// Measure the length of the new tool after the change
+    pub(crate) probe: bool,
+    /// XY of the tool setter, in machine coordinates
+    pub(crate) probe_position: TVector<Real>,
+    /// Machine Z where the probe move gives up
+    pub(crate) probe_z: Real,
+    /// Probe feedrate. When None, default travel speed is used
+    pub(crate) probe_speed: Option<Real>,
+}
+
+impl ManualToolChangeConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            position: TVector::new(),
+            probe: false,
+            probe_position: TVector::new(),
+            probe_z: Real::zero(),
+            probe_speed: None,
+        }
+    }
+}
+
+pub struct ToolLengths {
+    /// Measured lengths, relative to the reference tool
+    pub(crate) lengths: [Option<Real>; MAX_TOOL_LENGTHS],
+    /// Machine Z where the reference (first probed) tool triggered the tool setter
+    pub(crate) reference_z: Option<Real>,
+    /// Tool in the spindle
+    pub(crate) current: Option<u8>,
+    /// Tool requested by M6, until the user confirms the change (M108)
+    pub(crate) pending: Option<u8>,
+}
+
+impl ToolLengths {
+    pub(crate) const fn new() -> Self {
+        Self {
+            lengths: [None; MAX_TOOL_LENGTHS],
+            reference_z: None,
+            current: None,
+            pending: None,
+        }
+    }
+
+    pub fn get(&self, tool: u8) -> Option<Real> {
+        self.lengths.get(tool as usize).and_then(|l| *l)
+    }
+}
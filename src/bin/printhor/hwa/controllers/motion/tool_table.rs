@@ -0,0 +1,82 @@
+//! Tool table (M563, G10 P, Tn)
+use crate::math::Real;
+use crate::tgeo::TVector;
+
+/// Max number of tools that can be defined
+pub const MAX_TOOLS: usize = 4;
+
+/// Heater number of the hotend (RepRapFirmware numbering: H0 is the bed)
+pub const HOTEND_HEATER: u8 = 1;
+
+/// Extruder drives that can be given to a tool. The second one (E1) needs a board with its pins
+#[cfg(feature = "with-e1")]
+pub const E_DRIVES: u8 = 2;
+#[cfg(not(feature = "with-e1"))]
+pub const E_DRIVES: u8 = 1;
+
+#[derive(Clone, Copy)]
+pub struct ToolConfig {
+    /// Extruder drive moved by E while the tool is selected
+    pub(crate) drive: Option<u8>,
+    /// Heater of the tool
+    pub(crate) heater: Option<u8>,
+    /// Nozzle position relative to the head reference. Subtracted from the user coordinates to get the machine ones
+    pub(crate) offset: TVector<Real>,
+    /// Temperature when selected
+    pub(crate) active_temp: Option<Real>,
+    /// Temperature when not selected
+    pub(crate) standby_temp: Option<Real>,
+}
+
+impl ToolConfig {
+    pub(crate) const fn new(drive: Option<u8>, heater: Option<u8>) -> Self {
+        Self {
+            drive,
+            heater,
+            offset: TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None),
+            active_temp: None,
+            standby_temp: None,
+        }
+    }
+}
+
+/// What Tn does around the tool switch
+#[derive(Clone, Copy)]
+pub struct ToolChangeConfig {
+    /// Retract before and recover after the switch
+    pub(crate) retract: bool,
+    /// Park before and return after the switch (implies retract)
+    pub(crate) park: bool,
+}
+
+impl ToolChangeConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            retract: false,
+            park: false,
+        }
+    }
+}
+
+pub struct ToolTable {
+    pub(crate) tools: [Option<ToolConfig>; MAX_TOOLS],
+    pub(crate) change: ToolChangeConfig,
+}
+
+impl ToolTable {
+    pub(crate) const fn new() -> Self {
+        Self {
+            tools: [None; MAX_TOOLS],
+            change: ToolChangeConfig::new(),
+        }
+    }
+
+    pub fn get(&self, tool: u8) -> Option<ToolConfig> {
+        self.tools.get(tool as usize).and_then(|t| *t)
+    }
+
+    /// Number of defined tools
+    pub fn count(&self) -> u8 {
+        self.tools.iter().filter(|t| t.is_some()).count() as u8
+    }
+}
@@ -0,0 +1,71 @@
+//! Work coordinate systems (G54-G59, G10 L2/L20), G92, tool length (G43) and tool (Tn) offsets, layered on top of the machine coordinates
+use crate::math::Real;
+use crate::tgeo::{CoordSel, TVector};
+
+/// Number of work coordinate systems (G54 to G59)
+pub const WCS_COUNT: usize = 6;
+
+#[derive(Clone, Copy)]
+pub struct WorkCoords {
+    /// Origin of each work coordinate system, in machine coordinates
+    pub(crate) offsets: [TVector<Real>; WCS_COUNT],
+    /// Index of the selected system (0 is G54)
+    pub(crate) active: u8,
+    /// G92 offset, added to the selected system
+    pub(crate) g92: TVector<Real>,
+    /// Whether the G92 offset applies (G92.2 suspends it)
+    pub(crate) g92_enabled: bool,
+    /// Tool length offset added to Z (G43). Zero after G49
+    pub(crate) tool_length: Real,
+    /// Offset of the selected tool (Tn), subtracted from the user coordinates
+    pub(crate) tool: TVector<Real>,
+}
+
+impl WorkCoords {
+    pub(crate) const fn new() -> Self {
+        Self {
+            offsets: [TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None); WCS_COUNT],
+            active: 0,
+            g92: TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None),
+            g92_enabled: true,
+            tool_length: Real::zero(),
+            tool: TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(Real::zero()), None),
+        }
+    }
+
+    /// G-Code number of the selected system (54 to 59)
+    #[inline]
+    pub fn active_code(&self) -> u8 {
+        54 + self.active
+    }
+
+    /// The tool length and selected tool offsets as a vector
+    #[inline]
+    pub fn tool_offset(&self) -> TVector<Real> {
+        TVector::from_coords(Some(Real::zero()), Some(Real::zero()), Some(self.tool_length), None)
+            - self.tool.map_nan(Real::zero())
+    }
+
+    /// Total offset from machine to work coordinates
+    pub fn offset(&self) -> TVector<Real> {
+        let wcs = self.offsets[self.active as usize].map_nan(Real::zero()) + self.tool_offset();
+        match self.g92_enabled {
+            true => wcs + self.g92.map_nan(Real::zero()),
+            false => wcs,
+        }
+    }
+
+    /// Converts the given coords (the unset ones are kept unset). E is not affected
+    pub fn to_machine(&self, pos: &TVector<Real>) -> TVector<Real> {
+        let mut converted = *pos;
+        converted.assign_if_set(CoordSel::XYZ, &(*pos + self.offset()));
+        converted
+    }
+
+    /// Converts the given coords (the unset ones are kept unset). E is not affected
+    pub fn to_work(&self, pos: &TVector<Real>) -> TVector<Real> {
+        let mut converted = *pos;
+        converted.assign_if_set(CoordSel::XYZ, &(*pos - self.offset()));
+        converted
+    }
+}
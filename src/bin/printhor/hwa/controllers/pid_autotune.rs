@@ -0,0 +1,188 @@
+//! Heater PID gains (M301/M304) and the relay feedback autotune (M303), as in Åström-Hägglund:
+//! the heater is switched between two power levels around the target so that the temperature oscillates.
+//! The ultimate gain and period of the oscillation give the gains by the Ziegler-Nichols rules
+use core::f32::consts::PI;
+
+/// A switch only counts after this long in the current phase, so that noise does not toggle the relay
+const MIN_PHASE_MS: u64 = 5_000;
+/// Autotune fails when a phase lasts longer
+const MAX_PHASE_MS: u64 = 20 * 60 * 1000;
+/// Autotune fails when the temperature goes this far above the target
+const MAX_OVERSHOOT: f32 = 30.0;
+
+/// Gains of the heater PID, with the error in ºC and the output in % of the power.
+/// Ki is per second and Kd in seconds, so they do not depend on the control period
+#[derive(Clone, Copy, PartialEq)]
+pub struct PidGains {
+    pub(crate) kp: f32,
+    pub(crate) ki: f32,
+    pub(crate) kd: f32,
+}
+
+impl PidGains {
+    pub(crate) const fn new() -> Self {
+        Self {
+            kp: 5.0,
+            ki: 0.005,
+            kd: 3.0,
+        }
+    }
+}
+
+pub enum AutotuneStep {
+    /// Keep going with this power (fraction of the max)
+    Power(f32),
+    Done(PidGains),
+    /// Temperature out of bounds, sensor fault or no oscillation
+    Failed,
+}
+
+pub struct RelayAutotune {
+    target: f32,
+    cycles: u8,
+    cycle: u8,
+    heating: bool,
+    /// Middle power level and swing around it, as fractions of the max power
+    bias: f32,
+    d: f32,
+    /// When the last heating and cooling phases started
+    t_heat: u64,
+    t_cool: u64,
+    t_high: u64,
+    t_low: u64,
+    max: f32,
+    min: f32,
+    gains: Option<PidGains>,
+}
+
+impl RelayAutotune {
+    pub fn new(target: f32, cycles: u8) -> Self {
+        Self {
+            target,
+            cycles,
+            cycle: 0,
+            heating: true,
+            bias: 0.5,
+            d: 0.5,
+            t_heat: 0,
+            t_cool: 0,
+            t_high: 0,
+            t_low: 0,
+            max: target,
+            min: target,
+            gains: None,
+        }
+    }
+
+    /// Feeds a reading taken at `now_ms` since the start
+    pub fn update(&mut self, temp: f32, now_ms: u64) -> AutotuneStep {
+        if temp.is_nan() || temp > self.target + MAX_OVERSHOOT {
+            return AutotuneStep::Failed;
+        }
+        self.max = self.max.max(temp);
+        self.min = self.min.min(temp);
+
+        if self.heating && temp > self.target && now_ms - self.t_heat > MIN_PHASE_MS {
+            self.heating = false;
+            self.t_cool = now_ms;
+            self.t_high = self.t_cool - self.t_heat;
+            self.max = temp;
+        }
+        else if !self.heating && temp < self.target && now_ms - self.t_cool > MIN_PHASE_MS {
+            self.heating = true;
+            self.t_heat = now_ms;
+            self.t_low = self.t_heat - self.t_cool;
+            if self.cycle > 0 {
+                // Balance the phases, so the oscillation is centered on the target
+                let period = (self.t_high + self.t_low) as f32;
+                self.bias = (self.bias + self.d * (self.t_high as f32 - self.t_low as f32) / period).clamp(0.1, 0.9);
+                self.d = if self.bias > 0.5 { 1.0 - self.bias } else { self.bias };
+                if self.cycle > 2 && self.max > self.min {
+                    // The first cycles are not settled yet
+                    let ku = 4.0 * self.d * 100.0 / (PI * (self.max - self.min) / 2.0);
+                    let tu = period / 1000.0;
+                    let kp = 0.6 * ku;
+                    self.gains = Some(PidGains {
+                        kp,
+                        ki: 2.0 * kp / tu,
+                        kd: kp * tu / 8.0,
+                    });
+                }
+            }
+            self.cycle += 1;
+            self.min = temp;
+            if self.cycle > self.cycles {
+                return match self.gains {
+                    Some(gains) => AutotuneStep::Done(gains),
+                    None => AutotuneStep::Failed,
+                };
+            }
+        }
+
+        let phase_start = if self.heating { self.t_heat } else { self.t_cool };
+        if now_ms - phase_start > MAX_PHASE_MS {
+            return AutotuneStep::Failed;
+        }
+        match self.heating {
+            true => AutotuneStep::Power(self.bias + self.d),
+            false => AutotuneStep::Power(self.bias - self.d),
+        }
+    }
+}
+
+/// Simulated heater: first order plant (300ºC over ambient at full power, 60s time constant) with a 5s dead time
+#[cfg(test)]
+fn simulate_autotune(target: f32, cycles: u8) -> AutotuneStep {
+    const DT_MS: u64 = 250;
+    let mut delayed = [0.0f32; 20];
+    let mut temp = 25.0f32;
+    let mut tuner = RelayAutotune::new(target, cycles);
+    for i in 0..(4 * 3600 * 1000 / DT_MS) {
+        match tuner.update(temp, i * DT_MS) {
+            AutotuneStep::Power(power) => {
+                let applied = delayed[(i % 20) as usize];
+                delayed[(i % 20) as usize] = power;
+                temp += (DT_MS as f32 / 1000.0) * (applied * 300.0 - (temp - 25.0)) / 60.0;
+            }
+            step => return step,
+        }
+    }
+    AutotuneStep::Failed
+}
+
+#[test]
+pub fn relay_autotune_converges_test() {
+    match simulate_autotune(200.0, 5) {
+        AutotuneStep::Done(gains) => {
+            assert!(gains.kp > 0.0 && gains.ki > 0.0 && gains.kd > 0.0);
+            // Ziegler-Nichols: Ki = 2 Kp / Tu and Kd = Kp Tu / 8, so Ki * Kd = Kp^2 / 4
+            assert!((gains.ki * gains.kd - gains.kp * gains.kp / 4.0).abs() < 1e-3 * gains.kp * gains.kp);
+        }
+        _ => panic!("autotune did not converge"),
+    }
+}
+
+#[test]
+pub fn relay_autotune_relay_test() {
+    let mut tuner = RelayAutotune::new(200.0, 5);
+    // Full power below the target
+    assert!(matches!(tuner.update(25.0, 0), AutotuneStep::Power(p) if p == 1.0));
+    // Above the target, but not long enough in the phase to switch
+    assert!(matches!(tuner.update(201.0, MIN_PHASE_MS - 1000), AutotuneStep::Power(p) if p == 1.0));
+    // Switches off
+    assert!(matches!(tuner.update(201.0, MIN_PHASE_MS + 1000), AutotuneStep::Power(p) if p == 0.0));
+}
+
+#[test]
+pub fn relay_autotune_failures_test() {
+    let mut tuner = RelayAutotune::new(200.0, 5);
+    assert!(matches!(tuner.update(f32::NAN, 0), AutotuneStep::Failed));
+
+    let mut tuner = RelayAutotune::new(200.0, 5);
+    assert!(matches!(tuner.update(200.0 + MAX_OVERSHOOT + 1.0, 0), AutotuneStep::Failed));
+
+    // Never reaches the target
+    let mut tuner = RelayAutotune::new(200.0, 5);
+    assert!(matches!(tuner.update(25.0, MAX_PHASE_MS), AutotuneStep::Power(_)));
+    assert!(matches!(tuner.update(25.0, MAX_PHASE_MS + 1), AutotuneStep::Failed));
+}
@@ -85,6 +85,24 @@ impl PrinterController {
         self.channel.wait().await
     }
 
+    /// Takes a pending event, if any, without waiting
+    #[inline]
+    pub(crate) fn try_take(&self) -> Option<PrinterControllerEvent> {
+        self.channel.try_take()
+    }
+
+    /// Requests the running job to pause (park) at the next gcode
+    #[inline]
+    pub(crate) fn pause(&self) {
+        self.channel.signal(PrinterControllerEvent::Pause);
+    }
+
+    /// Requests the paused job to resume
+    #[inline]
+    pub(crate) fn resume(&self) {
+        self.channel.signal(PrinterControllerEvent::Resume);
+    }
+
     #[allow(unused)]
     #[inline]
     pub(crate) async fn complete(&mut self) {
@@ -12,7 +12,7 @@ where TimPeri: Pwm + 'static
 }
 
 impl<TimPeri> PwmController<TimPeri>
-    where TimPeri: Pwm + 'static,
+    where TimPeri: Pwm<Duty = u16> + 'static,
           <TimPeri as Pwm>::Channel: Copy
 {
     pub fn new(pwm: ControllerRef<TimPeri>, pwm_chan: <TimPeri as Pwm>::Channel) -> Self {
@@ -23,9 +23,19 @@ impl<TimPeri> PwmController<TimPeri>
         }
     }
 
-    pub async fn set_power(&self, _power: f32) {
-        let mut x = self.pwm.lock().await;
-        x.disable(self.pwm_chan.clone());
+    /// Sets the duty cycle as a fraction (0.0 to 1.0) of the max. Zero disables the channel
+    pub async fn set_power(&mut self, power: f32) {
+        let mut pwm = self.pwm.lock().await;
+        if power > 0.0f32 {
+            let max = pwm.get_max_duty() as f32;
+            pwm.set_duty(self.pwm_chan, (power.min(1.0f32) * max) as u16);
+            pwm.enable(self.pwm_chan);
+            self.enabled = true;
+        }
+        else {
+            pwm.disable(self.pwm_chan);
+            self.enabled = false;
+        }
     }
 
     #[inline]
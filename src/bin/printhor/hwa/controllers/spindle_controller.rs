@@ -0,0 +1,147 @@
+//! CNC spindle (M3/M4/M5): speed in RPM mapped to the PWM duty, a direction output and spin-up/down dwells
+use embassy_time::{Duration, Timer};
+use crate::hwa::devices::{PwmSpindle, SpindleDirPin};
+use super::pwm_controller::PwmController;
+
+/// Max number of points of the RPM to duty curve
+pub const SPINDLE_CURVE_POINTS: usize = 4;
+
+#[derive(Clone, Copy)]
+pub struct SpindleConfig {
+    /// (RPM, duty) points by ascending RPM. The duty is interpolated linearly in between and clamped out of range
+    pub(crate) curve: [(u32, f32); SPINDLE_CURVE_POINTS],
+    /// Number of points in use
+    pub(crate) curve_len: usize,
+    /// Time to reach the speed after starting, speeding up or reversing, in ms
+    pub(crate) spin_up_ms: u32,
+    /// Time to stop, in ms
+    pub(crate) spin_down_ms: u32,
+}
+
+impl SpindleConfig {
+    pub(crate) const fn new() -> Self {
+        Self {
+            curve: [(0, 0.0f32), (24000, 1.0f32), (0, 0.0f32), (0, 0.0f32)],
+            curve_len: 2,
+            spin_up_ms: 3000,
+            spin_down_ms: 3000,
+        }
+    }
+
+    /// Replaces the curve. Rejected if empty, too long or not sorted by RPM
+    pub fn set_curve(&mut self, points: &[(u32, f32)]) -> bool {
+        if points.is_empty() || points.len() > SPINDLE_CURVE_POINTS || points.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return false;
+        }
+        self.curve[..points.len()].copy_from_slice(points);
+        self.curve_len = points.len();
+        true
+    }
+
+    /// Duty (0.0 to 1.0) for the given speed
+    pub fn map_rpm(&self, rpm: u32) -> f32 {
+        let curve = &self.curve[..self.curve_len];
+        let (first, last) = (curve[0], curve[curve.len() - 1]);
+        let duty = if rpm <= first.0 {
+            first.1
+        }
+        else if rpm >= last.0 {
+            last.1
+        }
+        else {
+            curve.windows(2)
+                .find(|w| rpm <= w[1].0)
+                .map(|w| w[0].1 + (w[1].1 - w[0].1) * (rpm - w[0].0) as f32 / (w[1].0 - w[0].0) as f32)
+                .unwrap_or(last.1)
+        };
+        duty.clamp(0.0f32, 1.0f32)
+    }
+}
+
+pub struct SpindleController {
+    pwm: PwmController<PwmSpindle>,
+    dir_pin: SpindleDirPin,
+    config: SpindleConfig,
+    /// Current speed. Zero when stopped
+    rpm: u32,
+    /// Last speed requested, used when M3/M4 come without S
+    last_rpm: u32,
+    /// Clockwise (M3)
+    forward: bool,
+}
+
+impl SpindleController {
+    pub fn new(pwm: PwmController<PwmSpindle>, dir_pin: SpindleDirPin) -> Self {
+        Self {
+            pwm,
+            dir_pin,
+            config: SpindleConfig::new(),
+            rpm: 0,
+            last_rpm: 0,
+            forward: true,
+        }
+    }
+
+    /// Sets speed and direction, waiting for the spindle to settle. Reversing stops it first
+    pub async fn set_speed(&mut self, rpm: u32, forward: bool) {
+        if rpm > 0 && self.rpm > 0 && forward != self.forward {
+            self.stop().await;
+        }
+        if rpm == 0 {
+            self.stop().await;
+            return;
+        }
+        if self.rpm == 0 {
+            self.forward = forward;
+            match forward {
+                true => self.dir_pin.set_high(),
+                false => self.dir_pin.set_low(),
+            }
+        }
+        self.pwm.set_power(self.config.map_rpm(rpm)).await;
+        let speeding_up = rpm > self.rpm;
+        self.rpm = rpm;
+        self.last_rpm = rpm;
+        if speeding_up {
+            Timer::after(Duration::from_millis(self.config.spin_up_ms as u64)).await;
+        }
+    }
+
+    pub async fn stop(&mut self) {
+        if self.rpm > 0 {
+            self.pwm.set_power(0.0f32).await;
+            self.rpm = 0;
+            Timer::after(Duration::from_millis(self.config.spin_down_ms as u64)).await;
+        }
+    }
+
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.rpm > 0
+    }
+
+    #[inline]
+    pub fn get_rpm(&self) -> u32 {
+        self.rpm
+    }
+
+    #[inline]
+    pub fn get_last_rpm(&self) -> u32 {
+        self.last_rpm
+    }
+
+    #[inline]
+    pub fn is_forward(&self) -> bool {
+        self.forward
+    }
+
+    #[inline]
+    pub fn get_config(&self) -> SpindleConfig {
+        self.config
+    }
+
+    #[inline]
+    pub fn set_config(&mut self, config: SpindleConfig) {
+        self.config = config;
+    }
+}
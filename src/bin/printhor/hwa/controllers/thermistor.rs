@@ -0,0 +1,171 @@
+//! NTC thermistor conversion. The thermistor goes from the ADC pin to ground, with a pull-up resistor to the supply:
+//!
+//! V_adc = \frac{R}{R + R_{pullup}}*V_{supply}, so R = \frac{V_{adc}*R_{pullup}}{V_{supply}-V_{adc}}
+//!
+//! The resistance is then converted to degrees with the selected model
+use micromath::F32;
+
+/// Internal reference voltage, in millivolts. [crate::hwa::VREF_SAMPLE] is the ADC reading of it
+pub const VREFINT_MV: f32 = 1210.0;
+
+const KELVIN: f32 = 273.15;
+
+/// Resistance to temperature
+#[derive(Clone, Copy)]
+pub enum ThermistorModel {
+    /// β parameter equation: R25 (ohms at 25ºC) and β
+    Beta { r25: f32, beta: f32 },
+    /// Full Steinhart-Hart equation: 1/T = A + B*ln(R) + C*ln(R)^3, with T in Kelvin
+    SteinhartHart { a: f32, b: f32, c: f32 },
+    /// Piecewise linear table of (ohms, ºC), sorted by decreasing resistance
+    Table(&'static [(f32, f32)]),
+}
+
+impl ThermistorModel {
+    /// Temperature in ºC. NaN when the table does not cover the resistance
+    pub fn to_celsius(&self, r: f32) -> f32 {
+        match *self {
+            ThermistorModel::Beta { r25, beta } => {
+                let ln_r: f32 = F32::from(r / r25).ln().into();
+                1.0 / (ln_r / beta + 1.0 / (25.0 + KELVIN)) - KELVIN
+            }
+            ThermistorModel::SteinhartHart { a, b, c } => {
+                let ln_r: f32 = F32::from(r).ln().into();
+                1.0 / (a + b * ln_r + c * ln_r * ln_r * ln_r) - KELVIN
+            }
+            ThermistorModel::Table(points) => {
+                for w in points.windows(2) {
+                    let ((r0, t0), (r1, t1)) = (w[0], w[1]);
+                    if r <= r0 && r >= r1 {
+                        return t0 + (t1 - t0) * (r0 - r) / (r0 - r1);
+                    }
+                }
+                f32::NAN
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ThermistorConfig {
+    pub(crate) model: ThermistorModel,
+    /// Pull-up resistor, in ohms
+    pub(crate) pull_up: f32,
+    /// Voltage the pull-up is connected to, in millivolts
+    pub(crate) supply_mv: f32,
+    /// ADC samples averaged on each reading
+    pub(crate) oversampling: u8,
+    /// Weight of a new reading in the exponential moving average (1 = no filtering)
+    pub(crate) filter_alpha: f32,
+}
+
+impl ThermistorConfig {
+    /// A common 100K NTC (β 3950) with a 4.7K pull-up to 3.3V
+    pub(crate) const fn new() -> Self {
+        Self {
+            model: ThermistorModel::Beta { r25: 100_000.0, beta: 3950.0 },
+            pull_up: 4700.0,
+            supply_mv: 3300.0,
+            oversampling: 8,
+            filter_alpha: 0.5,
+        }
+    }
+
+    /// Thermistor resistance from the ADC pin voltage. None when the sensor is open or shorted
+    pub fn resistance(&self, v_adc_mv: f32) -> Option<f32> {
+        match v_adc_mv > 0.0 && v_adc_mv < self.supply_mv {
+            true => Some(v_adc_mv * self.pull_up / (self.supply_mv - v_adc_mv)),
+            false => None,
+        }
+    }
+}
+
+/// Exponential moving average of the readings
+pub struct TempFilter {
+    value: Option<f32>,
+}
+
+impl TempFilter {
+    pub const fn new() -> Self {
+        Self { value: None }
+    }
+
+    /// Adds a reading and returns the filtered value. Invalid (NaN) readings reset the filter and are returned as they are
+    pub fn update(&mut self, reading: f32, alpha: f32) -> f32 {
+        if reading.is_nan() {
+            self.value = None;
+            return reading;
+        }
+        let filtered = match self.value {
+            Some(v) => v + alpha * (reading - v),
+            None => reading,
+        };
+        self.value = Some(filtered);
+        filtered
+    }
+}
+
+#[cfg(test)]
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.5
+}
+
+#[test]
+pub fn thermistor_beta_test() {
+    let model = ThermistorModel::Beta { r25: 100_000.0, beta: 3950.0 };
+    assert!(close(model.to_celsius(100_000.0), 25.0));
+    assert!(close(model.to_celsius(744.6), 200.0));
+    // Lower resistance, hotter
+    assert!(model.to_celsius(10_000.0) > model.to_celsius(100_000.0));
+}
+
+#[test]
+pub fn thermistor_steinhart_hart_test() {
+    let model = ThermistorModel::SteinhartHart { a: 1.009249522e-3, b: 2.378405444e-4, c: 2.019202697e-7 };
+    assert!(close(model.to_celsius(10_000.0), 24.68));
+    assert!(close(model.to_celsius(3_000.0), 58.29));
+    // With C = 0 it is the Beta model
+    let beta = ThermistorModel::Beta { r25: 100_000.0, beta: 3950.0 };
+    let equivalent = ThermistorModel::SteinhartHart { a: 1.0 / (25.0 + KELVIN) - 11.512925 / 3950.0, b: 1.0 / 3950.0, c: 0.0 };
+    assert!(close(equivalent.to_celsius(744.6), beta.to_celsius(744.6)));
+}
+
+#[test]
+pub fn thermistor_table_test() {
+    static POINTS: [(f32, f32); 3] = [(1000.0, 100.0), (500.0, 150.0), (200.0, 200.0)];
+    let model = ThermistorModel::Table(&POINTS);
+    // Edges
+    assert!(close(model.to_celsius(1000.0), 100.0));
+    assert!(close(model.to_celsius(200.0), 200.0));
+    // Interpolated
+    assert!(close(model.to_celsius(750.0), 125.0));
+    assert!(close(model.to_celsius(300.0), 183.3));
+    // Not covered
+    assert!(model.to_celsius(1001.0).is_nan());
+    assert!(model.to_celsius(199.0).is_nan());
+    assert!(ThermistorModel::Table(&[]).to_celsius(500.0).is_nan());
+}
+
+#[test]
+pub fn thermistor_resistance_test() {
+    let cfg = ThermistorConfig::new();
+    // Half the supply: same as the pull-up
+    assert!(close(cfg.resistance(1650.0).unwrap(), 4700.0));
+    assert!(close(cfg.resistance(3300.0 / 3.0).unwrap(), 2350.0));
+    // Shorted or open
+    assert!(cfg.resistance(0.0).is_none());
+    assert!(cfg.resistance(3300.0).is_none());
+    assert!(cfg.resistance(3400.0).is_none());
+}
+
+#[test]
+pub fn temp_filter_test() {
+    let mut filter = TempFilter::new();
+    // The first reading is taken as is
+    assert_eq!(filter.update(100.0, 0.5), 100.0);
+    assert_eq!(filter.update(200.0, 0.5), 150.0);
+    assert_eq!(filter.update(150.0, 1.0), 150.0);
+    // An invalid reading goes through and restarts the average
+    assert!(filter.update(f32::NAN, 0.5).is_nan());
+    assert_eq!(filter.update(40.0, 0.5), 40.0);
+}
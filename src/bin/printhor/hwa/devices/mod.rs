@@ -29,10 +29,13 @@ pub use crate::hwa::device::PwmFan0;
 #[cfg(feature="with-fan1")]
 pub use crate::hwa::device::PwmFan1;
 
-#[cfg(feature="with-fan1")]
+#[cfg(any(feature="with-laser", feature = "with-spindle"))]
 pub use crate::hwa::device::PwmLaser;
 
-#[cfg(any(feature="with-hotend", feature = "with-hotbed", feature = "with-fan0", feature = "with-fan1"))]
+#[cfg(feature="with-spindle")]
+pub use crate::hwa::device::{PwmSpindle, SpindleDirPin};
+
+#[cfg(any(feature="with-hotend", feature = "with-hotbed", feature = "with-fan0", feature = "with-fan1", feature = "with-laser", feature = "with-spindle"))]
 pub use crate::hwa::device::{PwmTrait, PwmImpl};
 
 
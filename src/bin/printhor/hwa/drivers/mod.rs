@@ -4,4 +4,6 @@ pub(in crate::hwa) mod motion_driver;
 #[cfg(feature = "with-motion")]
 pub use motion_driver::MotionDriver;
 #[cfg(feature = "with-motion")]
-pub use motion_driver::MotionDriverParams;
\ No newline at end of file
+pub use motion_driver::MotionDriverParams;
+#[cfg(feature = "with-motion")]
+pub use motion_driver::PinPolarity;
\ No newline at end of file
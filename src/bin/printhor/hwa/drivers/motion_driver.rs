@@ -4,10 +4,55 @@ use crate::{hwa, hwi};
 use crate::hwa::{ControllerRef};
 #[cfg(feature = "with-probe")]
 use crate::hwa::controllers::ProbeTrait;
+#[cfg(feature = "with-motion")]
+use crate::tgeo::CoordSel;
+
+/// Sets the pin to the given logic level
+#[cfg(feature = "with-motion")]
+macro_rules! set_level {
+    ($pin:expr, $high:expr) => {
+        if $high { $pin.set_high() } else { $pin.set_low() }
+    };
+}
+
+/// Per-axis inversion of the pin levels. By default, direction pin high moves forward, drivers are enabled
+/// with the enable pin low (active low) and endstops read high when triggered (active high)
+#[cfg(feature = "with-motion")]
+#[derive(Clone, Copy)]
+pub struct PinPolarity {
+    /// Axes whose motor is wired in reverse (forward is dir pin low)
+    pub invert_dir: CoordSel,
+    /// Axes whose driver is enabled with the pin high
+    pub invert_enable: CoordSel,
+    /// Axes whose endstop reads low when triggered
+    pub invert_endstop: CoordSel,
+}
+
+#[cfg(feature = "with-motion")]
+impl PinPolarity {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            invert_dir: CoordSel::empty(),
+            invert_enable: CoordSel::empty(),
+            invert_endstop: CoordSel::empty(),
+        }
+    }
+
+    /// The polarity set in the board constants (see [hwi::consts])
+    pub const fn from_board() -> Self {
+        Self {
+            invert_dir: CoordSel::from_bits_truncate(hwi::consts::INVERT_DIR_AXES),
+            invert_enable: CoordSel::from_bits_truncate(hwi::consts::INVERT_ENABLE_AXES),
+            invert_endstop: CoordSel::from_bits_truncate(hwi::consts::INVERT_ENDSTOP_AXES),
+        }
+    }
+}
 
 #[cfg(feature = "with-motion")]
 pub struct MotionDriverParams {
     pub(crate) motion_device: hwi::device::MotionDevice,
+    pub(crate) pin_polarity: PinPolarity,
     #[cfg(feature = "with-probe")]
     pub(crate) probe_controller: ControllerRef<hwa::controllers::ServoController>,
     #[cfg(feature = "with-fan0")]
@@ -22,6 +67,11 @@ pub struct MotionDriver {
 
     #[cfg(feature = "with-motion")]
     pub pins: hwi::device::MotionPins,
+    #[cfg(feature = "with-motion")]
+    pub(crate) polarity: PinPolarity,
+    /// Extruder drive moved by E
+    #[cfg(feature = "with-e1")]
+    pub(crate) e_drive: u8,
     #[cfg(feature = "with-trinamic")]
     pub trinamic_controller: hwa::controllers::TrinamicController,
     #[cfg(feature = "with-probe")]
@@ -40,6 +90,9 @@ impl MotionDriver {
 
         Self {
             pins: params.motion_device.motion_pins,
+            polarity: params.pin_polarity,
+            #[cfg(feature = "with-e1")]
+            e_drive: 0,
             #[cfg(feature = "with-trinamic")]
             trinamic_controller: hwa::controllers::TrinamicController::new(params.motion_device.trinamic_uart),
             #[cfg(feature = "with-probe")]
@@ -53,41 +106,139 @@ impl MotionDriver {
         }
     }
 
+    pub fn set_pin_polarity(&mut self, polarity: PinPolarity) {
+        self.polarity = polarity;
+    }
+
+    /// Selects the extruder drive (see [hwa::controllers::E_DRIVES]) whose dir and step pins E drives from now on
+    #[inline]
+    pub fn select_e_drive(&mut self, _drive: u8) {
+        #[cfg(feature = "with-e1")]
+        {
+            self.e_drive = _drive;
+        }
+    }
+
+    /// Powers on the drivers of the given axes. E covers every extruder drive
+    pub fn enable_steppers(&mut self, axes: CoordSel) {
+        self.set_enable_level(axes, true);
+    }
+
+    /// Powers off the drivers of the given axes
+    pub fn disable_steppers(&mut self, axes: CoordSel) {
+        self.set_enable_level(axes, false);
+    }
+
+    fn set_enable_level(&mut self, axes: CoordSel, enabled: bool) {
+        let high = |axis: CoordSel| enabled == self.polarity.invert_enable.contains(axis);
+        let (x, y, z, e) = (high(CoordSel::X), high(CoordSel::Y), high(CoordSel::Z), high(CoordSel::E));
+        if axes.contains(CoordSel::X) { set_level!(self.pins.x_enable_pin, x); }
+        if axes.contains(CoordSel::Y) { set_level!(self.pins.y_enable_pin, y); }
+        if axes.contains(CoordSel::Z) { set_level!(self.pins.z_enable_pin, z); }
+        if axes.contains(CoordSel::E) {
+            set_level!(self.pins.e_enable_pin, e);
+            #[cfg(feature = "with-e1")]
+            set_level!(self.pins.e1_enable_pin, e);
+        }
+    }
+
+    /// Sets the direction of the given axes: Forward for those in `forward`, backward for the rest
+    pub fn set_forward_direction(&mut self, forward: CoordSel, axes: CoordSel) {
+        let high = |axis: CoordSel| forward.contains(axis) != self.polarity.invert_dir.contains(axis);
+        let (x, y, z, e) = (high(CoordSel::X), high(CoordSel::Y), high(CoordSel::Z), high(CoordSel::E));
+        if axes.contains(CoordSel::X) { set_level!(self.pins.x_dir_pin, x); }
+        if axes.contains(CoordSel::Y) { set_level!(self.pins.y_dir_pin, y); }
+        if axes.contains(CoordSel::Z) { set_level!(self.pins.z_dir_pin, z); }
+        if axes.contains(CoordSel::E) {
+            #[cfg(feature = "with-e1")]
+            if self.e_drive == 1 {
+                set_level!(self.pins.e1_dir_pin, e);
+                return;
+            }
+            set_level!(self.pins.e_dir_pin, e);
+        }
+    }
+
+    /// Whether the endstop of the given axis is triggered
+    pub fn endstop_triggered(&self, axis: CoordSel) -> bool {
+        let high = if axis.contains(CoordSel::X) { self.pins.x_endstop_pin.is_high() }
+        else if axis.contains(CoordSel::Y) { self.pins.y_endstop_pin.is_high() }
+        else if axis.contains(CoordSel::Z) { self.pins.z_endstop_pin.is_high() }
+        else { self.pins.e_endstop_pin.is_high() };
+        high != self.polarity.invert_endstop.contains(axis)
+    }
+
+    /// The probe input (G38.x). It shares the Z endstop input
+    #[inline]
+    pub fn probe_triggered(&self) -> bool {
+        self.endstop_triggered(CoordSel::Z)
+    }
+
+    #[inline]
+    pub fn step_high(&mut self, axes: CoordSel) {
+        if axes.contains(CoordSel::X) { self.pins.x_step_pin.set_high(); }
+        if axes.contains(CoordSel::Y) { self.pins.y_step_pin.set_high(); }
+        if axes.contains(CoordSel::Z) { self.pins.z_step_pin.set_high(); }
+        if axes.contains(CoordSel::E) {
+            #[cfg(feature = "with-e1")]
+            if self.e_drive == 1 {
+                self.pins.e1_step_pin.set_high();
+                return;
+            }
+            self.pins.e_step_pin.set_high();
+        }
+    }
+
+    #[inline]
+    pub fn step_low(&mut self, axes: CoordSel) {
+        if axes.contains(CoordSel::X) { self.pins.x_step_pin.set_low(); }
+        if axes.contains(CoordSel::Y) { self.pins.y_step_pin.set_low(); }
+        if axes.contains(CoordSel::Z) { self.pins.z_step_pin.set_low(); }
+        if axes.contains(CoordSel::E) {
+            #[cfg(feature = "with-e1")]
+            if self.e_drive == 1 {
+                self.pins.e1_step_pin.set_low();
+                return;
+            }
+            self.pins.e_step_pin.set_low();
+        }
+    }
+
     pub async fn homing_action(&mut self) -> Result<(), ()>{
         hwa::info!("Do homing");
 
         #[cfg(feature="with-laser")]
         let _on = self.laser_controller.lock().await.is_on();
 
-        self.pins.z_enable_pin.set_low();
+        self.enable_steppers(CoordSel::Z);
 
         let mut num_pulses = 0u32;
         let mut reached = false;
 
-        self.pins.z_dir_pin.set_high();
+        self.set_forward_direction(CoordSel::Z, CoordSel::Z);
 
         // Move up a little bit
         for _i in 0..2 {
-            self.pins.z_step_pin.set_high();
+            self.step_high(CoordSel::Z);
             embassy_time::Timer::after(embassy_time::Duration::from_micros(10)).await;
-            self.pins.z_step_pin.set_low();
+            self.step_low(CoordSel::Z);
             embassy_time::Timer::after(embassy_time::Duration::from_micros(1000)).await;
         }
 
         // Move down until endtop hit
-        self.pins.z_dir_pin.set_low();
+        self.set_forward_direction(CoordSel::empty(), CoordSel::Z);
         #[cfg(feature = "with-probe")]
         self.probe_controller.lock().await.probe_pin_down(300).await;
         for _i in 0..10 * 10 {
-            if self.pins.z_endstop_pin.is_high() {
+            if self.endstop_triggered(CoordSel::Z) {
                 hwa::info!(" - R");
                 reached = true;
                 break;
             }
             num_pulses += 1;
-            self.pins.z_step_pin.set_high();
+            self.step_high(CoordSel::Z);
             embassy_time::Timer::after(embassy_time::Duration::from_micros(10)).await;
-            self.pins.z_step_pin.set_low();
+            self.step_low(CoordSel::Z);
             embassy_time::Timer::after(embassy_time::Duration::from_micros(1000)).await;
         }
         hwa::info!("1.ZDone");
@@ -101,4 +252,71 @@ impl MotionDriver {
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+/// A driver on untraced native pins. Only when it needs no other controllers
+#[cfg(all(test, feature = "native", feature = "with-motion",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+pub(in crate::hwa) fn test_driver(polarity: PinPolarity) -> MotionDriver {
+    MotionDriver {
+        pins: hwi::device::MotionPins::new(),
+        polarity,
+        #[cfg(feature = "with-e1")]
+        e_drive: 0,
+    }
+}
+
+#[cfg(all(test, feature = "native", feature = "with-e1",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn e_drive_routing_test() {
+    let mut drv = test_driver(PinPolarity::new());
+    drv.set_forward_direction(CoordSel::E, CoordSel::E);
+    drv.step_high(CoordSel::E);
+    assert!(drv.pins.e_step_pin.is_set_high());
+    assert!(drv.pins.e_dir_pin.is_set_high());
+    assert!(drv.pins.e1_step_pin.is_set_low());
+    drv.step_low(CoordSel::E);
+
+    // T1 on the second drive
+    drv.select_e_drive(1);
+    drv.set_forward_direction(CoordSel::E, CoordSel::X | CoordSel::E);
+    drv.step_high(CoordSel::X | CoordSel::E);
+    assert!(drv.pins.x_step_pin.is_set_high());
+    assert!(drv.pins.e1_step_pin.is_set_high());
+    assert!(drv.pins.e1_dir_pin.is_set_high());
+    assert!(drv.pins.e_step_pin.is_set_low());
+    drv.step_low(CoordSel::X | CoordSel::E);
+    assert!(drv.pins.e1_step_pin.is_set_low());
+
+    // Both extruders are powered together
+    drv.enable_steppers(CoordSel::E);
+    assert!(drv.pins.e_enable_pin.is_set_low());
+    assert!(drv.pins.e1_enable_pin.is_set_low());
+    drv.disable_steppers(CoordSel::E);
+    assert!(drv.pins.e_enable_pin.is_set_high());
+    assert!(drv.pins.e1_enable_pin.is_set_high());
+}
+
+#[cfg(all(test, feature = "native", feature = "with-motion",
+    not(any(feature = "with-trinamic", feature = "with-probe", feature = "with-fan0", feature = "with-fan1", feature = "with-laser"))))]
+#[test]
+pub fn pin_polarity_test() {
+    let mut drv = test_driver(PinPolarity {
+        invert_dir: CoordSel::Y,
+        invert_enable: CoordSel::Y,
+        invert_endstop: CoordSel::empty(),
+    });
+    drv.set_forward_direction(CoordSel::X | CoordSel::Y, CoordSel::X | CoordSel::Y);
+    assert!(drv.pins.x_dir_pin.is_set_high());
+    assert!(drv.pins.y_dir_pin.is_set_low());
+    drv.set_forward_direction(CoordSel::empty(), CoordSel::X | CoordSel::Y);
+    assert!(drv.pins.x_dir_pin.is_set_low());
+    assert!(drv.pins.y_dir_pin.is_set_high());
+
+    drv.enable_steppers(CoordSel::X | CoordSel::Y);
+    assert!(drv.pins.x_enable_pin.is_set_low());
+    assert!(drv.pins.y_enable_pin.is_set_high());
+    drv.disable_steppers(CoordSel::X | CoordSel::Y);
+    assert!(drv.pins.x_enable_pin.is_set_high());
+    assert!(drv.pins.y_enable_pin.is_set_low());
+}
@@ -23,6 +23,9 @@ impl MachineInfo {
             machine_board: hwa::MACHINE_BOARD,
             machine_processor: hwa::MACHINE_PROCESSOR,
             machine_uuid: "00000000-0000-0000-0000-000000000000",
+            #[cfg(feature = "with-motion")]
+            extruder_count: crate::hwa::controllers::E_DRIVES,
+            #[cfg(not(feature = "with-motion"))]
             extruder_count: 1,
         }
     }
@@ -27,9 +27,15 @@ pub mod tgeo;
 pub mod ctrl;
 pub mod math;
 
+#[cfg(all(feature = "with-laser", feature = "with-spindle"))]
+compile_error!("with-laser and with-spindle share the same output and M3/M4/M5. Enable only one of them");
+
+#[cfg(all(feature = "with-grbl-protocol", not(any(feature = "with-usbserial", feature = "with-uart-port-1"))))]
+compile_error!("with-grbl-protocol requires with-usbserial or with-uart-port-1");
+
 use crate::control::control_task::ControlTaskControllers;
 use embassy_executor::Spawner;
-#[cfg(any(feature = "with-probe", feature = "with-hotbed", feature = "with-hotend", feature = "with-fan0", feature = "with-fan1"))]
+#[cfg(any(feature = "with-probe", feature = "with-hotbed", feature = "with-hotend", feature = "with-fan0", feature = "with-fan1", feature = "with-laser", feature = "with-spindle"))]
 use printhor_hwa_common::{ControllerMutex, ControllerRef};
 #[allow(unused)]
 use printhor_hwa_common::{EventBusRef, TrackedStaticCell};
@@ -50,8 +56,28 @@ use crate::hwa::controllers::printer_controller::PrinterController;
 
 ////
 
+#[cfg(not(feature = "native-simulation"))]
 #[embassy_executor::main]
 async fn main(spawner: embassy_executor::Spawner) -> ! {
+    boot(spawner).await
+}
+
+/// Simulation runs poll every executor from this thread on the virtual clock
+#[cfg(feature = "native-simulation")]
+fn main() -> ! {
+    let executor: &'static mut hwa::SimulationExecutor = Box::leak(Box::new(hwa::SimulationExecutor::new()));
+    executor.run(|spawner| {
+        spawner.spawn(boot_task(spawner)).unwrap();
+    })
+}
+
+#[cfg(feature = "native-simulation")]
+#[embassy_executor::task]
+async fn boot_task(spawner: embassy_executor::Spawner) {
+    boot(spawner).await
+}
+
+async fn boot(spawner: embassy_executor::Spawner) -> ! {
 
     hwa::init_logger();
 
@@ -187,6 +213,21 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
                                        ))
         ));
 
+    #[cfg(feature = "with-spindle")]
+    static SPINDLE_CONTROLLER_INST: TrackedStaticCell<ControllerMutex<hwa::controllers::SpindleController>> = TrackedStaticCell::new();
+    #[cfg(feature = "with-spindle")]
+        let spindle_controller = ControllerRef::new(
+        SPINDLE_CONTROLLER_INST.init( "SpindleController",
+                                   ControllerMutex::new(
+                                       hwa::controllers::SpindleController::new(
+                                           hwa::controllers::SpindlePwmController::new(
+                                               _pwm_devices.spindle.power_pwm,
+                                               _pwm_devices.spindle.power_channel,
+                                           ),
+                                           _pwm_devices.spindle.dir_pin,
+                                       ))
+        ));
+
     #[cfg(feature = "with-hotend")]
     static HOTEND_CONTROLLER_INST: TrackedStaticCell<ControllerMutex<hwa::controllers::HotendController>> = TrackedStaticCell::new();
     #[cfg(feature = "with-hotend")]
@@ -219,7 +260,7 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
         )
     );
     #[cfg(feature = "with-hotbed")]
-    hotend_controller.lock().await.init().await;
+    hotbed_controller.lock().await.init().await;
 
     #[cfg(feature = "with-motion")]
     static MPS : TrackedStaticCell<MotionPlanner> = TrackedStaticCell::new();
@@ -230,6 +271,7 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
                 event_bus.clone(),
                 hwa::drivers::MotionDriver::new(hwa::drivers::MotionDriverParams{
                     motion_device: _motion_device.motion_devices,
+                    pin_polarity: hwa::drivers::PinPolarity::from_board(),
                     #[cfg(feature = "with-probe")]
                     probe_controller: probe_controller.clone(),
                     #[cfg(feature = "with-fan0")]
@@ -263,16 +305,15 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
         fan1: fan1_controller.clone(),
         #[cfg(feature = "with-laser")]
         laser: laser_controller,
+        #[cfg(feature = "with-spindle")]
+        spindle: spindle_controller,
 
     });
     #[cfg(feature = "with-motion")]
     {
         {
             let mut md = motion_planer.motion_driver.lock().await;
-            md.pins.x_enable_pin.set_high();
-            md.pins.y_enable_pin.set_high();
-            md.pins.z_enable_pin.set_high();
-            md.pins.e_enable_pin.set_high();
+            md.disable_steppers(crate::tgeo::CoordSel::XYZE);
             //embassy_time::Timer::after_millis(500).await;
 
             #[cfg(feature = "with-trinamic")]
@@ -286,6 +327,25 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
             motion_planer.set_default_travel_speed(400).await;
             motion_planer.set_flow_rate(100).await;
             motion_planer.set_speed_rate(100).await;
+            motion_planer.set_retract_config(hwa::controllers::RetractConfig {
+                length: math::Real::new(3, 0),
+                speed: Some(math::Real::new(40, 0)),
+                z_hop: math::Real::zero(),
+                recover_extra_length: math::Real::zero(),
+                recover_speed: Some(math::Real::new(40, 0)),
+                auto_retract: false,
+            }).await;
+            motion_planer.set_park_config(hwa::controllers::ParkConfig {
+                position: crate::tgeo::TVector::from_coords(Some(math::Real::zero()), Some(math::Real::zero()), None, None),
+                z_lift: math::Real::new(10, 0),
+                unload_length: math::Real::new(100, 0),
+                load_length: math::Real::new(100, 0),
+                purge_length: math::Real::new(30, 0),
+                change_speed: Some(math::Real::new(20, 0)),
+            }).await;
+            // Single extruder setup: T0 drives E0 and uses the hotend
+            let _ = motion_planer.set_tool(0, Some(hwa::controllers::ToolConfig::new(Some(0), Some(hwa::controllers::HOTEND_HEATER)))).await;
+            let _ = motion_planer.change_tool(0).await;
             /*
             {
                 let mut md = mp.motion_driver.lock().await;
@@ -326,9 +386,9 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
     spawner.spawn(control::control_task::control_task(
         processor.clone(),
         control::control_task::ControlTaskDevices {
-            #[cfg(feature = "with-usbserial")]
+            #[cfg(all(feature = "with-usbserial", not(feature = "with-grbl-protocol")))]
             usb_serial_rx: devices.usbserial_rx_stream,
-            #[cfg(feature = "with-uart-port-1")]
+            #[cfg(all(feature = "with-uart-port-1", not(feature = "with-grbl-protocol")))]
             uart_port1_rx_stream: devices.uart_port1_rx_stream,
         },
         ControlTaskControllers {
@@ -339,23 +399,47 @@ async fn spawn_tasks(spawner: Spawner, event_bus: EventBusRef,
         }
     )).map_err(|_| ())?;
 
+    #[cfg(feature = "with-grbl-protocol")]
+    spawner.spawn(control::grbl::grbl_input_task(
+        processor.clone(),
+        #[cfg(feature = "with-usbserial")]
+        devices.usbserial_rx_stream,
+        #[cfg(all(not(feature = "with-usbserial"), feature = "with-uart-port-1"))]
+        devices.uart_port1_rx_stream,
+    )).map_err(|_| ())?;
+
     #[cfg(feature = "with-printjob")]
     spawner.spawn(control::printer_task::printer_task(
         processor.clone(),
-        printer_controller,
+        printer_controller.clone(),
         sdcard_controller,
     )).map_err(|_| ())?;
 
-    #[cfg(any(feature = "with-hotend", feature = "with-hotbed"))]
+    #[cfg(feature = "with-hotend")]
     spawner.spawn(control::temperature_task::temp_task(
         event_bus.clone(),
         hotend_controller,
     )).map_err(|_| ())?;
 
+    #[cfg(feature = "with-hotbed")]
+    spawner.spawn(control::temperature_task::hotbed_temp_task(
+        event_bus.clone(),
+        hotbed_controller,
+    )).map_err(|_| ())?;
+
     #[cfg(feature = "with-display")]
     spawner.spawn(display::display_task::display_task(
         devices.display_device,
-        event_bus.clone()
+        event_bus.clone(),
+        #[cfg(feature = "with-motion")]
+        processor.motion_planner.clone(),
+        #[cfg(feature = "with-printjob")]
+        printer_controller,
+    )).map_err(|_| ())?;
+
+    #[cfg(feature = "with-motion")]
+    spawner.spawn(control::report_task::report_task(
+        processor.clone(),
     )).map_err(|_| ())?;
 
     #[cfg(feature = "with-motion")]
@@ -0,0 +1,128 @@
+//! Feed hold within a segment: the profile time is scaled by a factor that ramps from 1 down to 0 (hold) and
+//! back up to 1 (cycle start). The path is untouched, so the move resumes from the exact point it stopped at
+use crate::math::{ONE, Real, SIX, THREE, TWO, ZERO};
+#[cfg(test)]
+use crate::math::HALF;
+
+#[derive(Clone, Copy)]
+pub struct FeedHoldRamp {
+    from: Real,
+    to: Real,
+    /// Elapsed ramp time, in seconds
+    elapsed: Real,
+    /// Ramp duration, in seconds
+    duration: Real,
+}
+
+impl FeedHoldRamp {
+    /// No ramp: full speed
+    pub const fn steady() -> Self {
+        Self {
+            from: ONE,
+            to: ONE,
+            elapsed: ZERO,
+            duration: ZERO,
+        }
+    }
+
+    /***
+    A ramp of the factor from its current value to `to`, at velocity `v` (what the factor is applied to).
+    The smoothstep `3u^2 - 2u^3` peaks at 1.5 dv/T of acceleration and 6 dv/T^2 of jerk, so the duration
+    is the shortest keeping both within the limits
+     */
+    pub fn new(from: Real, to: Real, v: Real, a_max: Real, j_max: Real) -> Self {
+        let dv = (v * (to - from)).abs();
+        let t_accel = match a_max.is_zero() {
+            true => ZERO,
+            false => THREE * dv / (TWO * a_max),
+        };
+        let t_jerk = match j_max.is_zero() {
+            true => ZERO,
+            false => (SIX * dv / j_max).sqrt().unwrap_or(ZERO),
+        };
+        Self {
+            from,
+            to,
+            elapsed: ZERO,
+            duration: core::cmp::max(t_accel, t_jerk),
+        }
+    }
+
+    /// The factor now
+    pub fn factor(&self) -> Real {
+        if self.elapsed >= self.duration {
+            return self.to;
+        }
+        let u = self.elapsed / self.duration;
+        self.from + (self.to - self.from) * u * u * (THREE - TWO * u)
+    }
+
+    /// Advances the ramp by dt seconds and returns the factor to apply over that time
+    pub fn advance(&mut self, dt: Real) -> Real {
+        let f0 = self.factor();
+        self.elapsed += dt;
+        (f0 + self.factor()) / TWO
+    }
+
+    /// Ramping (or ramped) down to a stop
+    #[inline]
+    pub fn is_stopping(&self) -> bool {
+        self.to.is_zero()
+    }
+
+    /// Fully stopped
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopping() && self.elapsed >= self.duration
+    }
+}
+
+#[cfg(test)]
+fn close(a: Real, b: Real) -> bool {
+    (a - b).abs() < Real::from_lit(1, 3)
+}
+
+#[test]
+pub fn feed_hold_ramp_duration_test() {
+    let v = Real::from_lit(10, 0);
+    // Jerk bound: sqrt(6 * 10 / 1000)
+    let ramp = FeedHoldRamp::new(ONE, ZERO, v, Real::from_lit(100, 0), Real::from_lit(1000, 0));
+    assert!(close(ramp.duration, Real::from_lit(6, 2).sqrt().unwrap()));
+    // Acceleration bound: 3 * 10 / (2 * 10)
+    let ramp = FeedHoldRamp::new(ONE, ZERO, v, Real::from_lit(10, 0), Real::from_lit(1000, 0));
+    assert!(close(ramp.duration, Real::from_lit(15, 1)));
+    // Half the swing takes less
+    let ramp = FeedHoldRamp::new(HALF, ZERO, v, Real::from_lit(10, 0), Real::from_lit(1000, 0));
+    assert!(close(ramp.duration, Real::from_lit(75, 2)));
+    // No limits: immediate
+    let ramp = FeedHoldRamp::new(ONE, ZERO, v, ZERO, ZERO);
+    assert!(ramp.is_stopped());
+    assert!(close(ramp.factor(), ZERO));
+}
+
+#[test]
+pub fn feed_hold_ramp_factor_test() {
+    let steady = FeedHoldRamp::steady();
+    assert!(!steady.is_stopping());
+    assert!(close(steady.factor(), ONE));
+
+    // 1.5 seconds down to a stop
+    let mut ramp = FeedHoldRamp::new(ONE, ZERO, Real::from_lit(10, 0), Real::from_lit(10, 0), Real::from_lit(1000, 0));
+    assert!(ramp.is_stopping() && !ramp.is_stopped());
+    assert!(close(ramp.factor(), ONE));
+    // Average over the step: (1 + 0.5) / 2
+    assert!(close(ramp.advance(Real::from_lit(75, 2)), Real::from_lit(75, 2)));
+    // Symmetric at half the ramp
+    assert!(close(ramp.factor(), HALF));
+    ramp.advance(Real::from_lit(75, 2));
+    assert!(ramp.is_stopped());
+    assert!(close(ramp.factor(), ZERO));
+    // Stays stopped
+    assert!(close(ramp.advance(ONE), ZERO));
+
+    // And back up
+    let mut ramp = FeedHoldRamp::new(ZERO, ONE, Real::from_lit(10, 0), Real::from_lit(10, 0), Real::from_lit(1000, 0));
+    assert!(!ramp.is_stopping());
+    ramp.advance(Real::from_lit(2, 0));
+    assert!(close(ramp.factor(), ONE));
+}
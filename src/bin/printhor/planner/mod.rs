@@ -1,5 +1,7 @@
 mod plan;
 mod interpolators;
+mod feed_hold;
 
 pub use plan::*;
-pub use interpolators::*;
\ No newline at end of file
+pub use interpolators::*;
+pub use feed_hold::*;
@@ -79,15 +79,98 @@ pub struct SCurveMotionProfile {
 
 #[allow(unused)]
 impl SCurveMotionProfile {
+    /// Computes the S-curve profile. When it is not feasible with the given constraints
+    /// (typically, very short moves), it falls back to a trapezoidal (infinite jerk) profile
+    /// with a reduced peak velocity, so every geometrically valid move can be executed
     pub fn compute(q_1: Real, v_0: Real, v_1:Real, constraints: &Constraints) -> Result<SCurveMotionProfile, CodeExecutionFailure>  {
+        let (v_0, v_1) = Self::clamp_boundaries(q_1, v_0, v_1, constraints.a_max)?;
+        match Self::compute_scurve(q_1, v_0, v_1, constraints) {
+            Ok(profile) if profile.is_sane() => Ok(profile),
+            _ => {
+                hwa::debug!("S-curve not feasible. Falling back to trapezoidal");
+                Self::compute_trapezoidal(q_1, v_0, v_1, constraints)
+            }
+        }
+    }
+
+    /// Lowers the faster boundary speed so the other one can be reached within the move at a_max:
+    /// |v_1²-v_0²| <= 2·a_max·q_1. Otherwise no profile meets both without exceeding a_max
+    fn clamp_boundaries(q_1: Real, v_0: Real, v_1: Real, a_max: Real) -> Result<(Real, Real), CodeExecutionFailure> {
+        let reach = TWO * a_max * q_1;
+        if v_1 > v_0 && (v_1 * v_1) - (v_0 * v_0) > reach {
+            Ok((v_0, ((v_0 * v_0) + reach).sqrt().ok_or(CodeExecutionFailure::NumericalError)?))
+        }
+        else if v_0 > v_1 && (v_0 * v_0) - (v_1 * v_1) > reach {
+            Ok((((v_1 * v_1) + reach).sqrt().ok_or(CodeExecutionFailure::NumericalError)?, v_1))
+        }
+        else {
+            Ok((v_0, v_1))
+        }
+    }
+
+    /// Trapezoidal velocity profile (no jerk limitation). If v_max cannot be reached, peak velocity is reduced.
+    pub fn compute_trapezoidal(q_1: Real, v_0: Real, v_1:Real, constraints: &Constraints) -> Result<SCurveMotionProfile, CodeExecutionFailure> {
+        let a_max = constraints.a_max;
+        let v_max = constraints.v_max;
+        if a_max <= ZERO || v_max <= ZERO || q_1 <= ZERO {
+            return Err(CodeExecutionFailure::NumericalError);
+        }
+        let (v_0, v_1) = Self::clamp_boundaries(q_1, v_0, v_1, a_max)?;
+        let accel_dist = (v_max * v_max - v_0 * v_0) / (TWO * a_max);
+        let decel_dist = (v_max * v_max - v_1 * v_1) / (TWO * a_max);
+
+        let (t_a, t_v, t_d, v_lim) = if accel_dist + decel_dist <= q_1 {
+            // Cruise phase is present
+            let t_a = (v_max - v_0) / a_max;
+            let t_d = (v_max - v_1) / a_max;
+            let t_v = (q_1 - accel_dist - decel_dist) / v_max;
+            (t_a, t_v, t_d, v_max)
+        }
+        else {
+            // Reduced peak velocity. Boundaries are clamped, so it is never below either of them (but for rounding)
+            let v_peak = ((TWO * a_max * q_1 + v_0 * v_0 + v_1 * v_1) / TWO).sqrt()
+                .ok_or(CodeExecutionFailure::NumericalError)?;
+            let t_a = if v_peak > v_0 { (v_peak - v_0) / a_max } else { ZERO };
+            let t_d = if v_peak > v_1 { (v_peak - v_1) / a_max } else { ZERO };
+            if (t_a + t_d).is_zero() {
+                return Err(CodeExecutionFailure::NumericalError);
+            }
+            (t_a, ZERO, t_d, v_peak)
+        };
+        Ok(SCurveMotionProfile {
+            t_j1: ZERO,
+            t_a,
+            t_v,
+            t_d,
+            t_j2: ZERO,
+            t: t_a + t_v + t_d,
+            v_0,
+            v_1,
+            j_max: constraints.j_max,
+            j_min: constraints.j_max.neg(),
+            a_lim_a: a_max,
+            a_lim_d: a_max,
+            v_lim,
+            q1: q_1,
+        })
+    }
+
+    /// Whether the phases are consistent (no negative durations and positive total time)
+    fn is_sane(&self) -> bool {
+        self.t_j1 >= ZERO && self.t_j2 >= ZERO && self.t_v >= ZERO
+            && self.t_a >= (self.t_j1 * TWO) && self.t_d >= (self.t_j2 * TWO)
+            && self.t > ZERO
+    }
+
+    fn compute_scurve(q_1: Real, v_0: Real, v_1:Real, constraints: &Constraints) -> Result<SCurveMotionProfile, CodeExecutionFailure>  {
 
         let _t0 = embassy_time::Instant::now();
 
         let t_jmax = constraints.a_max / constraints.j_max;
         let t_jstar = Real::min(
-            Some((((v_1 - v_0).abs()) / constraints.j_max).sqrt().unwrap()),
+            Some((((v_1 - v_0).abs()) / constraints.j_max).sqrt().ok_or(CodeExecutionFailure::NumericalError)?),
             Some(t_jmax),
-        ).unwrap();
+        ).ok_or(CodeExecutionFailure::NumericalError)?;
 
         hwa::trace!("t_jstar = {} t_jmax = {}", t_jstar, t_jmax);
 
@@ -99,11 +182,11 @@ impl SCurveMotionProfile {
                 * (t_jstar + ((v_1 - v_0).abs() / constraints.a_max))
         }
         else {
-            hwa::error!("unhandled situation");
+            hwa::debug!("unhandled situation");
             return Err(CodeExecutionFailure::ERR)
         };
         if !feasible {
-            hwa::error!("Movement NOT FEASIBLE");
+            hwa::debug!("Movement NOT FEASIBLE");
             return Err(CodeExecutionFailure::ERR)
         }
 
@@ -165,6 +248,7 @@ impl SCurveMotionProfile {
                 let mut v_lim = ZERO;
 
             let mut a_max_2 = constraints.a_max;
+            let mut converged = false;
 
             for _i in 0..10 {
                 // TODO: lagrange multipliers
@@ -175,7 +259,7 @@ impl SCurveMotionProfile {
                 let sqrt_delta = (((a_max_2 * a_max_2 * a_max_2 * a_max_2) / (constraints.j_max * constraints.j_max))
                     + (TWO * ((v_0 * v_0) + (v_1 * v_1))
                     + (a_max_2 * ((FOUR * q_1)
-                    - (TWO * (a_max_2 / constraints.j_max) * (v_0 + v_1)))))).sqrt().unwrap();
+                    - (TWO * (a_max_2 / constraints.j_max) * (v_0 + v_1)))))).sqrt().ok_or(CodeExecutionFailure::NumericalError)?;
                 let aj = (a_max_2 * a_max_2) / constraints.j_max;
                 t_a_2 = (aj - (TWO * v_0) + sqrt_delta) / (TWO * a_max_2);
                 t_d_2 = (aj - (TWO * v_1) + sqrt_delta) / (TWO * a_max_2);
@@ -184,13 +268,21 @@ impl SCurveMotionProfile {
                 v_lim = v_0 + (t_a_2 - t_j) * a_lim_a;
 
                 if t_a_2 > (TWO * t_j) && t_d_2 > (TWO * t_j) {
+                    converged = true;
                     break;
                 }
                 gamma = gamma * HALF;
                 a_max_2 = constraints.a_max * gamma;
             }
+            if !converged {
+                hwa::debug!("Reduced acceleration did not converge");
+                return Err(CodeExecutionFailure::ERR)
+            }
             t_j1_2 = t_j;
             t_j2_2 = t_j;
+            if (t_a_2 < Real::zero() || t_d_2 < Real::zero()) && (v_1 + v_0).is_zero() {
+                return Err(CodeExecutionFailure::NumericalError)
+            }
             if t_a_2 < Real::zero() {
                 t_a_2 = Real::zero();
                 t_j1_2 = Real::zero();
@@ -198,7 +290,7 @@ impl SCurveMotionProfile {
                 t_j2_2 = (constraints.j_max.mul(q_1) - (constraints.j_max.mul(
                     (constraints.j_max.mul(q_1.powi(2))) + ((v_1 + v_0).powi(2)).mul(v_1 - v_0)
                 )
-                ).sqrt().unwrap()).div(
+                ).sqrt().ok_or(CodeExecutionFailure::NumericalError)?).div(
                     constraints.j_max.mul(v_1 + v_0)
                 )
             }
@@ -209,7 +301,7 @@ impl SCurveMotionProfile {
                 t_j1_2 = (constraints.j_max.mul(q_1) - (constraints.j_max.mul(
                     (constraints.j_max.mul(q_1.powi(2))) + ((v_1 + v_0).powi(2)).mul(v_0 - v_1)
                 )
-                ).sqrt().unwrap()).div(
+                ).sqrt().ok_or(CodeExecutionFailure::NumericalError)?).div(
                     constraints.j_max.mul(v_1 + v_0)
                 )
             }
@@ -315,6 +407,30 @@ impl SCurveMotionProfile {
             self.q1
         }
     }
+
+    /// Computes the velocity in given timestamp. Derivative of [SCurveMotionProfile::eval_position]
+    pub fn eval_velocity(&self, t_i: Real) -> Real {
+        let v0 =  self.v_0;
+        let v1 = self.v_1;
+
+        if t_i <= self.t1() {
+            v0 + (self.j_max * t_i.powi(2) / TWO)
+        } else if t_i <= self.t2() {
+            v0 + (self.a_lim_a * (t_i - (self.t_j1 / TWO)))
+        } else if t_i <= self.t3() {
+            self.v_lim + self.j_min * (self.t_a - t_i).powi(2) / TWO
+        } else if t_i <= self.t4() {
+            self.v_lim
+        } else if t_i <= self.t5() {
+            self.v_lim - self.j_max * (t_i - self.t + self.t_d).powi(2) / TWO
+        } else if t_i <= self.t6() {
+            self.v_lim - self.a_lim_d * ((t_i - self.t + self.t_d) - (self.t_j2 / TWO))
+        } else if t_i <= self.t7() {
+            v1 + self.j_max * (self.t - t_i).powi(2) / TWO
+        } else {
+            v1
+        }
+    }
 }
 
 #[cfg(feature = "native")]
@@ -667,3 +783,64 @@ pub fn plan_test() -> Result<(),()>{
 
 
 
+
+#[test]
+pub fn trapezoidal_fallback_test() {
+    let constraints = Constraints {
+        v_max: Real::new(400, 0),
+        a_max: Real::new(800, 0),
+        j_max: Real::new(1600, 0),
+    };
+    // Short move: v_max is never reached, so peak velocity is reduced
+    let profile = SCurveMotionProfile::compute_trapezoidal(ONE, ZERO, ZERO, &constraints).unwrap();
+    assert!(profile.v_lim < constraints.v_max);
+    assert!((profile.eval_position(profile.t_a) - HALF).abs() < Real::new(1, 3));
+    assert!((profile.eval_position(profile.t) - ONE).abs() < Real::new(1, 3));
+
+    // Not feasible as S-curve (entry speed too high for the distance): must not fail
+    let profile = SCurveMotionProfile::compute(Real::new(1, 1), Real::new(10, 0), ZERO, &constraints).unwrap();
+    assert!(profile.t > ZERO);
+}
+
+#[test]
+pub fn short_move_end_test() {
+    let constraints = Constraints {
+        v_max: Real::new(400, 0),
+        a_max: Real::new(800, 0),
+        j_max: Real::new(1600, 0),
+    };
+    // Under 1 unit: the truncated step count is 0, but the move must still reach its target at the profile end
+    let q1 = Real::new(7, 1);
+    let profile = SCurveMotionProfile::compute(q1, ZERO, ZERO, &constraints).unwrap();
+    assert_eq!(q1.to_i32(), Some(0));
+    assert!(profile.t > ZERO);
+    assert!(profile.eval_position(profile.t / TWO) < q1);
+    assert!((profile.eval_position(profile.t) - q1).abs() < Real::new(1, 3));
+}
+
+#[test]
+pub fn boundary_speed_clamp_test() {
+    let constraints = Constraints {
+        v_max: Real::new(400, 0),
+        a_max: Real::new(800, 0),
+        j_max: Real::new(1600, 0),
+    };
+    let q1 = Real::new(1, 1);
+    let v = Real::new(100, 0);
+    let tolerance = Real::new(1, 3);
+    // 100 mm/s cannot be shed (or gained) in 0.1 mm at 800 mm/s²: the faster boundary is lowered to sqrt(2·800·0.1)
+    let reachable = Real::new(160, 0).sqrt().unwrap();
+    for (v_0, v_1) in [(v, ZERO), (ZERO, v)] {
+        let profile = SCurveMotionProfile::compute_trapezoidal(q1, v_0, v_1, &constraints).unwrap();
+        assert!(profile.a_lim_a <= constraints.a_max);
+        assert!(profile.a_lim_d <= constraints.a_max);
+        assert!((Real::max(Some(profile.v_0), Some(profile.v_1)).unwrap() - reachable).abs() < tolerance);
+        assert!(((profile.v_0 - profile.v_1).abs() / profile.t - constraints.a_max).abs() < tolerance);
+        assert!((profile.eval_position(profile.t) - q1).abs() < tolerance);
+
+        let profile = SCurveMotionProfile::compute(q1, v_0, v_1, &constraints).unwrap();
+        assert!(profile.a_lim_a <= constraints.a_max);
+        assert!(profile.a_lim_d <= constraints.a_max);
+        assert!(((profile.v_1 * profile.v_1) - (profile.v_0 * profile.v_0)).abs() <= (TWO * constraints.a_max * q1) + tolerance);
+    }
+}
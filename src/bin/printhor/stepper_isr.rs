@@ -2,14 +2,21 @@
 #[cfg(not(feature = "native"))]
 use core::ops::Neg;
 use embassy_time;
-use embassy_time::{block_for, Duration, with_timeout};
+use embassy_time::{Duration, with_timeout};
+#[cfg(not(feature = "native-simulation"))]
+use embassy_time::block_for;
 #[cfg(feature = "with-motion")]
-use crate::{hwa, hwa::controllers::{DeferEvent, DeferType}};
+use crate::{hwa, hwa::controllers::{DeferEvent, DeferType, ExecPlan}};
+#[cfg(feature = "with-laser")]
+use crate::hwa::controllers::LaserPower;
+#[cfg(feature = "with-laser")]
+use alloc::vec::Vec;
 #[allow(unused)]
 use crate::math::{Real, ONE_MILLION, ONE_THOUSAND};
-use crate::tgeo::TVector;
+#[cfg(feature = "with-motion")]
+use crate::planner::FeedHoldRamp;
+use crate::tgeo::{CoordSel, TVector};
 use core::cmp::min;
-use rust_decimal_macros::dec;
 #[allow(unused)]
 use printhor_hwa_common::{EventStatus, EventFlags};
 
@@ -55,6 +62,16 @@ where CN: Copy
 
 */
 
+/// Sets the laser power (fraction of the max), only when it changes
+#[cfg(feature = "with-laser")]
+async fn apply_laser_power(motion_planner: &hwa::controllers::MotionPlannerRef, applied: &mut Real, power: Real) {
+    if *applied != power {
+        *applied = power;
+        let laser = motion_planner.motion_driver.lock().await.laser_controller.clone();
+        laser.lock().await.set_power(power.to_f64() as f32).await;
+    }
+}
+
 /***
 This task feeds watchdog to ensure no reset happen due high CPU starvation when feed rate is very high
  */
@@ -65,9 +82,7 @@ pub async fn stepper_task(
 {
 
     let one_ns = Duration::from_micros(1);
-    let timeout = Duration::from_secs(10);
     let period_ms: i32 = (PULSE_WIDTH_US / 1000) as i32;
-    let mut steppers_off = true;
 
     let mut _nticks = 0u64;
     let mut acc: embassy_time::Duration = embassy_time::Duration::from_micros(0);
@@ -85,6 +100,9 @@ pub async fn stepper_task(
 
     hwa::info!("Pulse controller starting with {} us period", PULSE_WIDTH_US);
 
+    #[cfg(feature = "with-laser")]
+    let mut laser_applied = Real::zero();
+
     loop {
         acc += t0.elapsed();
         t0 = embassy_time::Instant::now();
@@ -93,14 +111,31 @@ pub async fn stepper_task(
 
         //s.wait_until(EventStatus::containing(EventFlags::SYS_READY | EventFlags::ATX_ON).and_not_containing(EventFlags::SYS_ALARM)).await;
 
-        match with_timeout(timeout, motion_planner.get_current_segment_data()).await {
+        // Idle timeout (M18 S) may change between plans
+        let next_plan = match motion_planner.get_stepper_idle_timeout().await {
+            0 => Ok(motion_planner.get_current_segment_data().await),
+            secs => with_timeout(Duration::from_secs(secs as u64), motion_planner.get_current_segment_data()).await,
+        };
+        match next_plan {
             // Process segment plan
-            Ok(Some(segment)) => {
+            Ok(ExecPlan::Segment(segment)) => {
                 hwa::trace!("Go move segment");
 
+                // Feed hold between segments: wait here for the cycle start
+                motion_planner.wait_cycle_start().await;
+                // Speed factor the realtime speed was last reported with
+                #[cfg(feature = "with-grbl-protocol")]
+                let mut reported_factor: Option<Real> = None;
+                // G7: the pixels of the scanline, freeing its slot for the next one
+                #[cfg(feature = "with-laser")]
+                let raster_pixels = match segment.segment_data.laser {
+                    LaserPower::Raster(scan) => motion_planner.take_raster_line(scan.slot).await.unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+
                 let mut tick_id = 1;
 
-                let to_ustep = Real::from_fixed(dec!(16.0)) * Real::from_fixed(dec!(8.0));
+                let to_ustep = motion_planner.get_usteps_per_unit().await;
 
                 ////////////////////////////////////////
                 let mut absolute_ticker = embassy_time::Ticker::every(Duration::from_hz(PERIOD_HZ));
@@ -123,6 +158,11 @@ pub async fn stepper_task(
 
                 let t_segment = embassy_time::Instant::now();
 
+                // Profile time. Advances scaled by the live speed rate (M220) and the feed hold ramp
+                let mut time = Real::zero();
+                let mut t_last = t_ref;
+                let mut hold = FeedHoldRamp::steady();
+
                 loop { // Iterate on segment
 
                     let t_tick = embassy_time::Instant::now();
@@ -130,13 +170,89 @@ pub async fn stepper_task(
                     // Feed watchdog because this high prio task could cause CPU starvation
                     watchdog.lock().await.pet();
 
-                    let time = Real::from_lit(t_ref.elapsed().as_millis() as i64, 3);
+                    if motion_planner.is_quick_stop_requested() {
+                        // M410: Abort the segment here and report the position reached by the steps already executed
+                        let mut stopped_pos = segment.segment_data.src_pos;
+                        stopped_pos.assign_if_set(CoordSel::XYZ, &(segment.segment_data.src_pos + (axis_steps_advanced_precise / to_ustep)));
+                        hwa::info!("Quick stop at {}", stopped_pos.rdp(4));
+                        #[cfg(feature = "with-laser")]
+                        apply_laser_power(&motion_planner, &mut laser_applied, Real::zero()).await;
+                        motion_planner.quick_stop_completed(&stopped_pos).await;
+                        break;
+                    }
+
+                    if let Some(probe) = segment.segment_data.probe {
+                        if motion_planner.motion_driver.lock().await.probe_triggered() == probe.toward {
+                            // G38.x: Stop here and report the position reached by the steps already executed
+                            let mut stopped_pos = segment.segment_data.src_pos;
+                            stopped_pos.assign_if_set(CoordSel::XYZ, &(segment.segment_data.src_pos + (axis_steps_advanced_precise / to_ustep)));
+                            hwa::info!("Probe changed at {}", stopped_pos.rdp(4));
+                            motion_planner.probe_completed(&stopped_pos).await;
+                            break;
+                        }
+                    }
+
+                    // M220, within the machine limits
+                    let speed_factor = min(motion_planner.get_speed_factor().await, segment.segment_data.speed_factor_max);
+                    #[cfg(feature = "with-grbl-protocol")]
+                    if reported_factor != Some(speed_factor) {
+                        reported_factor = Some(speed_factor);
+                        motion_planner.set_realtime_speed(segment.motion_profile.v_lim * speed_factor).await;
+                    }
+                    let flow_factor = motion_planner.get_flow_factor().await;
+
+                    // Feed hold: ramp down to a stop, wait for the cycle start and ramp up again
+                    match (motion_planner.is_feed_hold_requested(), hold.is_stopping()) {
+                        (true, false) | (false, true) => {
+                            let v = segment.motion_profile.eval_velocity(time) * speed_factor;
+                            let to = if hold.is_stopping() { Real::one() } else { Real::zero() };
+                            hold = FeedHoldRamp::new(hold.factor(), to, v, segment.segment_data.a_max, segment.segment_data.j_max);
+                        }
+                        (true, true) if hold.is_stopped() => {
+                            hwa::info!("Feed hold");
+                            #[cfg(feature = "with-laser")]
+                            apply_laser_power(&motion_planner, &mut laser_applied, Real::zero()).await;
+                            motion_planner.wait_cycle_start().await;
+                            hwa::info!("Cycle start");
+                            let v = segment.motion_profile.eval_velocity(time) * speed_factor;
+                            hold = FeedHoldRamp::new(Real::zero(), Real::one(), v, segment.segment_data.a_max, segment.segment_data.j_max);
+                            // The time waiting does not count
+                            t_last = embassy_time::Instant::now();
+                            absolute_ticker = embassy_time::Ticker::every(Duration::from_hz(PERIOD_HZ));
+                            continue;
+                        }
+                        _ => {}
+                    }
+
+                    #[cfg(feature = "with-laser")]
+                    {
+                        let power = match s.get_status().await.contains(EventFlags::SYS_ALARM) {
+                            true => Real::zero(),
+                            false => match segment.segment_data.laser {
+                                LaserPower::Off => Real::zero(),
+                                LaserPower::Constant(power) => power,
+                                LaserPower::Dynamic(power) => match segment.motion_profile.v_lim.is_zero() {
+                                    true => Real::zero(),
+                                    false => power * (hold.factor() * segment.motion_profile.eval_velocity(time) / segment.motion_profile.v_lim)
+                                        .clamp(Real::zero(), Real::one()),
+                                }
+                                // Scaled down while a feed hold ramps, as the pixel is crossed slower
+                                LaserPower::Raster(scan) => hold.factor() * scan.power_at(&raster_pixels, segment.motion_profile.eval_position(time)),
+                            }
+                        };
+                        apply_laser_power(&motion_planner, &mut laser_applied, power).await;
+                    }
+
+                    let dt = Real::from_lit((t_tick - t_last).as_micros() as i64, 6);
+                    time += dt * speed_factor * hold.advance(dt);
+                    t_last = t_tick;
 
                     hwa::debug!("tick_id {} t = {} ms", tick_id, t_ref.elapsed().as_millis());
 
-                    // Interpolate as microsegments
+                    // Interpolate as microsegments. Flow rate (M221) applies to E only
                     let current_position_precise = segment.motion_profile.eval_position(time);
-                    let axial_pos = segment.segment_data.vdir * current_position_precise;
+                    let axial_pos = (segment.segment_data.vdir * current_position_precise)
+                        .map_coord(CoordSel::E, |e, _| Some(e * flow_factor));
                     let step_pos = (axial_pos * to_ustep).rdp(0);
 
                     let steps_to_advance_precise: TVector<Real> = step_pos - axis_steps_advanced_precise;
@@ -155,47 +271,76 @@ pub async fn stepper_task(
                     axis_delta = rdp_adv;
                     // legacy end
 
-                    if let Some(max_steps_adv) = steps_to_advance_precise.max()
-                        .and_then(|v| v.to_i32())
+                    // Signed microsteps of this tick by axis
+                    let usteps_to_advance: TVector<i32> = steps_to_advance_precise.map_coords(|c| c.to_i32());
+                    let mut moving_axes = CoordSel::empty();
+                    let mut forward_axes = CoordSel::empty();
+                    for (axis, usteps) in [
+                        (CoordSel::X, usteps_to_advance.x), (CoordSel::Y, usteps_to_advance.y),
+                        (CoordSel::Z, usteps_to_advance.z), (CoordSel::E, usteps_to_advance.e)] {
+                        match usteps.unwrap_or(0) {
+                            n if n > 0 => { moving_axes |= axis; forward_axes |= axis; }
+                            n if n < 0 => { moving_axes |= axis; }
+                            _ => {}
+                        }
+                    }
+                    let abs_usteps = usteps_to_advance.map_coords(|c| Some(c.abs()));
+
+                    if let Some(max_steps_adv) = abs_usteps.max()
                         .and_then(|v| if v > 0 { Some(v) } else { None })
                     {
+                        motion_planner.enable_steppers(moving_axes).await;
                         let mut drv = motion_planner.motion_driver.lock().await;
-                        steppers_off = false;
-
-                        drv.pins.x_enable_pin.set_low();
-                        drv.pins.x_dir_pin.set_high();
-
-                        //let tv = steps_to_advance_precise.with_coord(CoordSel::all(), Some(Real::from_lit((PULSE_WIDTH_US) as i64, 3)));
-                        //hwa::info!("  -- tv = {}", tv);
-                        let _pulses_by_us = (steps_to_advance_precise
-                            .map_val(Real::from_lit((PULSE_WIDTH_US) as i64, 0)) / (steps_to_advance_precise + TVector::one())
-                        ).rdp(0);
-                        hwa::debug!("  -- max pulses {} =>  {} us/pulse", max_steps_adv, _pulses_by_us);
-                        // FIXME:
-                        let mut ustep_pulse_ticker = embassy_time::Ticker::every(Duration::from_micros(_pulses_by_us.x.unwrap_or(Real::zero()).to_i32().unwrap() as u64));
+                        drv.select_e_drive(segment.segment_data.e_drive);
+                        drv.set_forward_direction(forward_axes, moving_axes);
+
+                        let pulse_period_us = (PULSE_WIDTH_US / (max_steps_adv as u32 + 1)).max(1);
+                        hwa::debug!("  -- max pulses {} =>  {} us/pulse", max_steps_adv, pulse_period_us);
+                        let mut ustep_pulse_ticker = embassy_time::Ticker::every(Duration::from_micros(pulse_period_us as u64));
                         let tx = embassy_time::Instant::now();
                         for _i in 0 .. max_steps_adv {
+                            // Bresenham-like spread: the axis with most steps pulses every tick, others evenly in between
+                            let mut pulse_axes = CoordSel::empty();
+                            for (axis, n) in [
+                                (CoordSel::X, abs_usteps.x), (CoordSel::Y, abs_usteps.y),
+                                (CoordSel::Z, abs_usteps.z), (CoordSel::E, abs_usteps.e)] {
+                                let n = n.unwrap_or(0);
+                                if ((_i + 1) * n) / max_steps_adv > (_i * n) / max_steps_adv {
+                                    pulse_axes |= axis;
+                                }
+                            }
                             ustep_pulse_ticker.next().await;
                             hwa::debug!("\tuStep {} at t+{} ms", _i + 1, tx.elapsed().as_millis());
-                            drv.pins.x_step_pin.set_high();
+                            drv.step_high(pulse_axes);
+                            // The virtual clock does not advance while a task runs, so the pulse width is waited through an alarm
+                            #[cfg(feature = "native-simulation")]
+                            embassy_time::Timer::after(one_ns).await;
+                            #[cfg(not(feature = "native-simulation"))]
                             block_for(one_ns);
-                            drv.pins.x_step_pin.set_low();
-                            //block_for(one_ns);
+                            drv.step_low(pulse_axes);
                         }
+                        drop(drv);
+                        motion_planner.add_step_count(&usteps_to_advance).await;
                         hwa::debug!("\tTask took {} ms", tx.elapsed().as_millis())
-
-                        //let _single_pulse_us = PULSE_WIDTH_US / (max_steps_adv + 1) as u32;
-
                     }
 
                     _tcurr += PULSE_WIDTH_US;
                     advanced_steps += steps_to_advance;
 
-                    if advanced_steps >= segment.segment_data.total_steps {
+                    // Ends on profile time, where the position reaches the target. Steps truncate, so a move under 1 unit would never get there
+                    if time >= segment.motion_profile.t {
                         hwa::info!("Now at {} {} | {} ", t_segment.elapsed().as_millis(), advanced_steps, axis_advanced);
                         hwa::info!("    ++ axial {} | steps {} ", axial_pos.rdp(4), steps_to_advance_precise);
+                        if segment.segment_data.probe.is_some() {
+                            motion_planner.probe_failed(&segment.segment_data.dest_pos).await;
+                        }
                         motion_planner.consume_current_segment_data().await;
                         motion_planner.defer_channel.send(DeferEvent::LinearMove(DeferType::Completed)).await;
+                        #[cfg(feature = "with-laser")]
+                        if motion_planner.event_bus.has_flags(EventFlags::MOV_QUEUE_EMPTY).await {
+                            // Motion stopped
+                            apply_laser_power(&motion_planner, &mut laser_applied, Real::zero()).await;
+                        }
                         _mov_id += 1;
                         break;
                     }
@@ -208,9 +353,26 @@ pub async fn stepper_task(
 
                 }
             }
+            // Dwell
+            Ok(ExecPlan::Dwell(ms)) => {
+                hwa::debug!("Dwell {} ms", ms);
+                embassy_time::Timer::after(Duration::from_millis(ms as u64)).await;
+                motion_planner.consume_current_segment_data().await;
+            }
+            // Pen lift or lower, waiting for the servo to settle
+            #[cfg(feature = "with-pen-plotter")]
+            Ok(ExecPlan::Pen(down)) => {
+                let (angle, delay_ms) = motion_planner.get_pen_config().await.target(down);
+                hwa::debug!("Pen {} at {} deg", if down { "down" } else { "up" }, angle);
+                let servo = motion_planner.motion_driver.lock().await.probe_controller.clone();
+                servo.lock().await.set_angle(angle, delay_ms as u64 * 1000).await;
+                motion_planner.consume_current_segment_data().await;
+            }
             // Homing
-            Ok(None) => {
+            Ok(ExecPlan::Homing) => {
                 hwa::info!("Doing homing");
+                #[cfg(feature = "with-laser")]
+                apply_laser_power(&motion_planner, &mut laser_applied, Real::zero()).await;
                 if !motion_planner.do_homing().await.is_ok() {
                     // TODO
                 }
@@ -219,15 +381,7 @@ pub async fn stepper_task(
             }
             // Timeout
             Err(_) => {
-                if !steppers_off {
-                    hwa::info!("Timeout. Powering steppers off");
-                    let mut drv = motion_planner.motion_driver.lock().await;
-                    drv.pins.x_enable_pin.set_high();
-                    drv.pins.y_enable_pin.set_high();
-                    drv.pins.z_enable_pin.set_high();
-                    drv.pins.e_enable_pin.set_high();
-                    steppers_off = true;
-                }
+                motion_planner.disable_idle_steppers().await;
             }
         }
     }
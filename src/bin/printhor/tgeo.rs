@@ -9,7 +9,7 @@ use num_traits::float::FloatCore;
 use bitflags::bitflags;
 
 bitflags! {
-    #[derive(PartialEq, Eq)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
     pub struct CoordSel: u8 {
         const X = 0b00000001;
         const Y = 0b00000010;
@@ -0,0 +1,112 @@
+//! Simulation runs on the virtual clock must be reproducible.
+//!
+//! Runs the same G-code file twice through the firmware (control, planner and stepper tasks) and compares the pin traces
+//! ```shell
+//! cargo test --no-default-features --features native-simulation --test simulation
+//! ```
+#![cfg(feature = "native-simulation")]
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const GCODE: &str = "G28
+G1 X10 Y5 F3000
+G1 X20 Y0 E1 F1800
+G1 X12.5 Y7.25 Z0.4
+G4 P100
+G1 X0 Y0 Z0
+M84
+M79
+";
+
+const RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Empty FAT16 volume in the first partition, as the native board mounts `data/sdcard.img` at boot
+fn write_sdcard_image(path: &Path) {
+    const START: u32 = 1;
+    const SECTORS: u32 = 65536;
+    const RESERVED: u16 = 4;
+    const FAT_SECTORS: u16 = 64;
+    let mut image = File::create(path).unwrap();
+    image.set_len(((START + SECTORS) * 512) as u64).unwrap();
+
+    let mut mbr = [0u8; 512];
+    mbr[446 + 4] = 0x06;
+    mbr[446 + 8..446 + 12].copy_from_slice(&START.to_le_bytes());
+    mbr[446 + 12..446 + 16].copy_from_slice(&SECTORS.to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+    image.write_all(&mbr).unwrap();
+
+    let mut boot = [0u8; 512];
+    boot[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"PRINTHOR");
+    boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+    boot[13] = 4;
+    boot[14..16].copy_from_slice(&RESERVED.to_le_bytes());
+    boot[16] = 2;
+    boot[17..19].copy_from_slice(&512u16.to_le_bytes());
+    boot[21] = 0xF8;
+    boot[22..24].copy_from_slice(&FAT_SECTORS.to_le_bytes());
+    boot[24..26].copy_from_slice(&32u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&64u16.to_le_bytes());
+    boot[28..32].copy_from_slice(&START.to_le_bytes());
+    boot[32..36].copy_from_slice(&SECTORS.to_le_bytes());
+    boot[36] = 0x80;
+    boot[38] = 0x29;
+    boot[43..54].copy_from_slice(b"PRINTHOR   ");
+    boot[54..62].copy_from_slice(b"FAT16   ");
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+    image.write_all(&boot).unwrap();
+
+    for fat in 0..2u64 {
+        let sector = (START + RESERVED as u32) as u64 + fat * FAT_SECTORS as u64;
+        image.seek(SeekFrom::Start(sector * 512)).unwrap();
+        image.write_all(&[0xF8, 0xFF, 0xFF, 0xFF]).unwrap();
+    }
+}
+
+/// Runs the firmware on [GCODE] in its own directory and returns the pin trace
+fn simulate(run: &str) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("printhor-simulation-{}-{}", std::process::id(), run));
+    fs::create_dir_all(dir.join("data")).unwrap();
+    write_sdcard_image(&dir.join("data").join("sdcard.img"));
+    fs::write(dir.join("job.gcode"), GCODE).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_printhor"))
+        .current_dir(&dir)
+        .env("PRINTHOR_GCODE_FILE", dir.join("job.gcode"))
+        .env("PRINTHOR_PIN_TRACE", dir.join("trace.csv"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let t0 = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if t0.elapsed() > RUN_TIMEOUT {
+            child.kill().unwrap();
+            panic!("Simulation {} did not complete", run);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert!(status.success(), "Simulation {} failed: {}", run, status);
+    let trace = fs::read_to_string(dir.join("trace.csv")).unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    trace
+}
+
+#[test]
+fn simulation_is_reproducible() {
+    let first = simulate("a");
+    let second = simulate("b");
+    // Steps were actually emitted
+    assert!(first.lines().any(|l| l.contains("x_step,1")));
+    assert!(first.lines().any(|l| l.contains("z_step,1")));
+    assert_eq!(first, second);
+}